@@ -11,6 +11,128 @@ pub enum EscrowError {
   ExpectedAmountMismatch,
   #[error("Amount overflow")]
   AmountOverflow,
+  #[error("Escrow's key must match seed of ['escrow', program id, initializer, mint a, mint b, seed] provided")]
+  InvalidEscrowKey,
+  #[error("Token account mint does not match the offered mint of the escrow")]
+  OfferedMintMismatch,
+  #[error("Token account mint does not match the requested mint of the escrow")]
+  RequestedMintMismatch,
+  #[error("Fill amount must be greater than zero and at most the vault balance")]
+  InvalidFillAmount,
+  #[error("Escrow has expired")]
+  EscrowExpired,
+  #[error("Escrow has not expired yet")]
+  EscrowNotExpired,
+  #[error("Escrow is reserved for a different taker")]
+  TakerNotAllowed,
+  #[error("Side kind is not supported on this side of the escrow")]
+  UnsupportedSideKind,
+  #[error("Config's key must match seed of ['escrow', program id, 'config'] provided")]
+  InvalidConfigKey,
+  #[error("Data type mismatch")]
+  DataTypeMismatch,
+  #[error("Admin of the config must sign this transaction")]
+  AdminMismatch,
+  #[error("Basis points cannot be more than 10000")]
+  InvalidBasisPoints,
+  #[error("Too many fee recipients")]
+  TooManyFeeRecipients,
+  #[error("Fee recipient does not match the one configured for the mint")]
+  InvalidFeeRecipient,
+  #[error("Escrow program is paused")]
+  ProgramPaused,
+  #[error("Metadata's key must match seed of ['metadata', token metadata program id, offered mint] provided")]
+  InvalidMetadataKey,
+  #[error("Creator account does not belong to the creator or the requested mint")]
+  InvalidCreatorAccount,
+  #[error("Basket must have between 1 and 8 legs on each side")]
+  InvalidBasketLegs,
+  #[error("Nonce's key must match seed of ['escrow', program id, maker, 'nonce'] provided")]
+  InvalidNonceKey,
+  #[error("Order nonce is lower than the maker's current nonce")]
+  OrderNonceUsed,
+  #[error("Order is not signed by its maker through a preceding ed25519 instruction")]
+  InvalidOrderSignature,
+  #[error("Update would withdraw more than the escrow holds or leave it empty")]
+  InvalidUpdateAmount,
+  #[error("Required signature is missing")]
+  MissingSigner,
+  #[error("Account is not owned by the expected program")]
+  IncorrectOwner,
+  #[error("Escrow account is not initialized")]
+  UninitializedEscrow,
+  #[error("Vault authority must match seed of ['escrow', program id, escrow account] provided")]
+  InvalidVaultAuthority,
+  #[error("Token program is not the SPL token program")]
+  InvalidTokenProgram,
+  #[error("Signer is neither the buyer nor the seller of the escrow")]
+  NotEscrowParty,
+  #[error("Only the arbiter of the escrow can resolve its dispute")]
+  ArbiterMismatch,
+  #[error("Escrow is not in the status this instruction requires")]
+  InvalidEscrowStatus,
+  #[error("Vesting schedule is invalid or does not add up to the vault amount")]
+  InvalidVestingSchedule,
+  #[error("Nothing has vested since the last claim")]
+  NothingToClaim,
+  #[error("Vesting escrow is not revocable by this key")]
+  RevocationAuthorityMismatch,
+  #[error("Secret does not hash to the escrow's hashlock")]
+  InvalidSecret,
+  #[error("Bid must reach the reserve price and beat the top bid")]
+  BidTooLow,
+  #[error("Auction has not ended yet")]
+  AuctionNotEnded,
+  #[error("Auction has ended")]
+  AuctionEnded,
+  #[error(
+    "Dutch pricing must decay from its start price to its end price over a non-empty period"
+  )]
+  InvalidDutchPricing,
+  #[error("Payment is above the taker's maximum")]
+  PaymentAboveMaximum,
+  #[error("Order book page does not match the escrow's mint pair or does not list the escrow")]
+  InvalidOrderBookPage,
+  #[error("Order book page is full")]
+  OrderBookPageFull,
+  #[error("Ring swap needs between 3 and 8 distinct parties, each depositing a non-zero amount")]
+  InvalidRingParties,
+  #[error("Every party must deposit before the ring settles")]
+  RingIncomplete,
+  #[error("Escrow must offer a non-zero amount")]
+  EmptyOffer,
+  #[error("Token account already has a delegate")]
+  TokenAccountAlreadyDelegated,
+  #[error("Order nonce is too far above the maker's lowest unused nonce")]
+  OrderNonceOutsideWindow,
+  #[error("Arbiter can't be the buyer or the seller")]
+  ArbiterIsParty,
+  #[error("Buyer's share is above the vault balance")]
+  SplitAboveVaultBalance,
+  #[error("Timeout has already passed")]
+  TimeoutInPast,
+  #[error("Only NFTs can be auctioned")]
+  NotAnNft,
+  #[error("Bid refund account does not match the auction and bidder")]
+  InvalidBidRefundKey,
+  #[error("Top bidder can't withdraw before the auction settles")]
+  TopBidderCannotWithdraw,
+  #[error("Only an empty order book page without a next page can be closed")]
+  OrderBookPageInUse,
+  #[error("Neighbours in a ring swap can't deposit the same mint")]
+  RingLegsShareMint,
+  #[error("Ring swap still holds a deposit")]
+  RingHasDeposits,
+  #[error("Delegated allowance is above the account balance, only the initializer can revoke it")]
+  AllowanceAboveBalance,
+  #[error("Royalties and fees exceed the payment")]
+  FeesExceedPayment,
+  #[error("Signed order is for another escrow program")]
+  OrderForAnotherProgram,
+  #[error("Auction vault doesn't hold the NFT")]
+  AuctionVaultEmpty,
+  #[error("Every leg of an exchange of many escrows has to trade the same mint pair")]
+  ExchangeLegsMixMints,
 }
 
 impl From<EscrowError> for ProgramError {
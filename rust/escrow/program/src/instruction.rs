@@ -1,49 +1,740 @@
 use {
-  crate::state::EscrowReceive,
+  crate::{
+    pda::{
+      find_config_address, find_escrow_address, find_order_book_page_address, find_vault_authority,
+    },
+    state::{
+      DutchPricing, EscrowReceive, FeeRecipient, HashAlgorithm, SideKind, SignedOrder,
+      VestingSchedule,
+    },
+  },
   borsh::{BorshDeserialize, BorshSerialize},
+  solana_program::{
+    clock::UnixTimestamp,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    sysvar,
+  },
 };
 
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct InitEscrowArgs {
   pub data: EscrowReceive,
+  /// Distinguishes escrows of the same initializer and mint pair
+  pub seed: u64,
+  /// Unix timestamp after which the escrow can no longer be taken
+  pub expires_at: Option<UnixTimestamp>,
+  /// If set, only this key can take the escrow
+  pub allowed_taker: Option<Pubkey>,
+  /// How the initializer deposits the offered side
+  pub offered_kind: SideKind,
+  /// How the taker pays the requested side
+  pub requested_kind: SideKind,
+  /// Lamports moved into the escrow account when `offered_kind` is `SideKind::Lamports`,
+  /// or tokens approved to the vault authority when it is `SideKind::Delegate`
+  pub offered_amount: u64,
+  /// If set, takers pay a price decaying along it. Its start price replaces `data.amount`
+  pub dutch_pricing: Option<DutchPricing>,
 }
 
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct ExchangeArgs {
+  /// The most the taker agrees to pay for `fill_amount`
   pub data: EscrowReceive,
+  /// Amount of the offered token to take. Anything below the vault balance is a partial fill
+  pub fill_amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ExchangeManyArgs {
+  /// One per escrow to fill, in the order of the account groups
+  pub legs: Vec<ExchangeArgs>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct RingLeg {
+  pub party: Pubkey,
+  /// Mint the party deposits for the next party in the ring
+  pub mint: Pubkey,
+  pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitRingSwapArgs {
+  /// Distinguishes ring swaps of the same creator
+  pub seed: u64,
+  /// Parties in ring order, the last one pays the first one. Neighbours deposit different mints
+  pub legs: Vec<RingLeg>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitConfigArgs {
+  pub fee_basis_points: u16,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct UpdateConfigArgs {
+  /// Hands the config over to a new admin
+  pub admin: Option<Pubkey>,
+  pub fee_basis_points: Option<u16>,
+  /// Replaces the whole list of fee recipients
+  pub fee_recipients: Option<Vec<FeeRecipient>>,
+  /// Pauses or resumes new escrows and exchanges
+  pub paused: Option<bool>,
+  /// Enables creator royalties of NFTs described by this token metadata program, Some(None) disables them
+  pub token_metadata_program: Option<Option<Pubkey>>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct RequestedLeg {
+  /// Native mint requests lamports
+  pub mint: Pubkey,
+  pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitBasketEscrowArgs {
+  /// Distinguishes baskets of the same initializer
+  pub seed: u64,
+  pub requested: Vec<RequestedLeg>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct FillSignedOrderArgs {
+  pub order: SignedOrder,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct SetOrderNonceArgs {
+  /// Signed orders below this nonce can no longer be filled, whether or not their nonce was used
+  pub nonce: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct UpdateEscrowArgs {
+  /// New price of everything left in the escrow after the update. Keeps the current ratio if not set
+  pub expected_amount: Option<u64>,
+  /// Offered amount added to the escrow
+  pub deposit_amount: u64,
+  /// Offered amount returned to the initializer
+  pub withdraw_amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitArbitratedEscrowArgs {
+  /// Distinguishes arbitrated escrows of the same buyer
+  pub seed: u64,
+  pub seller: Pubkey,
+  pub arbiter: Pubkey,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ResolveDisputeArgs {
+  /// Part of the vault going back to the buyer, the seller gets the rest. At most the vault balance
+  pub buyer_amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitVestingEscrowArgs {
+  /// Distinguishes vesting escrows of the same funder
+  pub seed: u64,
+  pub beneficiary: Pubkey,
+  pub revocation_authority: Option<Pubkey>,
+  pub schedule: VestingSchedule,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitHashLockedEscrowArgs {
+  /// Distinguishes hash locked escrows of the same maker
+  pub seed: u64,
+  pub taker: Pubkey,
+  pub hash_algorithm: HashAlgorithm,
+  pub hash: [u8; 32],
+  pub timeout: UnixTimestamp,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct ClaimWithSecretArgs {
+  /// Preimage of the escrow's hash
+  pub secret: Vec<u8>,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct InitAuctionArgs {
+  /// Distinguishes auctions of the same seller
+  pub seed: u64,
+  pub reserve_price: u64,
+  pub end_time: UnixTimestamp,
+  /// Bids closer than this to the end extend it, 0 disables the extension
+  pub extension_window: i64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct PlaceBidArgs {
+  pub amount: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Clone)]
 pub enum EscrowInstruction {
   /// Starts the trade by creating and populating escrow account
-  /// 0. `[signer]` The account initializing the escrow. Transferring the ownership of the temporary account requires Initializer's signature.
-  /// 1. `[writable]` Temporary token account for escrow
+  /// A lamport side uses the native mint in the escrow seeds and ignores its token account.
+  /// The offered amount can't be zero.
+  /// 0. `[writable, signer]` The account initializing the escrow. Transferring the ownership of the temporary account requires Initializer's signature. Also pays rent for the escrow account and offered lamports.
+  /// 1. `[writable]` Temporary token account for escrow, or the initializer's token account to delegate from. A delegating account can't already have a delegate, so it backs a single escrow at a time
   /// 2. `[]` Token account of receiving token from the other
-  /// 3. `[writable]` escrow account
-  /// 4. `[]` Rent sysvar(An account that provides cluster info. In this case check if other account are rent exempt)
+  /// 3. `[writable]` escrow account (pda of ['escrow', program id, initializer, offered mint, requested mint, seed])
+  /// 4. `[]` Rent sysvar
   /// 5. `[]` The token prograrm
+  /// 6. `[]` System program
+  /// 7. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account]). Only used in delegate mode
+  /// 8. `[]` Config (pda of ['escrow', program id, 'config']). Escrows can't be created while it is paused
+  /// 9. `[writable]` Order book page the escrow is listed in (pda of ['escrow', program id, offered mint, requested mint, 'orderbook', page index]). Created if it doesn't exist yet, the initializer paying its rent
+  /// 10. `[writable]` The previous order book page, linked to the new one. Only used when creating a page past the first one, which requires it to be full
   InitEscrow(InitEscrowArgs),
 
-  /// Accept trade
-  /// 0. `[signer]` The account of the person taking the trade
-  /// 1. `[writable]` The taker's token account for the token they send
-  /// 2. `[writable]` The taker's token account for the token they will receive should the trade go through
-  /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
+  /// Accept trade, fully or partially at the initializer's ratio, or at the current Dutch price. The escrow closes once its vault is empty, or in delegate mode once its allowance is used up.
+  /// Fails if the payment for the fill is above the amount the taker agreed to
+  /// 0. `[writable, signer]` The account of the person taking the trade. Must be the allowed taker if the escrow names one. Pays requested lamports
+  /// 1. `[writable]` The taker's token account for the token they send. Ignored when lamports are requested
+  /// 2. `[writable]` The taker's token account for the token they will receive should the trade go through, or the account receiving offered lamports
+  /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close, or the initializer's delegating token account. Ignored when lamports are offered
   /// 4. `[writable]` The initializer's main account to send their rent fees to
-  /// 5. `[writable]` The initializer's token account that will receive tokens
+  /// 5. `[writable]` The initializer's token account that will receive tokens, or the initializer's main account when lamports are requested
   /// 6. `[writable]` The escrow account holding the escrow info
   /// 7. `[]` The token program
-  /// 8. `[]` The PDA account
+  /// 8. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 9. `[]` Clock sysvar
+  /// 10. `[]` System program
+  /// 11. `[]` Config (pda of ['escrow', program id, 'config']). No fee is taken while it is uninitialized, exchanges are rejected while it is paused
+  /// 12. `[writable]` Fee recipient configured for the requested side kind and mint. Ignored if there is none
+  /// 13. `[writable]` Fee recipient configured for the offered side kind and mint. Ignored if there is none
+  /// 14. `[]` Metadata of the offered mint (pda of ['metadata', token metadata program id, offered mint]). Ignored unless the config sets a token metadata program
+  /// 15. `[writable]` Order book page listing the escrow, which is removed from it once the vault is empty
+  /// 16. `[]` The offered mint. Royalties are only paid on NFTs, a supply of 1 with 0 decimals. Ignored unless the config sets a token metadata program
+  /// 17. `[writable]` Starting here, one account per verified creator of the metadata in order: the creator's token account for the requested mint or their main account when lamports are requested
   Exchange(ExchangeArgs),
 
   /// Cancel escrow
-  /// 0. `[signer]` The account cancelling the escrow
-  /// 1. `[writable]` The initializer's main account to transfer back the amount sent to temp token account
-  /// 2. `[writable]` The PDA's temp token account to close
+  /// 0. `[writable, signer]` The initializer's main account to transfer back the amount sent to temp token account
+  /// 1. `[writable]` The PDA's temp token account to hand back, closed if it is empty or holds wrapped SOL. The initializer's token account to revoke in delegate mode. Ignored when lamports are offered
+  /// 2. `[writable]` The escrow account holding the escrow info
+  /// 3. `[]` The token program
+  /// 4. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 5. `[writable]` Order book page listing the escrow
+  CancelEscrow(),
+
+  /// Refund an escrow past its deadline. Anyone can send this.
+  /// A delegate mode escrow has its remaining allowance moved to another token account of the initializer, which clears the delegation.
+  /// An allowance above the delegating account's balance can't be used up, the initializer has to sign to revoke it then
+  /// 0. `[writable]` The initializer's main account to send their rent fees to. Revokes the delegation instead when it signs
+  /// 1. `[writable]` The initializer's token account to return the vault tokens to. In delegate mode it must differ from the delegating account. Ignored when lamports are offered
+  /// 2. `[writable]` The PDA's temp token account to empty and close, or the initializer's delegating token account. Ignored when lamports are offered
   /// 3. `[writable]` The escrow account holding the escrow info
   /// 4. `[]` The token program
-  /// 5. `[]` The PDA account
-  CancelEscrow(),
+  /// 5. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 6. `[]` Clock sysvar
+  /// 7. `[writable]` Order book page listing the escrow
+  ExpireEscrow(),
+
+  /// Create the program config. The signer becomes its admin
+  /// 0. `[writable, signer]` Admin, also pays rent for the config account
+  /// 1. `[writable]` Config (pda of ['escrow', program id, 'config'])
+  /// 2. `[]` Rent sysvar
+  /// 3. `[]` System program
+  InitConfig(InitConfigArgs),
+
+  /// Update the program config
+  /// 0. `[signer]` Admin
+  /// 1. `[writable]` Config (pda of ['escrow', program id, 'config'])
+  UpdateConfig(UpdateConfigArgs),
+
+  /// Starts a basket trade, every offered temp token account is handed to the vault authority
+  /// 0. `[writable, signer]` The account initializing the basket, also pays rent for it
+  /// 1. `[writable]` The basket escrow account (pda of ['escrow', program id, initializer, 'basket', seed])
+  /// 2. `[]` The rent sysvar
+  /// 3. `[]` The token program
+  /// 4. `[]` System program
+  /// 5. `[]` Config (pda of ['escrow', program id, 'config']). Baskets can't be created while it is paused
+  /// 6. `[writable]` Starting here, the initializer's token account receiving each requested leg in order (their main account for lamports), then one temp token account holding the tokens of each offered leg
+  InitBasketEscrow(InitBasketEscrowArgs),
+
+  /// Settles every leg of a basket at once. No creator royalties are paid, the requested legs price the basket as a whole rather than each offered NFT
+  /// 0. `[writable, signer]` The account of the person taking the trade. Pays requested lamports
+  /// 1. `[writable]` The initializer's main account to send their rent fees to
+  /// 2. `[writable]` The basket escrow account
+  /// 3. `[]` The token program
+  /// 4. `[]` The basket's vault authority PDA (['escrow', program id, basket escrow account])
+  /// 5. `[]` System program
+  /// 6. `[]` Config (pda of ['escrow', program id, 'config']). No fee is taken while it is uninitialized
+  /// 7. `[writable]` Starting here, for each requested leg in order: the taker's token account to pay from (ignored for lamports), the initializer's receiving account and the fee recipient configured for its mint. Then for each offered leg in order: the vault, the taker's token account to receive it and the fee recipient configured for its mint
+  ExchangeBasket(),
+
+  /// Cancel a basket, ownership of every vault goes back to the initializer
+  /// 0. `[writable, signer]` The initializer's main account
+  /// 1. `[writable]` The basket escrow account
+  /// 2. `[]` The token program
+  /// 3. `[]` The basket's vault authority PDA (['escrow', program id, basket escrow account])
+  /// 4. `[writable]` Starting here, each vault of the basket in order
+  CancelBasketEscrow(),
+
+  /// Fill an order the maker signed off-chain. The maker must have approved the offered amount to the order delegate.
+  /// The instruction right before this one has to be an ed25519 program instruction verifying the maker's signature of the borsh encoded order
+  /// 0. `[writable, signer]` The account of the person taking the order. Pays rent for the maker's nonce account on first use
+  /// 1. `[writable]` The taker's token account for the requested mint
+  /// 2. `[writable]` The taker's token account to receive the offered mint
+  /// 3. `[writable]` The maker's token account delegating the offered mint
+  /// 4. `[writable]` The maker's token account to receive the requested mint
+  /// 5. `[writable]` The maker's nonce account (pda of ['escrow', program id, maker, 'nonce'])
+  /// 6. `[]` The order delegate PDA (['escrow', program id, 'order'])
+  /// 7. `[]` The token program
+  /// 8. `[]` Clock sysvar
+  /// 9. `[]` Instructions sysvar
+  /// 10. `[]` Rent sysvar
+  /// 11. `[]` System program
+  /// 12. `[]` Config (pda of ['escrow', program id, 'config']). No fee is taken while it is uninitialized, orders are rejected while it is paused
+  /// 13. `[writable]` Fee recipient configured for the requested side kind and mint. Ignored if there is none
+  /// 14. `[writable]` Fee recipient configured for the offered side kind and mint. Ignored if there is none
+  FillSignedOrder(FillSignedOrderArgs),
+
+  /// Raise the maker's lowest nonce, cancelling every signed order below it at once
+  /// 0. `[writable, signer]` The maker, pays rent for their nonce account on first use
+  /// 1. `[writable]` The maker's nonce account (pda of ['escrow', program id, maker, 'nonce'])
+  /// 2. `[]` Rent sysvar
+  /// 3. `[]` System program
+  SetOrderNonce(SetOrderNonceArgs),
+
+  /// Amend the terms of an open escrow
+  /// 0. `[writable, signer]` The initializer's main account. Pays topped up lamports and receives withdrawn ones
+  /// 1. `[writable]` The initializer's token account to top up from and withdraw to. Ignored unless tokens are held in a temp token account
+  /// 2. `[writable]` The PDA's temp token account, or the initializer's delegating token account. Ignored when lamports are offered
+  /// 3. `[writable]` The escrow account holding the escrow info
+  /// 4. `[]` The initializer's token account that will receive tokens from now on, or the initializer's main account when lamports are requested
+  /// 5. `[]` The token program
+  /// 6. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 7. `[]` System program
+  UpdateEscrow(UpdateEscrowArgs),
+
+  /// Locks a buyer's tokens for a seller until the buyer releases them or an arbiter settles a dispute.
+  /// The arbiter has to be someone other than the buyer and the seller
+  /// 0. `[writable, signer]` The buyer, also pays rent for the escrow account
+  /// 1. `[writable]` Temp token account holding the buyer's tokens, handed to the vault authority
+  /// 2. `[writable]` The escrow account (pda of ['escrow', program id, buyer, 'arbitrated', seed])
+  /// 3. `[]` The rent sysvar
+  /// 4. `[]` The token program
+  /// 5. `[]` System program
+  /// 6. `[]` Config (pda of ['escrow', program id, 'config']). Escrows can't be created while it is paused
+  InitArbitratedEscrow(InitArbitratedEscrowArgs),
+
+  /// Buyer pays the whole vault out to the seller
+  /// 0. `[writable, signer]` The buyer, receives the rent of the vault and escrow
+  /// 1. `[writable]` The vault
+  /// 2. `[writable]` The seller's token account of the escrow's mint
+  /// 3. `[writable]` The escrow account
+  /// 4. `[]` The token program
+  /// 5. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  Release(),
+
+  /// Hands the escrow over to its arbiter
+  /// 0. `[signer]` The buyer or the seller
+  /// 1. `[writable]` The escrow account
+  OpenDispute(),
+
+  /// Arbiter splits the vault of a disputed escrow between buyer and seller
+  /// 0. `[signer]` The arbiter
+  /// 1. `[writable]` The vault
+  /// 2. `[writable]` The buyer's token account of the escrow's mint
+  /// 3. `[writable]` The seller's token account of the escrow's mint
+  /// 4. `[writable]` The buyer's main account, receives the rent of the vault and escrow
+  /// 5. `[writable]` The escrow account
+  /// 6. `[]` The token program
+  /// 7. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  ResolveDispute(ResolveDisputeArgs),
+
+  /// Locks a grant that vests to a beneficiary on a schedule
+  /// 0. `[writable, signer]` The funder, also pays rent for the escrow account
+  /// 1. `[writable]` Temp token account holding the grant, handed to the vault authority. Tranches must add up to its amount
+  /// 2. `[writable]` The escrow account (pda of ['escrow', program id, funder, 'vesting', seed])
+  /// 3. `[]` The rent sysvar
+  /// 4. `[]` The token program
+  /// 5. `[]` System program
+  /// 6. `[]` Config (pda of ['escrow', program id, 'config']). Escrows can't be created while it is paused
+  InitVestingEscrow(InitVestingEscrowArgs),
+
+  /// Pays out what has vested to the beneficiary. Anyone can send this. The escrow closes once everything is claimed,
+  /// and the last claim also pays out any tokens sent to the vault on top of the grant
+  /// 0. `[writable]` The beneficiary's token account of the grant's mint
+  /// 1. `[writable]` The vault
+  /// 2. `[writable]` The escrow account
+  /// 3. `[writable]` The funder's main account, receives the rent of the vault and escrow
+  /// 4. `[]` The token program
+  /// 5. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 6. `[]` Clock sysvar
+  Claim(),
+
+  /// Pays out what has vested and returns the unvested remainder to the funder
+  /// 0. `[signer]` The revocation authority
+  /// 1. `[writable]` The vault
+  /// 2. `[writable]` The beneficiary's token account of the grant's mint
+  /// 3. `[writable]` The funder's token account of the grant's mint
+  /// 4. `[writable]` The funder's main account, receives the rent of the vault and escrow
+  /// 5. `[writable]` The escrow account
+  /// 6. `[]` The token program
+  /// 7. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 8. `[]` Clock sysvar
+  RevokeVesting(),
+
+  /// Locks a maker's tokens until a taker reveals the preimage of a hash, or the timeout passes
+  /// 0. `[writable, signer]` The maker, also pays rent for the escrow account
+  /// 1. `[writable]` Temp token account holding the maker's tokens, handed to the vault authority
+  /// 2. `[writable]` The escrow account (pda of ['escrow', program id, maker, 'hashlock', seed])
+  /// 3. `[]` The rent sysvar
+  /// 4. `[]` The token program
+  /// 5. `[]` System program
+  /// 6. `[]` Config (pda of ['escrow', program id, 'config']). Escrows can't be created while it is paused
+  /// 7. `[]` Clock sysvar, the timeout has to be in the future
+  InitHashLockedEscrow(InitHashLockedEscrowArgs),
+
+  /// Taker reveals the secret before the timeout and receives the vault
+  /// 0. `[signer]` The taker
+  /// 1. `[writable]` The taker's token account of the escrow's mint
+  /// 2. `[writable]` The vault
+  /// 3. `[writable]` The maker's main account, receives the rent of the vault and escrow
+  /// 4. `[writable]` The escrow account
+  /// 5. `[]` The token program
+  /// 6. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 7. `[]` Clock sysvar
+  ClaimWithSecret(ClaimWithSecretArgs),
+
+  /// Maker takes back the vault once the timeout has passed
+  /// 0. `[writable, signer]` The maker, receives the rent of the vault and escrow
+  /// 1. `[writable]` The maker's token account of the escrow's mint
+  /// 2. `[writable]` The vault
+  /// 3. `[writable]` The escrow account
+  /// 4. `[]` The token program
+  /// 5. `[]` The escrow's vault authority PDA (['escrow', program id, escrow account])
+  /// 6. `[]` Clock sysvar
+  Refund(),
+
+  /// Puts a vaulted NFT up for a timed English auction
+  /// 0. `[writable, signer]` The seller, also pays rent for the auction account
+  /// 1. `[writable]` Temp token account holding the NFT, handed to the vault authority
+  /// 2. `[writable]` Empty token account of the bid mint, handed to the vault authority to hold the top bid
+  /// 3. `[writable]` The auction account (pda of ['escrow', program id, seller, 'auction', seed])
+  /// 4. `[]` The rent sysvar
+  /// 5. `[]` The token program
+  /// 6. `[]` System program
+  /// 7. `[]` Config (pda of ['escrow', program id, 'config']). Auctions can't be created while it is paused
+  /// 8. `[]` Mint of the NFT, which must have a supply of 1 and no decimals
+  /// 9. `[]` Clock sysvar. The end time has to be in the future
+  InitAuction(InitAuctionArgs),
+
+  /// Escrows a bid above the current top bid. The previous top bid stays in the bid vault and is
+  /// owed to its bidder, who withdraws it with WithdrawBidRefund
+  /// 0. `[writable, signer]` The bidder, pays rent for its bid refund account on its first bid
+  /// 1. `[writable]` The bidder's token account of the bid mint
+  /// 2. `[writable]` The bid vault
+  /// 3. `[writable]` The bidder's bid refund account (pda of ['escrow', program id, auction account, 'refund', bidder])
+  /// 4. `[writable]` The previous top bidder's bid refund account. Ignored for the first bid
+  /// 5. `[writable]` The auction account
+  /// 6. `[]` The token program
+  /// 7. `[]` The auction's vault authority PDA (['escrow', program id, auction account])
+  /// 8. `[]` Clock sysvar
+  /// 9. `[]` The rent sysvar
+  /// 10. `[]` System program
+  PlaceBid(PlaceBidArgs),
+
+  /// Pays the top bid to the seller and delivers the NFT to the winner once the auction has ended.
+  /// Without bids the NFT goes back to the seller. Anyone can send this. The bid vault and auction
+  /// account stay open until every outbid bidder has withdrawn its refund
+  /// 0. `[writable]` The seller's main account, receives the rent of the vaults and auction
+  /// 1. `[writable]` The seller's token account of the bid mint. Ignored without bids
+  /// 2. `[writable]` The winner's token account of the NFT mint, or the seller's one without bids
+  /// 3. `[writable]` The NFT vault
+  /// 4. `[writable]` The bid vault
+  /// 5. `[writable]` The auction account
+  /// 6. `[]` The token program
+  /// 7. `[]` The auction's vault authority PDA (['escrow', program id, auction account])
+  /// 8. `[]` Clock sysvar
+  SettleAuction(),
+
+  /// Pays an outbid bidder what the auction owes it and closes its bid refund account. The last
+  /// withdrawal after settlement also closes the bid vault and auction account
+  /// 0. `[writable, signer]` The bidder, receives the rent of its bid refund account
+  /// 1. `[writable]` The bidder's token account of the bid mint
+  /// 2. `[writable]` The bidder's bid refund account
+  /// 3. `[writable]` The bid vault
+  /// 4. `[writable]` The auction account
+  /// 5. `[writable]` The seller's main account, receives the rent of the bid vault and auction
+  /// 6. `[]` The token program
+  /// 7. `[]` The auction's vault authority PDA (['escrow', program id, auction account])
+  WithdrawBidRefund(),
+
+  /// Fills several escrows at once, each leg with the same checks as Exchange. Fails as a whole if any leg fails.
+  /// The taker accounts and fee recipients are shared, so every escrow has to offer and request the same mints, and be paid
+  /// the same kind, as the first one
+  /// 0. `[writable, signer]` The taker. Must be the allowed taker of every private escrow. Pays requested lamports
+  /// 1. `[writable]` The taker's token account for the token they send. Ignored when lamports are requested
+  /// 2. `[writable]` The taker's token account for the token they receive, or the account receiving offered lamports
+  /// 3. `[]` The token program
+  /// 4. `[]` Clock sysvar
+  /// 5. `[]` System program
+  /// 6. `[]` Config (pda of ['escrow', program id, 'config'])
+  /// 7. `[writable]` Fee recipient configured for the requested side kind and mint. Ignored if there is none
+  /// 8. `[writable]` Fee recipient configured for the offered side kind and mint. Ignored if there is none
+  /// 9. `[writable]` Starting here, one group per leg in order: the vault, the initializer's main account, the initializer's receive account, the escrow account, its vault authority PDA, the metadata of its offered mint, the order book page listing it, its offered mint and its creator accounts, all as in Exchange
+  ExchangeMany(ExchangeManyArgs),
+
+  /// Creates a ring swap waiting for the deposit of every party
+  /// 0. `[writable, signer]` The creator, pays rent for the ring account
+  /// 1. `[writable]` The ring account (pda of ['escrow', program id, creator, 'ring', seed])
+  /// 2. `[]` The rent sysvar
+  /// 3. `[]` System program
+  /// 4. `[]` Config (pda of ['escrow', program id, 'config']). Ring swaps can't be created while it is paused
+  InitRingSwap(InitRingSwapArgs),
+
+  /// A party deposits its leg of the ring
+  /// 0. `[signer]` The party
+  /// 1. `[writable]` Temp token account holding exactly the party's amount of its mint, handed to the vault authority
+  /// 2. `[]` The party's token account of the previous party's mint, receives it at settlement
+  /// 3. `[writable]` The ring account
+  /// 4. `[]` The token program
+  DepositRing(),
+
+  /// A party takes its deposit back before the ring settles
+  /// 0. `[signer]` The party
+  /// 1. `[writable]` The party's vault, handed back to it
+  /// 2. `[writable]` The ring account
+  /// 3. `[]` The token program
+  /// 4. `[]` The ring's vault authority PDA (['escrow', program id, ring account])
+  WithdrawRing(),
+
+  /// Pays every deposit to the next party once all parties have deposited. Anyone can send this
+  /// 0. `[writable]` The creator's main account, receives the rent of the ring account
+  /// 1. `[writable]` The ring account
+  /// 2. `[]` The token program
+  /// 3. `[]` The ring's vault authority PDA (['escrow', program id, ring account])
+  /// 4. `[writable]` Starting here, one group per party in ring order: its vault, the receive account of the next party and the party's main account receiving the vault's rent
+  SettleRing(),
+
+  /// Closes an empty order book page without a next page and returns its rent to whoever paid it. Anyone can send this
+  /// 0. `[writable]` The order book page
+  /// 1. `[writable]` The account that paid the page's rent
+  /// 2. `[writable]` The previous order book page, unlinked from the closed one. Ignored for the first page
+  CloseOrderBookPage(),
+
+  /// Closes a ring swap no party has a deposit in, returning its rent to the creator
+  /// 0. `[writable, signer]` The creator
+  /// 1. `[writable]` The ring account
+  CloseRingSwap(),
+}
+
+/// Creates an InitEscrow instruction. Lamport sides take the native mint as their mint.
+/// The escrow is listed in the order book page `order_book_page_index` of its mint pair
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow(
+  program_id: Pubkey,
+  initializer: Pubkey,
+  temp_token_account: Pubkey,
+  token_to_receive_account: Pubkey,
+  offered_mint: Pubkey,
+  requested_mint: Pubkey,
+  order_book_page_index: u32,
+  args: InitEscrowArgs,
+) -> Instruction {
+  let (escrow, _) = find_escrow_address(
+    &program_id,
+    &initializer,
+    &offered_mint,
+    &requested_mint,
+    args.seed,
+  );
+  let (vault_authority, _) = find_vault_authority(&program_id, &escrow);
+  let (config, _) = find_config_address(&program_id);
+  let (order_book_page, _) = find_order_book_page_address(
+    &program_id,
+    &offered_mint,
+    &requested_mint,
+    order_book_page_index,
+  );
+  let (previous_order_book_page, _) = find_order_book_page_address(
+    &program_id,
+    &offered_mint,
+    &requested_mint,
+    order_book_page_index.saturating_sub(1),
+  );
+  Instruction {
+    program_id,
+    accounts: vec![
+      AccountMeta::new(initializer, true),
+      AccountMeta::new(temp_token_account, false),
+      AccountMeta::new_readonly(token_to_receive_account, false),
+      AccountMeta::new(escrow, false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(solana_program::system_program::id(), false),
+      AccountMeta::new_readonly(vault_authority, false),
+      AccountMeta::new_readonly(config, false),
+      AccountMeta::new(order_book_page, false),
+      AccountMeta::new(previous_order_book_page, false),
+    ],
+    data: EscrowInstruction::InitEscrow(args).try_to_vec().unwrap(),
+  }
+}
+
+/// Creates an Exchange instruction. `order_book_page` is the page listing the escrow,
+/// `creator_accounts` are only needed when royalties are paid. `max_payment` caps what the taker pays for `fill_amount`
+#[allow(clippy::too_many_arguments)]
+pub fn exchange(
+  program_id: Pubkey,
+  taker: Pubkey,
+  taker_sending_token_account: Pubkey,
+  taker_receive_token_account: Pubkey,
+  vault: Pubkey,
+  initializer: Pubkey,
+  initializer_receive_account: Pubkey,
+  escrow: Pubkey,
+  requested_fee_recipient: Pubkey,
+  offered_fee_recipient: Pubkey,
+  metadata: Pubkey,
+  order_book_page: Pubkey,
+  offered_mint: Pubkey,
+  creator_accounts: Vec<Pubkey>,
+  max_payment: u64,
+  fill_amount: u64,
+) -> Instruction {
+  let (vault_authority, _) = find_vault_authority(&program_id, &escrow);
+  let (config, _) = find_config_address(&program_id);
+  let mut accounts = vec![
+    AccountMeta::new(taker, true),
+    AccountMeta::new(taker_sending_token_account, false),
+    AccountMeta::new(taker_receive_token_account, false),
+    AccountMeta::new(vault, false),
+    AccountMeta::new(initializer, false),
+    AccountMeta::new(initializer_receive_account, false),
+    AccountMeta::new(escrow, false),
+    AccountMeta::new_readonly(spl_token::id(), false),
+    AccountMeta::new_readonly(vault_authority, false),
+    AccountMeta::new_readonly(sysvar::clock::id(), false),
+    AccountMeta::new_readonly(solana_program::system_program::id(), false),
+    AccountMeta::new_readonly(config, false),
+    AccountMeta::new(requested_fee_recipient, false),
+    AccountMeta::new(offered_fee_recipient, false),
+    AccountMeta::new_readonly(metadata, false),
+    AccountMeta::new(order_book_page, false),
+    AccountMeta::new_readonly(offered_mint, false),
+  ];
+  accounts.extend(
+    creator_accounts
+      .into_iter()
+      .map(|creator_account| AccountMeta::new(creator_account, false)),
+  );
+  Instruction {
+    program_id,
+    accounts,
+    data: EscrowInstruction::Exchange(ExchangeArgs {
+      data: EscrowReceive {
+        amount: max_payment,
+      },
+      fill_amount,
+    })
+    .try_to_vec()
+    .unwrap(),
+  }
+}
+
+/// Creates a CancelEscrow instruction. `order_book_page` is the page listing the escrow
+pub fn cancel_escrow(
+  program_id: Pubkey,
+  initializer: Pubkey,
+  temp_token_account: Pubkey,
+  escrow: Pubkey,
+  order_book_page: Pubkey,
+) -> Instruction {
+  let (vault_authority, _) = find_vault_authority(&program_id, &escrow);
+  Instruction {
+    program_id,
+    accounts: vec![
+      AccountMeta::new(initializer, true),
+      AccountMeta::new(temp_token_account, false),
+      AccountMeta::new(escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority, false),
+      AccountMeta::new(order_book_page, false),
+    ],
+    data: EscrowInstruction::CancelEscrow().try_to_vec().unwrap(),
+  }
+}
+
+/// Creates an ExpireEscrow instruction. `order_book_page` is the page listing the escrow
+pub fn expire_escrow(
+  program_id: Pubkey,
+  initializer: Pubkey,
+  initializer_refund_token_account: Pubkey,
+  temp_token_account: Pubkey,
+  escrow: Pubkey,
+  order_book_page: Pubkey,
+) -> Instruction {
+  let (vault_authority, _) = find_vault_authority(&program_id, &escrow);
+  Instruction {
+    program_id,
+    accounts: vec![
+      AccountMeta::new(initializer, false),
+      AccountMeta::new(initializer_refund_token_account, false),
+      AccountMeta::new(temp_token_account, false),
+      AccountMeta::new(escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority, false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+      AccountMeta::new(order_book_page, false),
+    ],
+    data: EscrowInstruction::ExpireEscrow().try_to_vec().unwrap(),
+  }
+}
+
+/// Creates a CloseOrderBookPage instruction for the empty last page of a mint pair
+pub fn close_order_book_page(
+  program_id: Pubkey,
+  offered_mint: Pubkey,
+  requested_mint: Pubkey,
+  page_index: u32,
+  payer: Pubkey,
+) -> Instruction {
+  let (page, _) =
+    find_order_book_page_address(&program_id, &offered_mint, &requested_mint, page_index);
+  let (previous_page, _) = find_order_book_page_address(
+    &program_id,
+    &offered_mint,
+    &requested_mint,
+    page_index.saturating_sub(1),
+  );
+  Instruction {
+    program_id,
+    accounts: vec![
+      AccountMeta::new(page, false),
+      AccountMeta::new(payer, false),
+      AccountMeta::new(previous_page, false),
+    ],
+    data: EscrowInstruction::CloseOrderBookPage()
+      .try_to_vec()
+      .unwrap(),
+  }
 }
@@ -1,8 +1,10 @@
 pub mod entrypoint;
 pub mod error;
 pub mod instruction;
+pub mod pda;
 pub mod processor;
 pub mod state;
+pub mod util;
 
 // Export current sdk types for downstream users building with a different sdk version
 pub use solana_program;
@@ -0,0 +1,207 @@
+use {
+  crate::state::{
+    ARBITRATED, AUCTION, BASKET, CONFIG, HASH_LOCK, NONCE, ORDER, ORDER_BOOK, PREFIX, REFUND, RING,
+    VESTING,
+  },
+  solana_program::pubkey::Pubkey,
+  tokenmetadataprogram::state::PREFIX as METADATA_PREFIX,
+};
+
+/// Escrow account of a trade. The native mint stands in for a lamport side
+pub fn find_escrow_address(
+  program_id: &Pubkey,
+  initializer: &Pubkey,
+  offered_mint: &Pubkey,
+  requested_mint: &Pubkey,
+  seed: u64,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      initializer.as_ref(),
+      offered_mint.as_ref(),
+      requested_mint.as_ref(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+/// Authority over the vaults of an escrow or basket escrow
+pub fn find_vault_authority(program_id: &Pubkey, escrow: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[PREFIX.as_bytes(), program_id.as_ref(), escrow.as_ref()],
+    program_id,
+  )
+}
+
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[PREFIX.as_bytes(), program_id.as_ref(), CONFIG.as_bytes()],
+    program_id,
+  )
+}
+
+pub fn find_basket_escrow_address(
+  program_id: &Pubkey,
+  initializer: &Pubkey,
+  seed: u64,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      initializer.as_ref(),
+      BASKET.as_bytes(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+pub fn find_arbitrated_escrow_address(
+  program_id: &Pubkey,
+  buyer: &Pubkey,
+  seed: u64,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      buyer.as_ref(),
+      ARBITRATED.as_bytes(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+pub fn find_vesting_escrow_address(
+  program_id: &Pubkey,
+  funder: &Pubkey,
+  seed: u64,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      funder.as_ref(),
+      VESTING.as_bytes(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+pub fn find_hash_locked_escrow_address(
+  program_id: &Pubkey,
+  maker: &Pubkey,
+  seed: u64,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      maker.as_ref(),
+      HASH_LOCK.as_bytes(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+pub fn find_auction_address(program_id: &Pubkey, seller: &Pubkey, seed: u64) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      seller.as_ref(),
+      AUCTION.as_bytes(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+pub fn find_bid_refund_address(
+  program_id: &Pubkey,
+  auction: &Pubkey,
+  bidder: &Pubkey,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      auction.as_ref(),
+      REFUND.as_bytes(),
+      bidder.as_ref(),
+    ],
+    program_id,
+  )
+}
+
+pub fn find_ring_swap_address(program_id: &Pubkey, creator: &Pubkey, seed: u64) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      creator.as_ref(),
+      RING.as_bytes(),
+      &seed.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+/// Page `page_index` of the open escrows offering `offered_mint` for `requested_mint`
+pub fn find_order_book_page_address(
+  program_id: &Pubkey,
+  offered_mint: &Pubkey,
+  requested_mint: &Pubkey,
+  page_index: u32,
+) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      offered_mint.as_ref(),
+      requested_mint.as_ref(),
+      ORDER_BOOK.as_bytes(),
+      &page_index.to_le_bytes(),
+    ],
+    program_id,
+  )
+}
+
+/// Delegate makers approve their signed orders' tokens to
+pub fn find_order_delegate(program_id: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[PREFIX.as_bytes(), program_id.as_ref(), ORDER.as_bytes()],
+    program_id,
+  )
+}
+
+pub fn find_order_nonce_address(program_id: &Pubkey, maker: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      maker.as_ref(),
+      NONCE.as_bytes(),
+    ],
+    program_id,
+  )
+}
+
+/// Token metadata account of `mint`, owned by `token_metadata_program`
+pub fn find_metadata_address(token_metadata_program: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[
+      METADATA_PREFIX.as_bytes(),
+      token_metadata_program.as_ref(),
+      mint.as_ref(),
+    ],
+    token_metadata_program,
+  )
+}
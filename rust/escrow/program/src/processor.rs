@@ -1,28 +1,91 @@
 use {
-  crate::{error, instruction::EscrowInstruction, state::Escrow},
-  borsh::BorshDeserialize,
+  crate::{
+    error,
+    instruction::{
+      EscrowInstruction, ExchangeArgs, InitAuctionArgs, InitHashLockedEscrowArgs, RequestedLeg,
+      RingLeg,
+    },
+    pda::{
+      find_arbitrated_escrow_address, find_auction_address, find_bid_refund_address,
+      find_config_address, find_hash_locked_escrow_address, find_metadata_address,
+      find_order_book_page_address, find_order_delegate, find_order_nonce_address,
+      find_ring_swap_address, find_vault_authority, find_vesting_escrow_address,
+    },
+    state::{
+      ArbitratedEscrow, ArbitrationStatus, Auction, BasketEscrow, BasketLeg, BidRefund, Config,
+      DutchPricing, Escrow, FeeRecipient, HashAlgorithm, HashLockedEscrow, Key, OrderBookPage,
+      OrderNonce, RingParticipant, RingSwap, SideKind, SignedOrder, VestingEscrow, VestingSchedule,
+      ARBITRATED, ARBITRATED_ESCROW_LEN, AUCTION, AUCTION_LEN, BASKET, BID_REFUND_LEN, CONFIG,
+      HASH_LOCK, HASH_LOCKED_ESCROW_LEN, MAX_BASKET_LEGS, MAX_CONFIG_LEN, MAX_FEE_RECIPIENTS,
+      MAX_RING_PARTIES, MAX_RING_SWAP_LEN, MAX_VESTING_TRANCHES, MIN_RING_PARTIES, NONCE, ORDER,
+      ORDER_BOOK, ORDER_BOOK_PAGE_LEN, ORDER_NONCE_LEN, PREFIX, REFUND, RING, VESTING,
+    },
+    util::{create_or_allocate_account_raw, ed25519_program},
+  },
+  borsh::{BorshDeserialize, BorshSerialize},
   solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
     entrypoint::ProgramResult,
-    msg,
+    hash, keccak, msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
+    program_option::COption,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    system_instruction,
+    sysvar::{self, clock::Clock, rent::Rent, Sysvar},
+  },
+  spl_token::{
+    instruction, native_mint,
+    state::{Account as TokenAccount, Mint},
   },
-  spl_token::{instruction, state::Account as TokenAccount},
-  std::cell::RefMut,
+  std::{cell::RefMut, convert::TryFrom, slice::Iter},
+  tokenmetadataprogram::state::Metadata,
 };
 
 fn assert_owned_by(account: &AccountInfo, owner: &Pubkey) -> ProgramResult {
   if account.owner != owner {
-    Err(ProgramError::IncorrectProgramId)
+    Err(error::EscrowError::IncorrectOwner.into())
+  } else {
+    Ok(())
+  }
+}
+
+fn assert_signer(account: &AccountInfo) -> ProgramResult {
+  if !account.is_signer {
+    Err(error::EscrowError::MissingSigner.into())
+  } else {
+    Ok(())
+  }
+}
+
+fn assert_token_program(token_program: &AccountInfo) -> ProgramResult {
+  if *token_program.key != spl_token::id() {
+    Err(error::EscrowError::InvalidTokenProgram.into())
+  } else {
+    Ok(())
+  }
+}
+
+fn assert_vault_authority(vault_authority: &AccountInfo, expected_key: &Pubkey) -> ProgramResult {
+  if vault_authority.key != expected_key {
+    Err(error::EscrowError::InvalidVaultAuthority.into())
   } else {
     Ok(())
   }
 }
 
+/// Escrow state of an account owned by this program, which has to be initialized
+fn load_escrow(program_id: &Pubkey, escrow_account: &AccountInfo) -> Result<Escrow, ProgramError> {
+  assert_owned_by(escrow_account, program_id)?;
+  let escrow_info: Escrow = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
+  if !escrow_info.is_initialized() {
+    return Err(error::EscrowError::UninitializedEscrow.into());
+  }
+  Ok(escrow_info)
+}
+
 fn close_escrow_account(main_account: &AccountInfo, escrow_account: &AccountInfo) -> ProgramResult {
   // Return lamports to main account
   let returned_amount: u64 = main_account
@@ -39,6 +102,623 @@ fn close_escrow_account(main_account: &AccountInfo, escrow_account: &AccountInfo
   Ok(())
 }
 
+/// Lamports an escrow account holds on top of its rent exempt reserve
+fn escrowed_lamports(escrow_account: &AccountInfo) -> Result<u64, ProgramError> {
+  let rent = Rent::get()?;
+  Ok(
+    escrow_account
+      .lamports()
+      .saturating_sub(rent.minimum_balance(escrow_account.data_len())),
+  )
+}
+
+/// Revokes the vault authority's delegation on a token account, which its owner must sign for
+fn revoke_delegation<'a>(
+  token_account: &AccountInfo<'a>,
+  owner: &AccountInfo<'a>,
+  token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+  msg!("Revoke vault authority delegation");
+  let revoke_instruction = instruction::revoke(
+    token_program.key,
+    token_account.key,
+    owner.key,
+    &[owner.key],
+  )?;
+  invoke(
+    &revoke_instruction,
+    &[token_account.clone(), owner.clone(), token_program.clone()],
+  )
+}
+
+/// Allowance a delegating token account gives the vault authority, whatever its balance
+fn delegated_allowance(token_account_info: &TokenAccount, vault_authority: &Pubkey) -> u64 {
+  match token_account_info.delegate {
+    COption::Some(delegate) if delegate == *vault_authority => token_account_info.delegated_amount,
+    _ => 0,
+  }
+}
+
+/// Tokens the vault authority can still pull from a delegating token account
+fn delegated_amount(token_account_info: &TokenAccount, vault_authority: &Pubkey) -> u64 {
+  delegated_allowance(token_account_info, vault_authority).min(token_account_info.amount)
+}
+
+/// Moves lamports out of an account owned by this program
+fn transfer_escrowed_lamports(
+  escrow_account: &AccountInfo,
+  destination_account: &AccountInfo,
+  amount: u64,
+) -> ProgramResult {
+  let escrow_lamports = escrow_account
+    .lamports()
+    .checked_sub(amount)
+    .ok_or(error::EscrowError::AmountOverflow)?;
+  let destination_lamports = destination_account
+    .lamports()
+    .checked_add(amount)
+    .ok_or(error::EscrowError::AmountOverflow)?;
+
+  **escrow_account.lamports.borrow_mut() = escrow_lamports;
+  **destination_account.lamports.borrow_mut() = destination_lamports;
+  Ok(())
+}
+
+/// Moves `amount` of the requested side from the taker
+fn transfer_from_taker<'a>(
+  requested_kind: SideKind,
+  taker: &AccountInfo<'a>,
+  source_account: &AccountInfo<'a>,
+  destination_account: &AccountInfo<'a>,
+  token_program: &AccountInfo<'a>,
+  system_program: &AccountInfo<'a>,
+  amount: u64,
+) -> ProgramResult {
+  if amount == 0 {
+    return Ok(());
+  }
+  match requested_kind {
+    SideKind::Token => {
+      let transfer_instruction = spl_token::instruction::transfer(
+        token_program.key,
+        source_account.key,
+        destination_account.key,
+        taker.key,
+        &[taker.key],
+        amount,
+      )?;
+      invoke(
+        &transfer_instruction,
+        &[
+          source_account.clone(),
+          destination_account.clone(),
+          taker.clone(),
+          token_program.clone(),
+        ],
+      )
+    }
+    SideKind::Lamports => invoke(
+      &system_instruction::transfer(taker.key, destination_account.key, amount),
+      &[
+        taker.clone(),
+        destination_account.clone(),
+        system_program.clone(),
+      ],
+    ),
+    SideKind::Delegate => Err(error::EscrowError::UnsupportedSideKind.into()),
+  }
+}
+
+/// Moves `amount` of the offered side out of the vault
+#[allow(clippy::too_many_arguments)]
+fn transfer_from_vault<'a>(
+  offered_kind: SideKind,
+  escrow_account: &AccountInfo<'a>,
+  vault_account: &AccountInfo<'a>,
+  destination_account: &AccountInfo<'a>,
+  vault_authority: &AccountInfo<'a>,
+  token_program: &AccountInfo<'a>,
+  signers_seeds: &[&[u8]],
+  amount: u64,
+) -> ProgramResult {
+  if amount == 0 {
+    return Ok(());
+  }
+  match offered_kind {
+    // Vault authority owns the temp token account, or is the delegate of the initializer's one
+    SideKind::Token | SideKind::Delegate => {
+      let transfer_instruction = spl_token::instruction::transfer(
+        token_program.key,
+        vault_account.key,
+        destination_account.key,
+        vault_authority.key,
+        &[vault_authority.key],
+        amount,
+      )?;
+      invoke_signed(
+        &transfer_instruction,
+        &[
+          vault_account.clone(),
+          destination_account.clone(),
+          vault_authority.clone(),
+          token_program.clone(),
+        ],
+        &[signers_seeds],
+      )
+    }
+    // Escrow account is owned by this program so lamports can be debited directly
+    SideKind::Lamports => transfer_escrowed_lamports(escrow_account, destination_account, amount),
+  }
+}
+
+/// Closes an emptied vault owned by the vault authority, its rent goes to `destination_account`
+fn close_vault<'a>(
+  vault_account: &AccountInfo<'a>,
+  destination_account: &AccountInfo<'a>,
+  vault_authority: &AccountInfo<'a>,
+  token_program: &AccountInfo<'a>,
+  signers_seeds: &[&[u8]],
+) -> ProgramResult {
+  let close_vault_instruction = spl_token::instruction::close_account(
+    token_program.key,
+    vault_account.key,
+    destination_account.key,
+    vault_authority.key,
+    &[vault_authority.key],
+  )?;
+  invoke_signed(
+    &close_vault_instruction,
+    &[
+      vault_account.clone(),
+      destination_account.clone(),
+      vault_authority.clone(),
+      token_program.clone(),
+    ],
+    &[signers_seeds],
+  )
+}
+
+/// Arbitrated escrow state, whose vault has to be `vault_account`
+fn load_arbitrated_escrow(
+  program_id: &Pubkey,
+  escrow_account: &AccountInfo,
+  vault_account: &AccountInfo,
+) -> Result<ArbitratedEscrow, ProgramError> {
+  assert_owned_by(escrow_account, program_id)?;
+  let escrow_info = ArbitratedEscrow::from_account_info(escrow_account)?;
+  if escrow_info.vault != *vault_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  Ok(escrow_info)
+}
+
+/// Vesting escrow state, whose vault has to be `vault_account`
+fn load_vesting_escrow(
+  program_id: &Pubkey,
+  escrow_account: &AccountInfo,
+  vault_account: &AccountInfo,
+) -> Result<VestingEscrow, ProgramError> {
+  assert_owned_by(escrow_account, program_id)?;
+  let escrow_info = VestingEscrow::from_account_info(escrow_account)?;
+  if escrow_info.vault != *vault_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  Ok(escrow_info)
+}
+
+/// Hash locked escrow state, whose vault has to be `vault_account`
+fn load_hash_locked_escrow(
+  program_id: &Pubkey,
+  escrow_account: &AccountInfo,
+  vault_account: &AccountInfo,
+) -> Result<HashLockedEscrow, ProgramError> {
+  assert_owned_by(escrow_account, program_id)?;
+  let escrow_info = HashLockedEscrow::from_account_info(escrow_account)?;
+  if escrow_info.vault != *vault_account.key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  Ok(escrow_info)
+}
+
+/// Digest of `secret` through the hashing syscall of `algorithm`
+fn hash_secret(algorithm: HashAlgorithm, secret: &[u8]) -> [u8; 32] {
+  match algorithm {
+    HashAlgorithm::Sha256 => hash::hashv(&[secret]).to_bytes(),
+    HashAlgorithm::Keccak256 => keccak::hashv(&[secret]).to_bytes(),
+  }
+}
+
+/// Order book page of the mint pair, checked against its seeds
+fn load_order_book_page(
+  program_id: &Pubkey,
+  page_account: &AccountInfo,
+  offered_mint: &Pubkey,
+  requested_mint: &Pubkey,
+) -> Result<OrderBookPage, ProgramError> {
+  assert_owned_by(page_account, program_id)?;
+  let page = OrderBookPage::from_account_info(page_account)?;
+  let (page_key, _) =
+    find_order_book_page_address(program_id, offered_mint, requested_mint, page.page_index);
+  if page_key != *page_account.key {
+    return Err(error::EscrowError::InvalidOrderBookPage.into());
+  }
+  Ok(page)
+}
+
+/// Lists a new escrow in `page_account`, creating the page if needed. A page past the first one
+/// can only be created once `previous_page_account` is full, and gets linked from it
+#[allow(clippy::too_many_arguments)]
+fn append_to_order_book<'a>(
+  program_id: &Pubkey,
+  page_account: &AccountInfo<'a>,
+  previous_page_account: &AccountInfo<'a>,
+  escrow_key: &Pubkey,
+  offered_mint: &Pubkey,
+  requested_mint: &Pubkey,
+  payer: &AccountInfo<'a>,
+  rent_info: &AccountInfo<'a>,
+  system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+  if !page_account.data_is_empty() {
+    let mut page = load_order_book_page(program_id, page_account, offered_mint, requested_mint)?;
+    if page.is_full() {
+      return Err(error::EscrowError::OrderBookPageFull.into());
+    }
+    page.escrows.push(*escrow_key);
+    page.serialize(&mut &mut page_account.data.borrow_mut()[..])?;
+    return Ok(());
+  }
+
+  let (first_page_key, _) =
+    find_order_book_page_address(program_id, offered_mint, requested_mint, 0);
+  let page_index = if *page_account.key == first_page_key {
+    0
+  } else {
+    // Any other address is either a later page, which needs its previous one, or not a page at all
+    if previous_page_account.data_is_empty() {
+      return Err(error::EscrowError::InvalidOrderBookPage.into());
+    }
+    let mut previous_page = load_order_book_page(
+      program_id,
+      previous_page_account,
+      offered_mint,
+      requested_mint,
+    )?;
+    if !previous_page.is_full() || previous_page.next_page.is_some() {
+      return Err(error::EscrowError::InvalidOrderBookPage.into());
+    }
+    previous_page.next_page = Some(*page_account.key);
+    previous_page.serialize(&mut &mut previous_page_account.data.borrow_mut()[..])?;
+    previous_page
+      .page_index
+      .checked_add(1)
+      .ok_or(error::EscrowError::AmountOverflow)?
+  };
+
+  let (page_key, page_bump_seed) =
+    find_order_book_page_address(program_id, offered_mint, requested_mint, page_index);
+  if *page_account.key != page_key {
+    return Err(error::EscrowError::InvalidOrderBookPage.into());
+  }
+
+  msg!("Create order book page {}", page_index);
+  create_or_allocate_account_raw(
+    *program_id,
+    page_account,
+    rent_info,
+    system_program,
+    payer,
+    ORDER_BOOK_PAGE_LEN,
+    &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      offered_mint.as_ref(),
+      requested_mint.as_ref(),
+      ORDER_BOOK.as_bytes(),
+      &page_index.to_le_bytes(),
+      &[page_bump_seed],
+    ],
+  )?;
+
+  let page = OrderBookPage {
+    key: Key::OrderBookPageV1,
+    offered_mint: *offered_mint,
+    requested_mint: *requested_mint,
+    page_index,
+    payer: *payer.key,
+    next_page: None,
+    escrows: vec![*escrow_key],
+  };
+  page.serialize(&mut &mut page_account.data.borrow_mut()[..])?;
+  Ok(())
+}
+
+/// Unlists a closing escrow from `page_account`
+fn remove_from_order_book(
+  program_id: &Pubkey,
+  page_account: &AccountInfo,
+  escrow_key: &Pubkey,
+  escrow_info: &Escrow,
+) -> ProgramResult {
+  let mut page = load_order_book_page(
+    program_id,
+    page_account,
+    &escrow_info.offered_mint,
+    &escrow_info.requested_mint,
+  )?;
+  let position = page
+    .escrows
+    .iter()
+    .position(|key| key == escrow_key)
+    .ok_or(error::EscrowError::InvalidOrderBookPage)?;
+  page.escrows.swap_remove(position);
+  page.serialize(&mut &mut page_account.data.borrow_mut()[..])?;
+  Ok(())
+}
+
+/// Checks `token_account` holds `mint` for `owner`
+fn assert_party_token_account(
+  token_account: &AccountInfo,
+  owner: &Pubkey,
+  mint: &Pubkey,
+) -> ProgramResult {
+  let token_account_info: TokenAccount = TokenAccount::unpack(&token_account.data.borrow())?;
+  if token_account_info.owner != *owner || token_account_info.mint != *mint {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  Ok(())
+}
+
+/// Accounts of an escrow fill, shared by Exchange and each leg of ExchangeMany
+#[derive(Clone, Copy)]
+struct ExchangeAccounts<'a, 'b> {
+  taker: &'b AccountInfo<'a>,
+  taker_sending_token_account: &'b AccountInfo<'a>,
+  taker_receive_token_account: &'b AccountInfo<'a>,
+  pda_temp_token_account: &'b AccountInfo<'a>,
+  initializer_main_account: &'b AccountInfo<'a>,
+  initializer_receive_token_account: &'b AccountInfo<'a>,
+  escrow_account: &'b AccountInfo<'a>,
+  token_program: &'b AccountInfo<'a>,
+  pda_account: &'b AccountInfo<'a>,
+  clock_account: &'b AccountInfo<'a>,
+  system_program: &'b AccountInfo<'a>,
+  config_account: &'b AccountInfo<'a>,
+  requested_fee_recipient: &'b AccountInfo<'a>,
+  offered_fee_recipient: &'b AccountInfo<'a>,
+  metadata_account: &'b AccountInfo<'a>,
+  order_book_page_account: &'b AccountInfo<'a>,
+  offered_mint_account: &'b AccountInfo<'a>,
+}
+
+/// Config of the program, or None while the admin has not created it yet
+fn load_config(
+  program_id: &Pubkey,
+  config_account: &AccountInfo,
+) -> Result<Option<Config>, ProgramError> {
+  let (config_key, _) = find_config_address(program_id);
+  if *config_account.key != config_key {
+    return Err(error::EscrowError::InvalidConfigKey.into());
+  }
+  if config_account.data_is_empty() {
+    return Ok(None);
+  }
+  assert_owned_by(config_account, program_id)?;
+  Ok(Some(Config::from_account_info(config_account)?))
+}
+
+/// Royalty owed to each verified creator of an NFT sold for `payment`
+fn creator_royalties(
+  metadata: &Metadata,
+  payment: u64,
+) -> Result<Vec<(Pubkey, u64)>, ProgramError> {
+  let creators = match &metadata.data.creators {
+    Some(creators) => creators,
+    None => return Ok(vec![]),
+  };
+  creators
+    .iter()
+    .filter(|creator| creator.verified)
+    .map(|creator| {
+      // Share is in percentages while seller fee is in basis points
+      let royalty = (payment as u128)
+        .checked_mul(metadata.data.seller_fee_basis_points as u128)
+        .and_then(|n| n.checked_mul(creator.share as u128))
+        .and_then(|n| n.checked_div(10000 * 100))
+        .ok_or(error::EscrowError::AmountOverflow)?;
+      let royalty = u64::try_from(royalty).map_err(|_| error::EscrowError::AmountOverflow)?;
+      Ok((creator.address, royalty))
+    })
+    .collect()
+}
+
+/// Checks `mint_account` is `mint` and whether it is an NFT, a single indivisible token
+fn is_nft(mint_account: &AccountInfo, mint: &Pubkey) -> Result<bool, ProgramError> {
+  if *mint_account.key != *mint {
+    return Err(error::EscrowError::OfferedMintMismatch.into());
+  }
+  assert_owned_by(mint_account, &spl_token::id())?;
+  let mint_info = Mint::unpack(&mint_account.data.borrow())?;
+  Ok(mint_info.supply == 1 && mint_info.decimals == 0)
+}
+
+/// Metadata of the offered mint, or None if it is not an NFT of the configured metadata program
+fn load_offered_metadata(
+  config: &Option<Config>,
+  offered_mint: &Pubkey,
+  offered_mint_account: &AccountInfo,
+  metadata_account: &AccountInfo,
+) -> Result<Option<Metadata>, ProgramError> {
+  let token_metadata_program = match config.as_ref().and_then(|c| c.token_metadata_program) {
+    Some(token_metadata_program) => token_metadata_program,
+    None => return Ok(None),
+  };
+  // Fungible tokens can have metadata too, only NFT sales pay royalties
+  if !is_nft(offered_mint_account, offered_mint)? {
+    return Ok(None);
+  }
+  let (metadata_key, _) = find_metadata_address(&token_metadata_program, offered_mint);
+  if *metadata_account.key != metadata_key {
+    return Err(error::EscrowError::InvalidMetadataKey.into());
+  }
+  if metadata_account.data_is_empty() {
+    return Ok(None);
+  }
+  assert_owned_by(metadata_account, &token_metadata_program)?;
+  Ok(Some(Metadata::from_account_info(metadata_account)?))
+}
+
+/// Checks the previous instruction is the ed25519 program verifying the maker's signature of `order`
+fn assert_signed_by_maker(instructions_sysvar: &AccountInfo, order: &SignedOrder) -> ProgramResult {
+  if !sysvar::instructions::check_id(instructions_sysvar.key) {
+    return Err(ProgramError::InvalidAccountData);
+  }
+  let data = instructions_sysvar.data.borrow();
+  let current_index = sysvar::instructions::load_current_index(&data) as usize;
+  if current_index == 0 {
+    return Err(error::EscrowError::InvalidOrderSignature.into());
+  }
+  let verify_instruction = sysvar::instructions::load_instruction_at(current_index - 1, &data)
+    .map_err(|_| error::EscrowError::InvalidOrderSignature)?;
+  if verify_instruction.program_id != ed25519_program::id() {
+    return Err(error::EscrowError::InvalidOrderSignature.into());
+  }
+
+  // A single signature whose public key and message sit in the ed25519 instruction itself
+  let verify_data = &verify_instruction.data;
+  if verify_data.len() < 16 || verify_data[0] != 1 {
+    return Err(error::EscrowError::InvalidOrderSignature.into());
+  }
+  let read_offset = |at: usize| u16::from_le_bytes([verify_data[at], verify_data[at + 1]]);
+  if read_offset(4) != u16::MAX || read_offset(8) != u16::MAX || read_offset(14) != u16::MAX {
+    return Err(error::EscrowError::InvalidOrderSignature.into());
+  }
+  let public_key_offset = read_offset(6) as usize;
+  let message_offset = read_offset(10) as usize;
+  let message_size = read_offset(12) as usize;
+  let public_key = verify_data.get(public_key_offset..public_key_offset + 32);
+  let message = verify_data.get(message_offset..message_offset + message_size);
+  if public_key != Some(order.maker.as_ref()) || message != Some(&order.try_to_vec()?[..]) {
+    return Err(error::EscrowError::InvalidOrderSignature.into());
+  }
+  Ok(())
+}
+
+/// Nonce of the maker, creating its account on first use
+fn load_or_create_order_nonce<'a>(
+  program_id: &Pubkey,
+  maker: &Pubkey,
+  nonce_account: &AccountInfo<'a>,
+  rent_info: &AccountInfo<'a>,
+  system_program: &AccountInfo<'a>,
+  payer: &AccountInfo<'a>,
+) -> Result<OrderNonce, ProgramError> {
+  let (nonce_key, nonce_bump_seed) = find_order_nonce_address(program_id, maker);
+  if *nonce_account.key != nonce_key {
+    return Err(error::EscrowError::InvalidNonceKey.into());
+  }
+
+  if nonce_account.data_is_empty() {
+    create_or_allocate_account_raw(
+      *program_id,
+      nonce_account,
+      rent_info,
+      system_program,
+      payer,
+      ORDER_NONCE_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        maker.as_ref(),
+        NONCE.as_bytes(),
+        &[nonce_bump_seed],
+      ],
+    )?;
+    return Ok(OrderNonce {
+      key: Key::OrderNonceV1,
+      maker: *maker,
+      nonce: 0,
+      used: 0,
+    });
+  }
+
+  assert_owned_by(nonce_account, program_id)?;
+  OrderNonce::from_account_info(nonce_account)
+}
+
+/// Refund account of `bidder` in the auction, created empty on the bidder's first bid
+fn load_or_create_bid_refund<'a>(
+  program_id: &Pubkey,
+  auction: &Pubkey,
+  bidder: &AccountInfo<'a>,
+  refund_account: &AccountInfo<'a>,
+  rent_info: &AccountInfo<'a>,
+  system_program: &AccountInfo<'a>,
+) -> Result<BidRefund, ProgramError> {
+  let (refund_key, refund_bump_seed) = find_bid_refund_address(program_id, auction, bidder.key);
+  if *refund_account.key != refund_key {
+    return Err(error::EscrowError::InvalidBidRefundKey.into());
+  }
+
+  if refund_account.data_is_empty() {
+    create_or_allocate_account_raw(
+      *program_id,
+      refund_account,
+      rent_info,
+      system_program,
+      bidder,
+      BID_REFUND_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        auction.as_ref(),
+        REFUND.as_bytes(),
+        bidder.key.as_ref(),
+        &[refund_bump_seed],
+      ],
+    )?;
+    return Ok(BidRefund {
+      key: Key::BidRefundV1,
+      auction: *auction,
+      bidder: *bidder.key,
+      amount: 0,
+    });
+  }
+
+  load_bid_refund(program_id, auction, bidder.key, refund_account)
+}
+
+/// Refund account of `bidder` in the auction, checked against its seeds
+fn load_bid_refund(
+  program_id: &Pubkey,
+  auction: &Pubkey,
+  bidder: &Pubkey,
+  refund_account: &AccountInfo,
+) -> Result<BidRefund, ProgramError> {
+  let (refund_key, _) = find_bid_refund_address(program_id, auction, bidder);
+  if *refund_account.key != refund_key {
+    return Err(error::EscrowError::InvalidBidRefundKey.into());
+  }
+  assert_owned_by(refund_account, program_id)?;
+  BidRefund::from_account_info(refund_account)
+}
+
+fn assert_not_paused(config: &Option<Config>) -> ProgramResult {
+  if let Some(config) = config {
+    if config.paused {
+      return Err(error::EscrowError::ProgramPaused.into());
+    }
+  }
+  Ok(())
+}
+
+fn assert_valid_fee_basis_points(fee_basis_points: u16) -> ProgramResult {
+  if fee_basis_points > 10000 {
+    return Err(error::EscrowError::InvalidBasisPoints.into());
+  }
+  Ok(())
+}
+
 pub struct Processor;
 impl Processor {
   pub fn process(
@@ -51,133 +731,569 @@ impl Processor {
     match instruction {
       EscrowInstruction::InitEscrow(args) => {
         msg!("Instruction: Init Escrow");
-        Self::process_init_escrow(program_id, accounts, args.data.amount)
+        Self::process_init_escrow(
+          program_id,
+          accounts,
+          args.data.amount,
+          args.seed,
+          args.expires_at,
+          args.allowed_taker,
+          args.offered_kind,
+          args.requested_kind,
+          args.offered_amount,
+          args.dutch_pricing,
+        )
       }
       EscrowInstruction::Exchange(args) => {
         msg!("Instruction: Exchange Escrow");
-        Self::process_exchange(program_id, accounts, args.data.amount)
+        Self::process_exchange(program_id, accounts, args.data.amount, args.fill_amount)
+      }
+      EscrowInstruction::ExchangeMany(args) => {
+        msg!("Instruction: Exchange Many");
+        Self::process_exchange_many(program_id, accounts, args.legs)
       }
       EscrowInstruction::CancelEscrow() => {
         msg!("Instruction: Cancel Escrow");
         Self::process_cancel(program_id, accounts)
       }
+      EscrowInstruction::ExpireEscrow() => {
+        msg!("Instruction: Expire Escrow");
+        Self::process_expire(program_id, accounts)
+      }
+      EscrowInstruction::InitConfig(args) => {
+        msg!("Instruction: Init Config");
+        Self::process_init_config(program_id, accounts, args.fee_basis_points)
+      }
+      EscrowInstruction::UpdateConfig(args) => {
+        msg!("Instruction: Update Config");
+        Self::process_update_config(
+          program_id,
+          accounts,
+          args.admin,
+          args.fee_basis_points,
+          args.fee_recipients,
+          args.paused,
+          args.token_metadata_program,
+        )
+      }
+      EscrowInstruction::InitBasketEscrow(args) => {
+        msg!("Instruction: Init Basket Escrow");
+        Self::process_init_basket_escrow(program_id, accounts, args.seed, args.requested)
+      }
+      EscrowInstruction::ExchangeBasket() => {
+        msg!("Instruction: Exchange Basket");
+        Self::process_exchange_basket(program_id, accounts)
+      }
+      EscrowInstruction::CancelBasketEscrow() => {
+        msg!("Instruction: Cancel Basket Escrow");
+        Self::process_cancel_basket(program_id, accounts)
+      }
+      EscrowInstruction::FillSignedOrder(args) => {
+        msg!("Instruction: Fill Signed Order");
+        Self::process_fill_signed_order(program_id, accounts, args.order)
+      }
+      EscrowInstruction::SetOrderNonce(args) => {
+        msg!("Instruction: Set Order Nonce");
+        Self::process_set_order_nonce(program_id, accounts, args.nonce)
+      }
+      EscrowInstruction::InitArbitratedEscrow(args) => {
+        msg!("Instruction: Init Arbitrated Escrow");
+        Self::process_init_arbitrated_escrow(
+          program_id,
+          accounts,
+          args.seed,
+          args.seller,
+          args.arbiter,
+        )
+      }
+      EscrowInstruction::Release() => {
+        msg!("Instruction: Release");
+        Self::process_release(program_id, accounts)
+      }
+      EscrowInstruction::OpenDispute() => {
+        msg!("Instruction: Open Dispute");
+        Self::process_open_dispute(program_id, accounts)
+      }
+      EscrowInstruction::ResolveDispute(args) => {
+        msg!("Instruction: Resolve Dispute");
+        Self::process_resolve_dispute(program_id, accounts, args.buyer_amount)
+      }
+      EscrowInstruction::InitVestingEscrow(args) => {
+        msg!("Instruction: Init Vesting Escrow");
+        Self::process_init_vesting_escrow(
+          program_id,
+          accounts,
+          args.seed,
+          args.beneficiary,
+          args.revocation_authority,
+          args.schedule,
+        )
+      }
+      EscrowInstruction::Claim() => {
+        msg!("Instruction: Claim");
+        Self::process_claim(program_id, accounts)
+      }
+      EscrowInstruction::RevokeVesting() => {
+        msg!("Instruction: Revoke Vesting");
+        Self::process_revoke_vesting(program_id, accounts)
+      }
+      EscrowInstruction::InitHashLockedEscrow(args) => {
+        msg!("Instruction: Init Hash Locked Escrow");
+        Self::process_init_hash_locked_escrow(program_id, accounts, args)
+      }
+      EscrowInstruction::ClaimWithSecret(args) => {
+        msg!("Instruction: Claim With Secret");
+        Self::process_claim_with_secret(program_id, accounts, args.secret)
+      }
+      EscrowInstruction::Refund() => {
+        msg!("Instruction: Refund");
+        Self::process_refund(program_id, accounts)
+      }
+      EscrowInstruction::InitAuction(args) => {
+        msg!("Instruction: Init Auction");
+        Self::process_init_auction(program_id, accounts, args)
+      }
+      EscrowInstruction::PlaceBid(args) => {
+        msg!("Instruction: Place Bid");
+        Self::process_place_bid(program_id, accounts, args.amount)
+      }
+      EscrowInstruction::SettleAuction() => {
+        msg!("Instruction: Settle Auction");
+        Self::process_settle_auction(program_id, accounts)
+      }
+      EscrowInstruction::WithdrawBidRefund() => {
+        msg!("Instruction: Withdraw Bid Refund");
+        Self::process_withdraw_bid_refund(program_id, accounts)
+      }
+      EscrowInstruction::InitRingSwap(args) => {
+        msg!("Instruction: Init Ring Swap");
+        Self::process_init_ring_swap(program_id, accounts, args.seed, args.legs)
+      }
+      EscrowInstruction::DepositRing() => {
+        msg!("Instruction: Deposit Ring");
+        Self::process_deposit_ring(program_id, accounts)
+      }
+      EscrowInstruction::WithdrawRing() => {
+        msg!("Instruction: Withdraw Ring");
+        Self::process_withdraw_ring(program_id, accounts)
+      }
+      EscrowInstruction::SettleRing() => {
+        msg!("Instruction: Settle Ring");
+        Self::process_settle_ring(program_id, accounts)
+      }
+      EscrowInstruction::CloseOrderBookPage() => {
+        msg!("Instruction: Close Order Book Page");
+        Self::process_close_order_book_page(program_id, accounts)
+      }
+      EscrowInstruction::CloseRingSwap() => {
+        msg!("Instruction: Close Ring Swap");
+        Self::process_close_ring_swap(program_id, accounts)
+      }
+      EscrowInstruction::UpdateEscrow(args) => {
+        msg!("Instruction: Update Escrow");
+        Self::process_update_escrow(
+          program_id,
+          accounts,
+          args.expected_amount,
+          args.deposit_amount,
+          args.withdraw_amount,
+        )
+      }
     }
   }
 
+  #[allow(clippy::too_many_arguments)]
   pub fn process_init_escrow(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount: u64,
+    seed: u64,
+    expires_at: Option<UnixTimestamp>,
+    allowed_taker: Option<Pubkey>,
+    offered_kind: SideKind,
+    requested_kind: SideKind,
+    offered_amount: u64,
+    dutch_pricing: Option<DutchPricing>,
   ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let initializer = next_account_info(account_info_iter)?;
 
-    if !initializer.is_signer {
-      return Err(ProgramError::MissingRequiredSignature);
-    }
+    assert_signer(initializer)?;
 
     let temp_token_account = next_account_info(account_info_iter)?;
     let token_to_receive_account = next_account_info(account_info_iter)?;
+    let escrow_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let rent_info: &AccountInfo = next_account_info(account_info_iter)?;
+    let token_program: &AccountInfo = next_account_info(account_info_iter)?;
+    let system_program: &AccountInfo = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let order_book_page_account = next_account_info(account_info_iter)?;
+    let previous_order_book_page_account = next_account_info(account_info_iter)?;
 
-    // Make sure Token Account is owned by Token Program
-    assert_owned_by(token_to_receive_account, &spl_token::id())?;
+    assert_token_program(token_program)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
 
-    let escrow_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let expected_amount = match &dutch_pricing {
+      Some(dutch_pricing) if !dutch_pricing.is_valid() => {
+        return Err(error::EscrowError::InvalidDutchPricing.into())
+      }
+      Some(dutch_pricing) => dutch_pricing.start_price,
+      None => amount,
+    };
+
+    // Lamport sides have no token account, the native mint stands in for them
+    let (offered_mint, offered_amount) = match offered_kind {
+      SideKind::Token => {
+        assert_owned_by(temp_token_account, &spl_token::id())?;
+        let temp_token_account_info: TokenAccount =
+          TokenAccount::unpack(&temp_token_account.data.borrow())?;
+        (temp_token_account_info.mint, temp_token_account_info.amount)
+      }
+      SideKind::Lamports => (native_mint::id(), offered_amount),
+      SideKind::Delegate => {
+        assert_owned_by(temp_token_account, &spl_token::id())?;
+        let temp_token_account_info: TokenAccount =
+          TokenAccount::unpack(&temp_token_account.data.borrow())?;
+        // A token account has a single delegate, approving again would hijack another escrow's allowance
+        if temp_token_account_info.delegate.is_some() {
+          return Err(error::EscrowError::TokenAccountAlreadyDelegated.into());
+        }
+        (temp_token_account_info.mint, offered_amount)
+      }
+    };
+    // Prices are pro rata of the offered amount, nothing to divide by without it
+    if offered_amount == 0 {
+      return Err(error::EscrowError::EmptyOffer.into());
+    }
 
-    let rent: &Rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let requested_mint = match requested_kind {
+      SideKind::Token => {
+        // Make sure Token Account is owned by Token Program
+        assert_owned_by(token_to_receive_account, &spl_token::id())?;
+        let token_to_receive_account_info: TokenAccount =
+          TokenAccount::unpack(&token_to_receive_account.data.borrow())?;
+        token_to_receive_account_info.mint
+      }
+      SideKind::Lamports => native_mint::id(),
+      SideKind::Delegate => return Err(error::EscrowError::UnsupportedSideKind.into()),
+    };
+
+    // Escrow account is a PDA of the trade so clients can find it without tracking keypairs
+    let seed_bytes = seed.to_le_bytes();
+    let escrow_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      initializer.key.as_ref(),
+      offered_mint.as_ref(),
+      requested_mint.as_ref(),
+      &seed_bytes,
+    ];
+    let (escrow_key, escrow_bump_seed) = Pubkey::find_program_address(escrow_seeds, program_id);
+    if *escrow_account.key != escrow_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+    let escrow_signer_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      initializer.key.as_ref(),
+      offered_mint.as_ref(),
+      requested_mint.as_ref(),
+      &seed_bytes,
+      &[escrow_bump_seed],
+    ];
 
-    if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
-      return Err(error::EscrowError::NotRentExmpt.into());
+    // A second init would otherwise fail allocating the existing account
+    if !escrow_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
     }
 
+    create_or_allocate_account_raw(
+      *program_id,
+      escrow_account,
+      rent_info,
+      system_program,
+      initializer,
+      Escrow::LEN,
+      escrow_signer_seeds,
+    )?;
+
     let mut escrow_info: Escrow = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
     if escrow_info.is_initialized() {
       return Err(ProgramError::AccountAlreadyInitialized);
     }
 
+    // Each escrow gets its own vault authority so that one trade can never sign for another's vault
+    let vault_authority_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+    ];
+
+    // Program derived address for Cross Program Invocation
+    let (pda_key, bump_seed) = Pubkey::find_program_address(vault_authority_seeds, program_id);
+
     escrow_info.is_initialized = true;
     escrow_info.initializer_pubkey = *initializer.key;
-    escrow_info.temp_token_account_pubkey = *temp_token_account.key;
-    escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
-    escrow_info.expected_amount = amount;
+    escrow_info.temp_token_account_pubkey = match offered_kind {
+      SideKind::Token | SideKind::Delegate => *temp_token_account.key,
+      SideKind::Lamports => Pubkey::default(),
+    };
+    // Requested lamports are paid straight to the initializer's main account
+    escrow_info.initializer_token_to_receive_account_pubkey = match requested_kind {
+      SideKind::Lamports => *initializer.key,
+      _ => *token_to_receive_account.key,
+    };
+    escrow_info.expected_amount = expected_amount;
+    escrow_info.vault_authority_bump_seed = bump_seed;
+    escrow_info.seed = seed;
+    escrow_info.offered_mint = offered_mint;
+    escrow_info.requested_mint = requested_mint;
+    escrow_info.offered_amount = offered_amount;
+    escrow_info.filled_amount = 0;
+    escrow_info.expires_at = expires_at;
+    escrow_info.allowed_taker = allowed_taker;
+    escrow_info.offered_kind = offered_kind;
+    escrow_info.requested_kind = requested_kind;
+    escrow_info.dutch_pricing = dutch_pricing;
 
     Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
 
-    let escrow_seed = &["escrow".as_bytes(), program_id.as_ref()];
-
-    // Program derived address for Cross Program Invocation
-    let (pda_key, _bump_seed) = Pubkey::find_program_address(escrow_seed, program_id);
+    append_to_order_book(
+      program_id,
+      order_book_page_account,
+      previous_order_book_page_account,
+      escrow_account.key,
+      &offered_mint,
+      &requested_mint,
+      initializer,
+      rent_info,
+      system_program,
+    )?;
 
-    let token_program: &AccountInfo = next_account_info(account_info_iter)?;
+    match offered_kind {
+      SideKind::Token => {
+        // token_program_id: &Pubkey,
+        // owned_pubkey: &Pubkey,
+        // new_authority_pubkey: Option<&Pubkey>,
+        // authority_type: AuthorityType,
+        // owner_pubkey: &Pubkey,
+        // signer_pubkeys: &[&Pubkey])
 
-    // token_program_id: &Pubkey,
-    // owned_pubkey: &Pubkey,
-    // new_authority_pubkey: Option<&Pubkey>,
-    // authority_type: AuthorityType,
-    // owner_pubkey: &Pubkey,
-    // signer_pubkeys: &[&Pubkey])
+        let owner_change_instruction = instruction::set_authority(
+          token_program.key,
+          temp_token_account.key,
+          Some(&pda_key),
+          instruction::AuthorityType::AccountOwner,
+          initializer.key,
+          &[initializer.key],
+        )?;
 
-    let owner_change_instruction = instruction::set_authority(
-      token_program.key,
-      temp_token_account.key,
-      Some(&pda_key),
-      instruction::AuthorityType::AccountOwner,
-      initializer.key,
-      &[&initializer.key],
-    )?;
+        // Transfer temporary token account ownership to PDA
+        invoke(
+          &owner_change_instruction,
+          &[
+            temp_token_account.clone(),
+            initializer.clone(),
+            token_program.clone(),
+          ],
+        )?;
+      }
+      SideKind::Lamports => {
+        msg!("Deposit {} lamports", offered_amount);
+        // Escrow account holds offered lamports on top of its rent
+        invoke(
+          &system_instruction::transfer(initializer.key, escrow_account.key, offered_amount),
+          &[
+            initializer.clone(),
+            escrow_account.clone(),
+            system_program.clone(),
+          ],
+        )?;
+      }
+      SideKind::Delegate => {
+        assert_vault_authority(pda_account, &pda_key)?;
 
-    // Transfer temporary token account ownership to PDA
-    invoke(
-      &owner_change_instruction,
-      &[
-        temp_token_account.clone(),
-        initializer.clone(),
-        token_program.clone(),
-      ],
-    )?;
+        msg!("Approve {} to vault authority", offered_amount);
+        // Initializer keeps custody of the tokens until a taker pulls them through the delegation
+        let approve_instruction = instruction::approve(
+          token_program.key,
+          temp_token_account.key,
+          &pda_key,
+          initializer.key,
+          &[initializer.key],
+          offered_amount,
+        )?;
+        invoke(
+          &approve_instruction,
+          &[
+            temp_token_account.clone(),
+            pda_account.clone(),
+            initializer.clone(),
+            token_program.clone(),
+          ],
+        )?;
+      }
+    }
 
     Ok(())
   }
 
-  fn process_exchange(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+  fn process_exchange(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_payment: u64,
+    fill_amount: u64,
+  ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let taker: &AccountInfo = next_account_info(account_info_iter)?;
-    if !taker.is_signer {
-      return Err(ProgramError::MissingRequiredSignature);
-    }
+    assert_signer(taker)?;
 
     let taker_sending_token_account = next_account_info(account_info_iter)?;
     let taker_receive_token_account = next_account_info(account_info_iter)?;
-
     let pda_temp_token_account: &AccountInfo = next_account_info(account_info_iter)?;
-    let pda_temp_token_account_info: TokenAccount =
-      TokenAccount::unpack(&pda_temp_token_account.data.borrow())?;
+    let initializer_main_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let initializer_receive_token_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let escrow_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let requested_fee_recipient = next_account_info(account_info_iter)?;
+    let offered_fee_recipient = next_account_info(account_info_iter)?;
+    let metadata_account = next_account_info(account_info_iter)?;
+    let order_book_page_account = next_account_info(account_info_iter)?;
+    let offered_mint_account = next_account_info(account_info_iter)?;
 
-    let escrow_seed = &["escrow".as_bytes(), program_id.as_ref()];
+    let exchange_accounts = ExchangeAccounts {
+      taker,
+      taker_sending_token_account,
+      taker_receive_token_account,
+      pda_temp_token_account,
+      initializer_main_account,
+      initializer_receive_token_account,
+      escrow_account,
+      token_program,
+      pda_account,
+      clock_account,
+      system_program,
+      config_account,
+      requested_fee_recipient,
+      offered_fee_recipient,
+      metadata_account,
+      order_book_page_account,
+      offered_mint_account,
+    };
+    Self::exchange_escrow(
+      program_id,
+      &exchange_accounts,
+      account_info_iter,
+      max_payment,
+      fill_amount,
+    )
+  }
 
-    // Find a valid program address and its corresponding bump seed which must be passed as an additional seed when calling invoke_signed
-    let (pda_key, bump_seed) = Pubkey::find_program_address(escrow_seed, program_id);
+  fn process_exchange_many(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    legs: Vec<ExchangeArgs>,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+    assert_signer(taker)?;
 
-    msg!(
-      "amount {} pda_temp_amount {}",
-      amount,
-      pda_temp_token_account_info.amount
-    );
-    if amount != pda_temp_token_account_info.amount {
-      return Err(error::EscrowError::ExpectedAmountMismatch.into());
-    }
+    let taker_sending_token_account = next_account_info(account_info_iter)?;
+    let taker_receive_token_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let requested_fee_recipient = next_account_info(account_info_iter)?;
+    let offered_fee_recipient = next_account_info(account_info_iter)?;
 
-    let initializer_main_account: &AccountInfo = next_account_info(account_info_iter)?;
+    if legs.is_empty() {
+      return Err(error::EscrowError::InvalidInstruction.into());
+    }
 
-    let initializer_receive_token_account: &AccountInfo = next_account_info(account_info_iter)?;
+    // The taker accounts and fee recipients are shared, so every leg trades the first leg's pair
+    let mut mint_pair = None;
+    for (index, leg) in legs.into_iter().enumerate() {
+      msg!("Exchange leg {}", index);
+      let exchange_accounts = ExchangeAccounts {
+        taker,
+        taker_sending_token_account,
+        taker_receive_token_account,
+        pda_temp_token_account: next_account_info(account_info_iter)?,
+        initializer_main_account: next_account_info(account_info_iter)?,
+        initializer_receive_token_account: next_account_info(account_info_iter)?,
+        escrow_account: next_account_info(account_info_iter)?,
+        token_program,
+        pda_account: next_account_info(account_info_iter)?,
+        clock_account,
+        system_program,
+        config_account,
+        requested_fee_recipient,
+        offered_fee_recipient,
+        metadata_account: next_account_info(account_info_iter)?,
+        order_book_page_account: next_account_info(account_info_iter)?,
+        offered_mint_account: next_account_info(account_info_iter)?,
+      };
+      let escrow_info = load_escrow(program_id, exchange_accounts.escrow_account)?;
+      let leg_mint_pair = (
+        escrow_info.offered_mint,
+        escrow_info.requested_mint,
+        escrow_info.requested_kind,
+      );
+      if *mint_pair.get_or_insert(leg_mint_pair) != leg_mint_pair {
+        return Err(error::EscrowError::ExchangeLegsMixMints.into());
+      }
+      // Creator accounts of the leg are read right after its group
+      Self::exchange_escrow(
+        program_id,
+        &exchange_accounts,
+        account_info_iter,
+        leg.data.amount,
+        leg.fill_amount,
+      )?;
+    }
+    Ok(())
+  }
 
-    let escrow_account: &AccountInfo = next_account_info(account_info_iter)?;
+  /// Fills one escrow for the taker. Creator accounts are read from `account_info_iter`
+  fn exchange_escrow<'a, 'b>(
+    program_id: &Pubkey,
+    exchange_accounts: &ExchangeAccounts<'a, 'b>,
+    account_info_iter: &mut Iter<'b, AccountInfo<'a>>,
+    max_payment: u64,
+    fill_amount: u64,
+  ) -> ProgramResult {
+    let ExchangeAccounts {
+      taker,
+      taker_sending_token_account,
+      taker_receive_token_account,
+      pda_temp_token_account,
+      initializer_main_account,
+      initializer_receive_token_account,
+      escrow_account,
+      token_program,
+      pda_account,
+      clock_account,
+      system_program,
+      config_account,
+      requested_fee_recipient,
+      offered_fee_recipient,
+      metadata_account,
+      order_book_page_account,
+      offered_mint_account,
+    } = *exchange_accounts;
 
     msg!("unpacking escrow_info");
-    let escrow_info: Escrow = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
+    let mut escrow_info = load_escrow(program_id, escrow_account)?;
 
-    if escrow_info.temp_token_account_pubkey != *pda_temp_token_account.key {
-      return Err(ProgramError::InvalidAccountData);
+    // Private escrows can only be taken by the counterparty they were negotiated with
+    if !escrow_info.is_taker_allowed(taker.key) {
+      return Err(error::EscrowError::TakerNotAllowed.into());
     }
 
     if escrow_info.initializer_pubkey != *initializer_main_account.key {
@@ -190,125 +1306,484 @@ impl Processor {
       return Err(ProgramError::InvalidAccountData);
     }
 
-    let token_program = next_account_info(account_info_iter)?;
+    // The bump seed stored at init must be passed as an additional seed when calling invoke_signed
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
 
-    msg!("Start transfer");
-    let transfer_to_initializer_instruction = spl_token::instruction::transfer(
-      token_program.key,
-      taker_sending_token_account.key,
-      initializer_receive_token_account.key,
-      taker.key,
-      &[&taker.key],
-      escrow_info.expected_amount,
-    )?;
+    let clock: &Clock = &Clock::from_account_info(clock_account)?;
 
-    // transfer tokens to initializer's receive token account
-    invoke(
-      &transfer_to_initializer_instruction,
-      &[
-        taker_sending_token_account.clone(),
-        initializer_receive_token_account.clone(),
-        taker.clone(),
-        token_program.clone(),
-      ],
-    )?;
+    assert_token_program(token_program)?;
+    assert_vault_authority(pda_account, &pda_key)?;
 
-    let pda_account = next_account_info(account_info_iter)?;
+    let config = load_config(program_id, config_account)?;
+    assert_not_paused(&config)?;
 
-    let transfer_to_taker_instruction = spl_token::instruction::transfer(
-      token_program.key,
-      pda_temp_token_account.key,
-      taker_receive_token_account.key,
-      &pda_key,
-      &[&pda_key],
-      pda_temp_token_account_info.amount,
+    if escrow_info.is_expired(clock.unix_timestamp) {
+      return Err(error::EscrowError::EscrowExpired.into());
+    }
+
+    // Taker has to send the requested mint and receive the offered one. Only the initializer can
+    // revoke a delegation, so a delegate mode escrow stays open until its allowance is used up
+    let (available_amount, open_amount) = match escrow_info.offered_kind {
+      SideKind::Token | SideKind::Delegate => {
+        if escrow_info.temp_token_account_pubkey != *pda_temp_token_account.key {
+          return Err(ProgramError::InvalidAccountData);
+        }
+        let pda_temp_token_account_info: TokenAccount =
+          TokenAccount::unpack(&pda_temp_token_account.data.borrow())?;
+        let taker_receive_token_account_info: TokenAccount =
+          TokenAccount::unpack(&taker_receive_token_account.data.borrow())?;
+        if pda_temp_token_account_info.mint != escrow_info.offered_mint
+          || taker_receive_token_account_info.mint != escrow_info.offered_mint
+        {
+          return Err(error::EscrowError::OfferedMintMismatch.into());
+        }
+        if escrow_info.offered_kind == SideKind::Delegate {
+          (
+            delegated_amount(&pda_temp_token_account_info, &pda_key),
+            delegated_allowance(&pda_temp_token_account_info, &pda_key),
+          )
+        } else {
+          (
+            pda_temp_token_account_info.amount,
+            pda_temp_token_account_info.amount,
+          )
+        }
+      }
+      SideKind::Lamports => {
+        let escrowed_amount = escrowed_lamports(escrow_account)?;
+        (escrowed_amount, escrowed_amount)
+      }
+    };
+
+    if escrow_info.requested_kind != SideKind::Lamports {
+      let taker_sending_token_account_info: TokenAccount =
+        TokenAccount::unpack(&taker_sending_token_account.data.borrow())?;
+      if taker_sending_token_account_info.mint != escrow_info.requested_mint {
+        return Err(error::EscrowError::RequestedMintMismatch.into());
+      }
+
+      let initializer_receive_token_account_info: TokenAccount =
+        TokenAccount::unpack(&initializer_receive_token_account.data.borrow())?;
+      if initializer_receive_token_account_info.mint != escrow_info.requested_mint {
+        return Err(error::EscrowError::RequestedMintMismatch.into());
+      }
+    }
+
+    // A fill racing an earlier one fails here rather than on a stale vault balance,
+    // the payment cap below guards the price
+    if fill_amount == 0 || fill_amount > available_amount {
+      return Err(error::EscrowError::InvalidFillAmount.into());
+    }
+
+    let payment = escrow_info.payment_at(fill_amount, clock.unix_timestamp)?;
+    msg!("payment {} max_payment {}", payment, max_payment);
+    if payment > max_payment {
+      return Err(error::EscrowError::PaymentAboveMaximum.into());
+    }
+
+    let (requested_fee, offered_fee) = match &config {
+      Some(config) => (
+        config.fee_for(
+          escrow_info.requested_kind,
+          &escrow_info.requested_mint,
+          requested_fee_recipient.key,
+          payment,
+        )?,
+        config.fee_for(
+          escrow_info.offered_kind,
+          &escrow_info.offered_mint,
+          offered_fee_recipient.key,
+          fill_amount,
+        )?,
+      ),
+      None => (0, 0),
+    };
+
+    // Creators of an NFT get their royalty out of the initializer's proceeds
+    let royalties = match escrow_info.offered_kind {
+      SideKind::Token | SideKind::Delegate => {
+        match load_offered_metadata(
+          &config,
+          &escrow_info.offered_mint,
+          offered_mint_account,
+          metadata_account,
+        )? {
+          Some(metadata) => creator_royalties(&metadata, payment)?,
+          None => vec![],
+        }
+      }
+      SideKind::Lamports => vec![],
+    };
+    let initializer_proceeds = royalties
+      .iter()
+      .try_fold(payment - requested_fee, |proceeds, (_, royalty)| {
+        proceeds.checked_sub(*royalty)
+      })
+      .ok_or(error::EscrowError::FeesExceedPayment)?;
+
+    msg!("Start transfer of {} for {}", payment, fill_amount);
+    // transfer to initializer's receive account, minus the fee on the requested leg
+    transfer_from_taker(
+      escrow_info.requested_kind,
+      taker,
+      taker_sending_token_account,
+      initializer_receive_token_account,
+      token_program,
+      system_program,
+      initializer_proceeds,
+    )?;
+    transfer_from_taker(
+      escrow_info.requested_kind,
+      taker,
+      taker_sending_token_account,
+      requested_fee_recipient,
+      token_program,
+      system_program,
+      requested_fee,
     )?;
 
+    for (creator, royalty) in royalties {
+      let creator_account = next_account_info(account_info_iter)?;
+      match escrow_info.requested_kind {
+        SideKind::Token => {
+          let creator_account_info: TokenAccount =
+            TokenAccount::unpack(&creator_account.data.borrow())?;
+          if creator_account_info.owner != creator
+            || creator_account_info.mint != escrow_info.requested_mint
+          {
+            return Err(error::EscrowError::InvalidCreatorAccount.into());
+          }
+        }
+        _ => {
+          if *creator_account.key != creator {
+            return Err(error::EscrowError::InvalidCreatorAccount.into());
+          }
+        }
+      }
+      msg!("Pay royalty of {} to {}", royalty, creator);
+      transfer_from_taker(
+        escrow_info.requested_kind,
+        taker,
+        taker_sending_token_account,
+        creator_account,
+        token_program,
+        system_program,
+        royalty,
+      )?;
+    }
+
     msg!("Start transfer to taker");
+    // transfer to taker's receive account, minus the fee on the offered leg
+    transfer_from_vault(
+      escrow_info.offered_kind,
+      escrow_account,
+      pda_temp_token_account,
+      taker_receive_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      fill_amount - offered_fee,
+    )?;
+    transfer_from_vault(
+      escrow_info.offered_kind,
+      escrow_account,
+      pda_temp_token_account,
+      offered_fee_recipient,
+      pda_account,
+      token_program,
+      signers_seeds,
+      offered_fee,
+    )?;
+
+    let remaining_amount = open_amount - fill_amount;
+    if remaining_amount > 0 {
+      // Partial fill keeps the escrow open with what is left in the vault
+      escrow_info.filled_amount = escrow_info
+        .filled_amount
+        .checked_add(fill_amount)
+        .ok_or(error::EscrowError::AmountOverflow)?;
+      msg!("Escrow stays open with {}", remaining_amount);
+      Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+      return Ok(());
+    }
+
+    remove_from_order_book(
+      program_id,
+      order_book_page_account,
+      escrow_account.key,
+      &escrow_info,
+    )?;
+
+    if escrow_info.offered_kind == SideKind::Token {
+      msg!("Close Account");
+      close_vault(
+        pda_temp_token_account,
+        initializer_main_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+      )?;
+    }
+
+    // Finally closing escrow account
+    close_escrow_account(initializer_main_account, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let initializer_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let temp_token_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let escrow_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let order_book_page_account = next_account_info(account_info_iter)?;
+
+    msg!("Validate Accounts before cancelling");
+
+    // Lamport escrows have no token CPI requiring the initializer's signature
+    assert_signer(initializer_account)?;
+    assert_token_program(token_program)?;
+
+    let escrow_info = load_escrow(program_id, escrow_account)?;
+
+    if escrow_info.initializer_pubkey != *initializer_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    if escrow_info.offered_kind == SideKind::Lamports {
+      remove_from_order_book(
+        program_id,
+        order_book_page_account,
+        escrow_account.key,
+        &escrow_info,
+      )?;
+      msg!("Close Escrow Account");
+      // Offered lamports go back together with the rent
+      close_escrow_account(initializer_account, escrow_account)?;
+      return Ok(());
+    }
+
+    if escrow_info.temp_token_account_pubkey != *temp_token_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+    if temp_token_account_info.mint != escrow_info.offered_mint {
+      return Err(error::EscrowError::OfferedMintMismatch.into());
+    }
+
     // Signer seeds to let pda invoke program as pda does not own private key
-    let signers_seeds = &["escrow".as_bytes(), program_id.as_ref(), &[bump_seed]];
-    // transfer tokens to taker's receive token account
-    invoke_signed(
-      &transfer_to_taker_instruction,
-      &[
-        pda_temp_token_account.clone(),
-        taker_receive_token_account.clone(),
-        pda_account.clone(),
-        token_program.clone(),
-      ],
-      &[signers_seeds],
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    remove_from_order_book(
+      program_id,
+      order_book_page_account,
+      escrow_account.key,
+      &escrow_info,
     )?;
 
-    msg!("Close Account");
-    let close_pda_temp_account_instruction = spl_token::instruction::close_account(
+    if escrow_info.offered_kind == SideKind::Delegate {
+      // Tokens never left the initializer, only the delegation has to go, even once it can't pull anything
+      if temp_token_account_info.delegate == COption::Some(pda_key) {
+        revoke_delegation(temp_token_account, initializer_account, token_program)?;
+      }
+      msg!("Close Escrow Account");
+      close_escrow_account(initializer_account, escrow_account)?;
+      return Ok(());
+    }
+
+    msg!(
+      "Transfer back to Initializer {}",
+      temp_token_account_info.amount
+    );
+
+    msg!("Change temp account owner to initializer");
+    let owner_change_instruction = instruction::set_authority(
       token_program.key,
-      pda_temp_token_account.key,
-      initializer_main_account.key,
+      temp_token_account.key,
+      Some(&initializer_account.key),
+      instruction::AuthorityType::AccountOwner,
       &pda_key,
       &[&pda_key],
     )?;
-    // close pda temp account
     invoke_signed(
-      &close_pda_temp_account_instruction,
+      &owner_change_instruction,
       &[
-        pda_temp_token_account.clone(),
-        initializer_main_account.clone(),
+        temp_token_account.clone(),
+        initializer_account.clone(),
         pda_account.clone(),
         token_program.clone(),
       ],
       &[signers_seeds],
     )?;
 
-    // Finally closing escrow account
-    close_escrow_account(&initializer_main_account, &escrow_account)?;
+    // Token program refuses to close an account still holding tokens, the initializer keeps it then
+    if temp_token_account_info.is_native() || temp_token_account_info.amount == 0 {
+      msg!("Close Temp Token Account");
+      let close_temp_account_instruction = spl_token::instruction::close_account(
+        token_program.key,
+        temp_token_account.key,
+        initializer_account.key,
+        &initializer_account.key,
+        &[&initializer_account.key],
+      )?;
+
+      invoke(
+        &close_temp_account_instruction,
+        &[
+          temp_token_account.clone(),
+          initializer_account.clone(),
+          token_program.clone(),
+        ],
+      )?;
+    }
+
+    msg!("Close Escrow Account");
+    close_escrow_account(&initializer_account, &escrow_account)?;
     Ok(())
   }
 
-  fn process_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+  fn process_expire(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let initializer_account: &AccountInfo = next_account_info(account_info_iter)?;
+    let initializer_refund_token_account: &AccountInfo = next_account_info(account_info_iter)?;
     let temp_token_account: &AccountInfo = next_account_info(account_info_iter)?;
-    let temp_token_account_info: TokenAccount =
-      TokenAccount::unpack(&temp_token_account.data.borrow())?;
     let escrow_account: &AccountInfo = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let order_book_page_account = next_account_info(account_info_iter)?;
 
-    msg!("Validate Accounts before cancelling");
+    msg!("Validate Accounts before expiring");
+
+    assert_token_program(token_program)?;
+    let escrow_info = load_escrow(program_id, escrow_account)?;
+
+    if !escrow_info.is_expired(clock.unix_timestamp) {
+      return Err(error::EscrowError::EscrowNotExpired.into());
+    }
+
+    if escrow_info.initializer_pubkey != *initializer_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
 
-    let escrow_info: Escrow = Escrow::unpack_unchecked(&escrow_account.data.borrow())?;
+    if escrow_info.offered_kind == SideKind::Lamports {
+      remove_from_order_book(
+        program_id,
+        order_book_page_account,
+        escrow_account.key,
+        &escrow_info,
+      )?;
+      msg!("Close Escrow Account");
+      close_escrow_account(initializer_account, escrow_account)?;
+      return Ok(());
+    }
 
     if escrow_info.temp_token_account_pubkey != *temp_token_account.key {
       return Err(ProgramError::InvalidAccountData);
     }
 
-    if escrow_info.initializer_pubkey != *initializer_account.key {
+    let initializer_refund_token_account_info: TokenAccount =
+      TokenAccount::unpack(&initializer_refund_token_account.data.borrow())?;
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+
+    // Anyone can send this, so tokens may only go back to an account the initializer owns
+    if initializer_refund_token_account_info.owner != escrow_info.initializer_pubkey {
       return Err(ProgramError::InvalidAccountData);
     }
+    // A delegate transferring to the delegating account itself would keep the allowance
+    if escrow_info.offered_kind == SideKind::Delegate
+      && *initializer_refund_token_account.key == *temp_token_account.key
+    {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    if temp_token_account_info.mint != escrow_info.offered_mint
+      || initializer_refund_token_account_info.mint != escrow_info.offered_mint
+    {
+      return Err(error::EscrowError::OfferedMintMismatch.into());
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    remove_from_order_book(
+      program_id,
+      order_book_page_account,
+      escrow_account.key,
+      &escrow_info,
+    )?;
+
+    if escrow_info.offered_kind == SideKind::Delegate {
+      if initializer_account.is_signer {
+        if temp_token_account_info.delegate == COption::Some(pda_key) {
+          revoke_delegation(temp_token_account, initializer_account, token_program)?;
+        }
+        msg!("Close Escrow Account");
+        close_escrow_account(initializer_account, escrow_account)?;
+        return Ok(());
+      }
+      // Only the initializer can revoke, but using up the allowance clears the delegate as well.
+      // An allowance above the balance can't be used up and would outlive the escrow
+      let remaining_amount = delegated_amount(&temp_token_account_info, &pda_key);
+      if delegated_allowance(&temp_token_account_info, &pda_key) > remaining_amount {
+        return Err(error::EscrowError::AllowanceAboveBalance.into());
+      }
+      msg!("Use up the delegation of {}", remaining_amount);
+      transfer_from_vault(
+        SideKind::Delegate,
+        escrow_account,
+        temp_token_account,
+        initializer_refund_token_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+        remaining_amount,
+      )?;
+      msg!("Close Escrow Account");
+      close_escrow_account(initializer_account, escrow_account)?;
+      return Ok(());
+    }
 
     msg!(
       "Transfer back to Initializer {}",
       temp_token_account_info.amount
     );
-    let escrow_seed = &["escrow".as_bytes(), program_id.as_ref()];
-
-    let (pda_key, bump_seed) = Pubkey::find_program_address(escrow_seed, program_id);
-    let signers_seeds = &["escrow".as_bytes(), program_id.as_ref(), &[bump_seed]];
-
-    msg!("Change temp account owner to initializer");
-    let owner_change_instruction = instruction::set_authority(
+    let transfer_to_initializer_instruction = spl_token::instruction::transfer(
       token_program.key,
       temp_token_account.key,
-      Some(&initializer_account.key),
-      instruction::AuthorityType::AccountOwner,
+      initializer_refund_token_account.key,
       &pda_key,
       &[&pda_key],
+      temp_token_account_info.amount,
     )?;
     invoke_signed(
-      &owner_change_instruction,
+      &transfer_to_initializer_instruction,
       &[
         temp_token_account.clone(),
-        initializer_account.clone(),
+        initializer_refund_token_account.clone(),
         pda_account.clone(),
         token_program.clone(),
       ],
@@ -320,19 +1795,2304 @@ impl Processor {
       token_program.key,
       temp_token_account.key,
       initializer_account.key,
-      &initializer_account.key,
-      &[&initializer_account.key],
+      &pda_key,
+      &[&pda_key],
     )?;
-
-    invoke(
+    invoke_signed(
       &close_temp_account_instruction,
       &[
         temp_token_account.clone(),
         initializer_account.clone(),
+        pda_account.clone(),
         token_program.clone(),
       ],
+      &[signers_seeds],
     )?;
+
     close_escrow_account(&initializer_account, &escrow_account)?;
     Ok(())
   }
+
+  fn process_init_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee_basis_points: u16,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    assert_signer(admin)?;
+
+    assert_valid_fee_basis_points(fee_basis_points)?;
+
+    let (config_key, config_bump_seed) = find_config_address(program_id);
+    if *config_account.key != config_key {
+      return Err(error::EscrowError::InvalidConfigKey.into());
+    }
+
+    if !config_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_or_allocate_account_raw(
+      *program_id,
+      config_account,
+      rent_info,
+      system_program,
+      admin,
+      MAX_CONFIG_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        CONFIG.as_bytes(),
+        &[config_bump_seed],
+      ],
+    )?;
+
+    let config = Config {
+      key: Key::ConfigV1,
+      admin: *admin.key,
+      fee_basis_points,
+      paused: false,
+      token_metadata_program: None,
+      fee_recipients: vec![],
+    };
+    config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_admin: Option<Pubkey>,
+    fee_basis_points: Option<u16>,
+    fee_recipients: Option<Vec<FeeRecipient>>,
+    paused: Option<bool>,
+    token_metadata_program: Option<Option<Pubkey>>,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    let mut config = match load_config(program_id, config_account)? {
+      Some(config) => config,
+      None => return Err(ProgramError::UninitializedAccount),
+    };
+
+    assert_signer(admin)?;
+    if config.admin != *admin.key {
+      return Err(error::EscrowError::AdminMismatch.into());
+    }
+
+    if let Some(new_admin) = new_admin {
+      config.admin = new_admin;
+    }
+
+    if let Some(fee_basis_points) = fee_basis_points {
+      assert_valid_fee_basis_points(fee_basis_points)?;
+      config.fee_basis_points = fee_basis_points;
+    }
+
+    if let Some(fee_recipients) = fee_recipients {
+      if fee_recipients.len() > MAX_FEE_RECIPIENTS {
+        return Err(error::EscrowError::TooManyFeeRecipients.into());
+      }
+      if fee_recipients
+        .iter()
+        .any(|fee_recipient| fee_recipient.kind == SideKind::Delegate)
+      {
+        return Err(error::EscrowError::UnsupportedSideKind.into());
+      }
+      config.fee_recipients = fee_recipients;
+    }
+
+    if let Some(paused) = paused {
+      msg!("Set paused to {}", paused);
+      config.paused = paused;
+    }
+
+    if let Some(token_metadata_program) = token_metadata_program {
+      config.token_metadata_program = token_metadata_program;
+    }
+
+    config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_init_basket_escrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seed: u64,
+    requested: Vec<RequestedLeg>,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let initializer = next_account_info(account_info_iter)?;
+
+    assert_signer(initializer)?;
+
+    let basket_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    assert_token_program(token_program)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
+
+    // Receiving accounts of the requested legs come first, the rest are offered vaults
+    let leg_accounts = account_info_iter.as_slice();
+    if requested.is_empty()
+      || requested.len() > MAX_BASKET_LEGS
+      || leg_accounts.len() <= requested.len()
+      || leg_accounts.len() - requested.len() > MAX_BASKET_LEGS
+    {
+      return Err(error::EscrowError::InvalidBasketLegs.into());
+    }
+    let (receive_accounts, vault_accounts) = leg_accounts.split_at(requested.len());
+
+    let seed_bytes = seed.to_le_bytes();
+    let basket_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      initializer.key.as_ref(),
+      BASKET.as_bytes(),
+      &seed_bytes,
+    ];
+    let (basket_key, basket_bump_seed) = Pubkey::find_program_address(basket_seeds, program_id);
+    if *basket_account.key != basket_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+
+    if !basket_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_or_allocate_account_raw(
+      *program_id,
+      basket_account,
+      rent_info,
+      system_program,
+      initializer,
+      BasketEscrow::space(vault_accounts.len(), requested.len()),
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        initializer.key.as_ref(),
+        BASKET.as_bytes(),
+        &seed_bytes,
+        &[basket_bump_seed],
+      ],
+    )?;
+
+    let mut requested_legs = Vec::with_capacity(requested.len());
+    for (leg, receive_account) in requested.iter().zip(receive_accounts) {
+      if leg.amount == 0 {
+        return Err(error::EscrowError::InvalidBasketLegs.into());
+      }
+      // Requested lamports are paid straight to the initializer's main account
+      if leg.mint == native_mint::id() {
+        if receive_account.key != initializer.key {
+          return Err(ProgramError::InvalidAccountData);
+        }
+      } else {
+        assert_owned_by(receive_account, &spl_token::id())?;
+        let receive_account_info: TokenAccount =
+          TokenAccount::unpack(&receive_account.data.borrow())?;
+        if receive_account_info.mint != leg.mint {
+          return Err(error::EscrowError::RequestedMintMismatch.into());
+        }
+      }
+      requested_legs.push(BasketLeg {
+        mint: leg.mint,
+        amount: leg.amount,
+        account: *receive_account.key,
+      });
+    }
+
+    // All vaults of a basket share its vault authority
+    let (pda_key, bump_seed) = find_vault_authority(program_id, basket_account.key);
+
+    let mut offered_legs = Vec::with_capacity(vault_accounts.len());
+    for vault_account in vault_accounts {
+      let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+      if vault_account_info.amount == 0 {
+        return Err(error::EscrowError::InvalidBasketLegs.into());
+      }
+      offered_legs.push(BasketLeg {
+        mint: vault_account_info.mint,
+        amount: vault_account_info.amount,
+        account: *vault_account.key,
+      });
+
+      let owner_change_instruction = instruction::set_authority(
+        token_program.key,
+        vault_account.key,
+        Some(&pda_key),
+        instruction::AuthorityType::AccountOwner,
+        initializer.key,
+        &[initializer.key],
+      )?;
+
+      // Transfer temporary token account ownership to PDA
+      invoke(
+        &owner_change_instruction,
+        &[
+          vault_account.clone(),
+          initializer.clone(),
+          token_program.clone(),
+        ],
+      )?;
+    }
+
+    let basket = BasketEscrow {
+      key: Key::BasketEscrowV1,
+      initializer: *initializer.key,
+      seed,
+      vault_authority_bump_seed: bump_seed,
+      offered: offered_legs,
+      requested: requested_legs,
+    };
+    basket.serialize(&mut &mut basket_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_exchange_basket(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+    assert_signer(taker)?;
+
+    let initializer_main_account = next_account_info(account_info_iter)?;
+    let basket_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    assert_token_program(token_program)?;
+    assert_owned_by(basket_account, program_id)?;
+    let basket = BasketEscrow::from_account_info(basket_account)?;
+
+    if basket.initializer != *initializer_main_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let config = load_config(program_id, config_account)?;
+    assert_not_paused(&config)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      basket_account.key.as_ref(),
+      &[basket.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    // Any failing leg fails the whole transaction, so the basket settles completely or not at all.
+    // Royalties are left out, there is no price of a single offered leg to take them from
+    for leg in basket.requested.iter() {
+      let taker_sending_account = next_account_info(account_info_iter)?;
+      let initializer_receive_account = next_account_info(account_info_iter)?;
+      let fee_recipient = next_account_info(account_info_iter)?;
+
+      if leg.account != *initializer_receive_account.key {
+        return Err(ProgramError::InvalidAccountData);
+      }
+
+      let requested_kind = if leg.mint == native_mint::id() {
+        SideKind::Lamports
+      } else {
+        let taker_sending_account_info: TokenAccount =
+          TokenAccount::unpack(&taker_sending_account.data.borrow())?;
+        if taker_sending_account_info.mint != leg.mint {
+          return Err(error::EscrowError::RequestedMintMismatch.into());
+        }
+        SideKind::Token
+      };
+
+      let fee = match &config {
+        Some(config) => config.fee_for(requested_kind, &leg.mint, fee_recipient.key, leg.amount)?,
+        None => 0,
+      };
+
+      msg!("Transfer {} of {} to initializer", leg.amount, leg.mint);
+      transfer_from_taker(
+        requested_kind,
+        taker,
+        taker_sending_account,
+        initializer_receive_account,
+        token_program,
+        system_program,
+        leg.amount - fee,
+      )?;
+      transfer_from_taker(
+        requested_kind,
+        taker,
+        taker_sending_account,
+        fee_recipient,
+        token_program,
+        system_program,
+        fee,
+      )?;
+    }
+
+    for leg in basket.offered.iter() {
+      let vault_account = next_account_info(account_info_iter)?;
+      let taker_receive_account = next_account_info(account_info_iter)?;
+      let fee_recipient = next_account_info(account_info_iter)?;
+
+      if leg.account != *vault_account.key {
+        return Err(ProgramError::InvalidAccountData);
+      }
+
+      let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+      let taker_receive_account_info: TokenAccount =
+        TokenAccount::unpack(&taker_receive_account.data.borrow())?;
+      if taker_receive_account_info.mint != leg.mint {
+        return Err(error::EscrowError::OfferedMintMismatch.into());
+      }
+
+      // Whole vault goes to the taker so that it can be closed
+      let amount = vault_account_info.amount;
+      let fee = match &config {
+        Some(config) => config.fee_for(SideKind::Token, &leg.mint, fee_recipient.key, amount)?,
+        None => 0,
+      };
+
+      msg!("Transfer {} of {} to taker", amount, leg.mint);
+      transfer_from_vault(
+        SideKind::Token,
+        basket_account,
+        vault_account,
+        taker_receive_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+        amount - fee,
+      )?;
+      transfer_from_vault(
+        SideKind::Token,
+        basket_account,
+        vault_account,
+        fee_recipient,
+        pda_account,
+        token_program,
+        signers_seeds,
+        fee,
+      )?;
+
+      close_vault(
+        vault_account,
+        initializer_main_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+      )?;
+    }
+
+    msg!("Close Basket Escrow Account");
+    close_escrow_account(initializer_main_account, basket_account)?;
+    Ok(())
+  }
+
+  fn process_cancel_basket(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let initializer_account = next_account_info(account_info_iter)?;
+    let basket_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+
+    assert_signer(initializer_account)?;
+    assert_token_program(token_program)?;
+
+    assert_owned_by(basket_account, program_id)?;
+    let basket = BasketEscrow::from_account_info(basket_account)?;
+
+    if basket.initializer != *initializer_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      basket_account.key.as_ref(),
+      &[basket.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    for leg in basket.offered.iter() {
+      let vault_account = next_account_info(account_info_iter)?;
+      if leg.account != *vault_account.key {
+        return Err(ProgramError::InvalidAccountData);
+      }
+
+      msg!("Change vault owner to initializer");
+      let owner_change_instruction = instruction::set_authority(
+        token_program.key,
+        vault_account.key,
+        Some(initializer_account.key),
+        instruction::AuthorityType::AccountOwner,
+        &pda_key,
+        &[&pda_key],
+      )?;
+      invoke_signed(
+        &owner_change_instruction,
+        &[
+          vault_account.clone(),
+          initializer_account.clone(),
+          pda_account.clone(),
+          token_program.clone(),
+        ],
+        &[signers_seeds],
+      )?;
+    }
+
+    msg!("Close Basket Escrow Account");
+    close_escrow_account(initializer_account, basket_account)?;
+    Ok(())
+  }
+
+  fn process_fill_signed_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    order: SignedOrder,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+    assert_signer(taker)?;
+
+    let taker_sending_token_account = next_account_info(account_info_iter)?;
+    let taker_receive_token_account = next_account_info(account_info_iter)?;
+    let maker_sending_token_account = next_account_info(account_info_iter)?;
+    let maker_receive_token_account = next_account_info(account_info_iter)?;
+    let nonce_account = next_account_info(account_info_iter)?;
+    let order_delegate = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let requested_fee_recipient = next_account_info(account_info_iter)?;
+    let offered_fee_recipient = next_account_info(account_info_iter)?;
+
+    assert_token_program(token_program)?;
+
+    let config = load_config(program_id, config_account)?;
+    assert_not_paused(&config)?;
+
+    if order.program_id != *program_id {
+      return Err(error::EscrowError::OrderForAnotherProgram.into());
+    }
+    if clock.unix_timestamp >= order.expires_at {
+      return Err(error::EscrowError::EscrowExpired.into());
+    }
+
+    assert_signed_by_maker(instructions_sysvar, &order)?;
+
+    // Each nonce fills a single order, so it can never be replayed
+    let mut order_nonce = load_or_create_order_nonce(
+      program_id,
+      &order.maker,
+      nonce_account,
+      rent_info,
+      system_program,
+      taker,
+    )?;
+    order_nonce.use_nonce(order.nonce)?;
+    order_nonce.serialize(&mut &mut nonce_account.data.borrow_mut()[..])?;
+
+    // The order delegate is shared by every maker, so their accounts must really be the maker's
+    let maker_sending_token_account_info: TokenAccount =
+      TokenAccount::unpack(&maker_sending_token_account.data.borrow())?;
+    let maker_receive_token_account_info: TokenAccount =
+      TokenAccount::unpack(&maker_receive_token_account.data.borrow())?;
+    if maker_sending_token_account_info.owner != order.maker
+      || maker_receive_token_account_info.owner != order.maker
+    {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let taker_sending_token_account_info: TokenAccount =
+      TokenAccount::unpack(&taker_sending_token_account.data.borrow())?;
+    let taker_receive_token_account_info: TokenAccount =
+      TokenAccount::unpack(&taker_receive_token_account.data.borrow())?;
+    if maker_sending_token_account_info.mint != order.offered_mint
+      || taker_receive_token_account_info.mint != order.offered_mint
+    {
+      return Err(error::EscrowError::OfferedMintMismatch.into());
+    }
+    if maker_receive_token_account_info.mint != order.requested_mint
+      || taker_sending_token_account_info.mint != order.requested_mint
+    {
+      return Err(error::EscrowError::RequestedMintMismatch.into());
+    }
+
+    let (order_delegate_key, order_delegate_bump_seed) = find_order_delegate(program_id);
+    assert_vault_authority(order_delegate, &order_delegate_key)?;
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      ORDER.as_bytes(),
+      &[order_delegate_bump_seed],
+    ];
+
+    let (requested_fee, offered_fee) = match &config {
+      Some(config) => (
+        config.fee_for(
+          SideKind::Token,
+          &order.requested_mint,
+          requested_fee_recipient.key,
+          order.requested_amount,
+        )?,
+        config.fee_for(
+          SideKind::Delegate,
+          &order.offered_mint,
+          offered_fee_recipient.key,
+          order.offered_amount,
+        )?,
+      ),
+      None => (0, 0),
+    };
+
+    msg!(
+      "Fill order {} of {} for {}",
+      order.nonce,
+      order.offered_amount,
+      order.requested_amount
+    );
+    transfer_from_taker(
+      SideKind::Token,
+      taker,
+      taker_sending_token_account,
+      maker_receive_token_account,
+      token_program,
+      system_program,
+      order.requested_amount - requested_fee,
+    )?;
+    transfer_from_taker(
+      SideKind::Token,
+      taker,
+      taker_sending_token_account,
+      requested_fee_recipient,
+      token_program,
+      system_program,
+      requested_fee,
+    )?;
+
+    transfer_from_vault(
+      SideKind::Delegate,
+      nonce_account,
+      maker_sending_token_account,
+      taker_receive_token_account,
+      order_delegate,
+      token_program,
+      signers_seeds,
+      order.offered_amount - offered_fee,
+    )?;
+    transfer_from_vault(
+      SideKind::Delegate,
+      nonce_account,
+      maker_sending_token_account,
+      offered_fee_recipient,
+      order_delegate,
+      token_program,
+      signers_seeds,
+      offered_fee,
+    )?;
+    Ok(())
+  }
+
+  fn process_set_order_nonce(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    nonce: u64,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+    let nonce_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    assert_signer(maker)?;
+
+    let mut order_nonce = load_or_create_order_nonce(
+      program_id,
+      maker.key,
+      nonce_account,
+      rent_info,
+      system_program,
+      maker,
+    )?;
+    if nonce <= order_nonce.nonce {
+      return Err(error::EscrowError::OrderNonceUsed.into());
+    }
+
+    msg!("Set order nonce to {}", nonce);
+    order_nonce.cancel_below(nonce);
+    order_nonce.serialize(&mut &mut nonce_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_update_escrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    expected_amount: Option<u64>,
+    deposit_amount: u64,
+    withdraw_amount: u64,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let initializer = next_account_info(account_info_iter)?;
+    let initializer_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let token_to_receive_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    assert_signer(initializer)?;
+    assert_token_program(token_program)?;
+
+    let mut escrow_info = load_escrow(program_id, escrow_account)?;
+
+    if escrow_info.initializer_pubkey != *initializer.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    let available_amount = match escrow_info.offered_kind {
+      SideKind::Token | SideKind::Delegate => {
+        if escrow_info.temp_token_account_pubkey != *vault_account.key {
+          return Err(ProgramError::InvalidAccountData);
+        }
+        let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+        if escrow_info.offered_kind == SideKind::Delegate {
+          delegated_amount(&vault_account_info, &pda_key)
+        } else {
+          vault_account_info.amount
+        }
+      }
+      SideKind::Lamports => escrowed_lamports(escrow_account)?,
+    };
+
+    let new_available_amount = available_amount
+      .checked_add(deposit_amount)
+      .and_then(|n| n.checked_sub(withdraw_amount))
+      .ok_or(error::EscrowError::InvalidUpdateAmount)?;
+    if new_available_amount == 0 {
+      return Err(error::EscrowError::InvalidUpdateAmount.into());
+    }
+
+    // A new price is the price of what is left and ends the Dutch pricing. Otherwise the ratio stays
+    // as it is, rebasing it on the new amount would round it
+    if let Some(expected_amount) = expected_amount {
+      escrow_info.expected_amount = expected_amount;
+      escrow_info.offered_amount = new_available_amount;
+      escrow_info.dutch_pricing = None;
+    }
+
+    if escrow_info.requested_kind == SideKind::Lamports {
+      if *token_to_receive_account.key != *initializer.key {
+        return Err(ProgramError::InvalidAccountData);
+      }
+    } else {
+      let token_to_receive_account_info: TokenAccount =
+        TokenAccount::unpack(&token_to_receive_account.data.borrow())?;
+      if token_to_receive_account_info.mint != escrow_info.requested_mint {
+        return Err(error::EscrowError::RequestedMintMismatch.into());
+      }
+      escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
+    }
+
+    match escrow_info.offered_kind {
+      SideKind::Token => {
+        if deposit_amount > 0 || withdraw_amount > 0 {
+          let initializer_token_account_info: TokenAccount =
+            TokenAccount::unpack(&initializer_token_account.data.borrow())?;
+          if initializer_token_account_info.mint != escrow_info.offered_mint {
+            return Err(error::EscrowError::OfferedMintMismatch.into());
+          }
+        }
+        if deposit_amount > 0 {
+          msg!("Top up vault with {}", deposit_amount);
+          transfer_from_taker(
+            SideKind::Token,
+            initializer,
+            initializer_token_account,
+            vault_account,
+            token_program,
+            system_program,
+            deposit_amount,
+          )?;
+        }
+        msg!("Withdraw {} from vault", withdraw_amount);
+        transfer_from_vault(
+          SideKind::Token,
+          escrow_account,
+          vault_account,
+          initializer_token_account,
+          pda_account,
+          token_program,
+          signers_seeds,
+          withdraw_amount,
+        )?;
+      }
+      SideKind::Lamports => {
+        msg!("Top up escrow with {} lamports", deposit_amount);
+        transfer_from_taker(
+          SideKind::Lamports,
+          initializer,
+          initializer_token_account,
+          escrow_account,
+          token_program,
+          system_program,
+          deposit_amount,
+        )?;
+        msg!("Withdraw {} lamports from escrow", withdraw_amount);
+        transfer_from_vault(
+          SideKind::Lamports,
+          escrow_account,
+          vault_account,
+          initializer,
+          pda_account,
+          token_program,
+          signers_seeds,
+          withdraw_amount,
+        )?;
+      }
+      SideKind::Delegate => {
+        msg!("Approve {} to vault authority", new_available_amount);
+        // Tokens never left the initializer, only the delegated amount changes
+        let approve_instruction = instruction::approve(
+          token_program.key,
+          vault_account.key,
+          &pda_key,
+          initializer.key,
+          &[initializer.key],
+          new_available_amount,
+        )?;
+        invoke(
+          &approve_instruction,
+          &[
+            vault_account.clone(),
+            pda_account.clone(),
+            initializer.clone(),
+            token_program.clone(),
+          ],
+        )?;
+      }
+    }
+
+    Escrow::pack(escrow_info, &mut escrow_account.data.borrow_mut())?;
+    Ok(())
+  }
+
+  fn process_init_arbitrated_escrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seed: u64,
+    seller: Pubkey,
+    arbiter: Pubkey,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let buyer = next_account_info(account_info_iter)?;
+    let temp_token_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    assert_signer(buyer)?;
+    assert_token_program(token_program)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
+
+    // A party acting as arbiter could settle any dispute in its own favour
+    if arbiter == *buyer.key || arbiter == seller {
+      return Err(error::EscrowError::ArbiterIsParty.into());
+    }
+
+    assert_owned_by(temp_token_account, &spl_token::id())?;
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+
+    let (escrow_key, escrow_bump_seed) =
+      find_arbitrated_escrow_address(program_id, buyer.key, seed);
+    if *escrow_account.key != escrow_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+
+    if !escrow_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_or_allocate_account_raw(
+      *program_id,
+      escrow_account,
+      rent_info,
+      system_program,
+      buyer,
+      ARBITRATED_ESCROW_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        buyer.key.as_ref(),
+        ARBITRATED.as_bytes(),
+        &seed.to_le_bytes(),
+        &[escrow_bump_seed],
+      ],
+    )?;
+
+    let (pda_key, bump_seed) = find_vault_authority(program_id, escrow_account.key);
+
+    let escrow_info = ArbitratedEscrow {
+      key: Key::ArbitratedEscrowV1,
+      buyer: *buyer.key,
+      seller,
+      arbiter,
+      mint: temp_token_account_info.mint,
+      vault: *temp_token_account.key,
+      seed,
+      vault_authority_bump_seed: bump_seed,
+      status: ArbitrationStatus::Funded,
+    };
+    escrow_info.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+    let owner_change_instruction = instruction::set_authority(
+      token_program.key,
+      temp_token_account.key,
+      Some(&pda_key),
+      instruction::AuthorityType::AccountOwner,
+      buyer.key,
+      &[buyer.key],
+    )?;
+
+    // Transfer temporary token account ownership to PDA
+    invoke(
+      &owner_change_instruction,
+      &[
+        temp_token_account.clone(),
+        buyer.clone(),
+        token_program.clone(),
+      ],
+    )?;
+    Ok(())
+  }
+
+  fn process_release(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let buyer = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let seller_token_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+
+    assert_signer(buyer)?;
+    assert_token_program(token_program)?;
+
+    let escrow_info = load_arbitrated_escrow(program_id, escrow_account, vault_account)?;
+    if escrow_info.buyer != *buyer.key {
+      return Err(error::EscrowError::NotEscrowParty.into());
+    }
+    assert_party_token_account(seller_token_account, &escrow_info.seller, &escrow_info.mint)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+    msg!("Release {} to seller", vault_account_info.amount);
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      seller_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      vault_account_info.amount,
+    )?;
+
+    close_vault(
+      vault_account,
+      buyer,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(buyer, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_open_dispute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let party = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+
+    assert_signer(party)?;
+    assert_owned_by(escrow_account, program_id)?;
+    let mut escrow_info = ArbitratedEscrow::from_account_info(escrow_account)?;
+
+    if escrow_info.buyer != *party.key && escrow_info.seller != *party.key {
+      return Err(error::EscrowError::NotEscrowParty.into());
+    }
+    if escrow_info.status != ArbitrationStatus::Funded {
+      return Err(error::EscrowError::InvalidEscrowStatus.into());
+    }
+
+    msg!("Dispute opened by {}", party.key);
+    escrow_info.status = ArbitrationStatus::Disputed;
+    escrow_info.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_resolve_dispute(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    buyer_amount: u64,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let arbiter = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let buyer_token_account = next_account_info(account_info_iter)?;
+    let seller_token_account = next_account_info(account_info_iter)?;
+    let buyer_main_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+
+    assert_signer(arbiter)?;
+    assert_token_program(token_program)?;
+
+    let escrow_info = load_arbitrated_escrow(program_id, escrow_account, vault_account)?;
+    if escrow_info.arbiter != *arbiter.key {
+      return Err(error::EscrowError::ArbiterMismatch.into());
+    }
+    if escrow_info.status != ArbitrationStatus::Disputed {
+      return Err(error::EscrowError::InvalidEscrowStatus.into());
+    }
+    if escrow_info.buyer != *buyer_main_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    assert_party_token_account(buyer_token_account, &escrow_info.buyer, &escrow_info.mint)?;
+    assert_party_token_account(seller_token_account, &escrow_info.seller, &escrow_info.mint)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+    let seller_amount = vault_account_info
+      .amount
+      .checked_sub(buyer_amount)
+      .ok_or(error::EscrowError::SplitAboveVaultBalance)?;
+
+    msg!(
+      "Split {} to buyer and {} to seller",
+      buyer_amount,
+      seller_amount
+    );
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      buyer_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      buyer_amount,
+    )?;
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      seller_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      seller_amount,
+    )?;
+
+    close_vault(
+      vault_account,
+      buyer_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(buyer_main_account, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_init_vesting_escrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seed: u64,
+    beneficiary: Pubkey,
+    revocation_authority: Option<Pubkey>,
+    schedule: VestingSchedule,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let funder = next_account_info(account_info_iter)?;
+    let temp_token_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    assert_signer(funder)?;
+    assert_token_program(token_program)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
+
+    assert_owned_by(temp_token_account, &spl_token::id())?;
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+    let total_amount = temp_token_account_info.amount;
+
+    let valid_schedule = match &schedule {
+      VestingSchedule::Tranches(tranches) => {
+        let tranche_total = tranches
+          .iter()
+          .try_fold(0u64, |total, tranche| total.checked_add(tranche.amount));
+        !tranches.is_empty()
+          && tranches.len() <= MAX_VESTING_TRANCHES
+          && tranche_total == Some(total_amount)
+      }
+      VestingSchedule::Linear {
+        cliff, duration, ..
+      } => *duration > 0 && *cliff >= 0 && cliff <= duration,
+    };
+    if total_amount == 0 || !valid_schedule {
+      return Err(error::EscrowError::InvalidVestingSchedule.into());
+    }
+
+    let (escrow_key, escrow_bump_seed) = find_vesting_escrow_address(program_id, funder.key, seed);
+    if *escrow_account.key != escrow_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+
+    if !escrow_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let (pda_key, bump_seed) = find_vault_authority(program_id, escrow_account.key);
+
+    let escrow_info = VestingEscrow {
+      key: Key::VestingEscrowV1,
+      funder: *funder.key,
+      beneficiary,
+      revocation_authority,
+      mint: temp_token_account_info.mint,
+      vault: *temp_token_account.key,
+      seed,
+      vault_authority_bump_seed: bump_seed,
+      total_amount,
+      claimed_amount: 0,
+      schedule,
+    };
+    let escrow_data = escrow_info.try_to_vec()?;
+
+    create_or_allocate_account_raw(
+      *program_id,
+      escrow_account,
+      rent_info,
+      system_program,
+      funder,
+      escrow_data.len(),
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        funder.key.as_ref(),
+        VESTING.as_bytes(),
+        &seed.to_le_bytes(),
+        &[escrow_bump_seed],
+      ],
+    )?;
+    escrow_account
+      .data
+      .borrow_mut()
+      .copy_from_slice(&escrow_data);
+
+    let owner_change_instruction = instruction::set_authority(
+      token_program.key,
+      temp_token_account.key,
+      Some(&pda_key),
+      instruction::AuthorityType::AccountOwner,
+      funder.key,
+      &[funder.key],
+    )?;
+
+    // Transfer temporary token account ownership to PDA
+    invoke(
+      &owner_change_instruction,
+      &[
+        temp_token_account.clone(),
+        funder.clone(),
+        token_program.clone(),
+      ],
+    )?;
+    Ok(())
+  }
+
+  fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let beneficiary_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let funder_main_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_token_program(token_program)?;
+
+    let mut escrow_info = load_vesting_escrow(program_id, escrow_account, vault_account)?;
+    if escrow_info.funder != *funder_main_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    assert_party_token_account(
+      beneficiary_token_account,
+      &escrow_info.beneficiary,
+      &escrow_info.mint,
+    )?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    let claimable_amount = escrow_info
+      .vested_amount(clock.unix_timestamp)?
+      .saturating_sub(escrow_info.claimed_amount);
+    if claimable_amount == 0 {
+      return Err(error::EscrowError::NothingToClaim.into());
+    }
+
+    escrow_info.claimed_amount = escrow_info
+      .claimed_amount
+      .checked_add(claimable_amount)
+      .ok_or(error::EscrowError::AmountOverflow)?;
+    let fully_claimed = escrow_info.claimed_amount >= escrow_info.total_amount;
+
+    // Anyone can send tokens to the vault. Whatever sits on top of the grant goes out with
+    // the last claim, otherwise the vault could never be closed
+    let transfer_amount = if fully_claimed {
+      let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+      vault_account_info.amount
+    } else {
+      claimable_amount
+    };
+
+    msg!("Claim {}", transfer_amount);
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      beneficiary_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      transfer_amount,
+    )?;
+
+    if !fully_claimed {
+      escrow_info.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+      return Ok(());
+    }
+
+    msg!("Grant fully claimed");
+    close_vault(
+      vault_account,
+      funder_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(funder_main_account, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_revoke_vesting(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let revocation_authority = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let beneficiary_token_account = next_account_info(account_info_iter)?;
+    let funder_token_account = next_account_info(account_info_iter)?;
+    let funder_main_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_signer(revocation_authority)?;
+    assert_token_program(token_program)?;
+
+    let escrow_info = load_vesting_escrow(program_id, escrow_account, vault_account)?;
+    if escrow_info.revocation_authority != Some(*revocation_authority.key) {
+      return Err(error::EscrowError::RevocationAuthorityMismatch.into());
+    }
+    if escrow_info.funder != *funder_main_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    assert_party_token_account(
+      beneficiary_token_account,
+      &escrow_info.beneficiary,
+      &escrow_info.mint,
+    )?;
+    assert_party_token_account(funder_token_account, &escrow_info.funder, &escrow_info.mint)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    // What already vested still belongs to the beneficiary
+    let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+    let claimable_amount = escrow_info
+      .vested_amount(clock.unix_timestamp)?
+      .saturating_sub(escrow_info.claimed_amount)
+      .min(vault_account_info.amount);
+    let unvested_amount = vault_account_info.amount - claimable_amount;
+
+    msg!(
+      "Pay {} to beneficiary and claw back {}",
+      claimable_amount,
+      unvested_amount
+    );
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      beneficiary_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      claimable_amount,
+    )?;
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      funder_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      unvested_amount,
+    )?;
+
+    close_vault(
+      vault_account,
+      funder_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(funder_main_account, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_init_hash_locked_escrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: InitHashLockedEscrowArgs,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+    let temp_token_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_signer(maker)?;
+    assert_token_program(token_program)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
+
+    // The maker could refund right away and the taker would have no time to claim
+    if args.timeout <= clock.unix_timestamp {
+      return Err(error::EscrowError::TimeoutInPast.into());
+    }
+
+    assert_owned_by(temp_token_account, &spl_token::id())?;
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+    if temp_token_account_info.amount == 0 {
+      return Err(error::EscrowError::EmptyOffer.into());
+    }
+
+    let (escrow_key, escrow_bump_seed) =
+      find_hash_locked_escrow_address(program_id, maker.key, args.seed);
+    if *escrow_account.key != escrow_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+
+    if !escrow_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_or_allocate_account_raw(
+      *program_id,
+      escrow_account,
+      rent_info,
+      system_program,
+      maker,
+      HASH_LOCKED_ESCROW_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        maker.key.as_ref(),
+        HASH_LOCK.as_bytes(),
+        &args.seed.to_le_bytes(),
+        &[escrow_bump_seed],
+      ],
+    )?;
+
+    let (pda_key, bump_seed) = find_vault_authority(program_id, escrow_account.key);
+
+    let escrow_info = HashLockedEscrow {
+      key: Key::HashLockedEscrowV1,
+      maker: *maker.key,
+      taker: args.taker,
+      mint: temp_token_account_info.mint,
+      vault: *temp_token_account.key,
+      seed: args.seed,
+      vault_authority_bump_seed: bump_seed,
+      hash_algorithm: args.hash_algorithm,
+      hash: args.hash,
+      timeout: args.timeout,
+    };
+    escrow_info.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+
+    let owner_change_instruction = instruction::set_authority(
+      token_program.key,
+      temp_token_account.key,
+      Some(&pda_key),
+      instruction::AuthorityType::AccountOwner,
+      maker.key,
+      &[maker.key],
+    )?;
+
+    // Transfer temporary token account ownership to PDA
+    invoke(
+      &owner_change_instruction,
+      &[
+        temp_token_account.clone(),
+        maker.clone(),
+        token_program.clone(),
+      ],
+    )?;
+    Ok(())
+  }
+
+  fn process_claim_with_secret(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    secret: Vec<u8>,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+    let taker_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let maker_main_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_signer(taker)?;
+    assert_token_program(token_program)?;
+
+    let escrow_info = load_hash_locked_escrow(program_id, escrow_account, vault_account)?;
+    if escrow_info.taker != *taker.key {
+      return Err(error::EscrowError::TakerNotAllowed.into());
+    }
+    if escrow_info.maker != *maker_main_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    assert_party_token_account(taker_token_account, &escrow_info.taker, &escrow_info.mint)?;
+
+    if escrow_info.is_expired(clock.unix_timestamp) {
+      return Err(error::EscrowError::EscrowExpired.into());
+    }
+    if hash_secret(escrow_info.hash_algorithm, &secret) != escrow_info.hash {
+      return Err(error::EscrowError::InvalidSecret.into());
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+    msg!("Claim {} with secret", vault_account_info.amount);
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      taker_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      vault_account_info.amount,
+    )?;
+
+    close_vault(
+      vault_account,
+      maker_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(maker_main_account, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_refund(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+    let maker_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_signer(maker)?;
+    assert_token_program(token_program)?;
+
+    let escrow_info = load_hash_locked_escrow(program_id, escrow_account, vault_account)?;
+    if escrow_info.maker != *maker.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    assert_party_token_account(maker_token_account, &escrow_info.maker, &escrow_info.mint)?;
+
+    if !escrow_info.is_expired(clock.unix_timestamp) {
+      return Err(error::EscrowError::EscrowNotExpired.into());
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      escrow_account.key.as_ref(),
+      &[escrow_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+    msg!("Refund {} to maker", vault_account_info.amount);
+    transfer_from_vault(
+      SideKind::Token,
+      escrow_account,
+      vault_account,
+      maker_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      vault_account_info.amount,
+    )?;
+
+    close_vault(
+      vault_account,
+      maker,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(maker, escrow_account)?;
+    Ok(())
+  }
+
+  fn process_init_auction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: InitAuctionArgs,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let seller = next_account_info(account_info_iter)?;
+    let temp_token_account = next_account_info(account_info_iter)?;
+    let bid_vault_account = next_account_info(account_info_iter)?;
+    let auction_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let nft_mint_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_signer(seller)?;
+    assert_token_program(token_program)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
+
+    if args.extension_window < 0 {
+      return Err(ProgramError::InvalidArgument);
+    }
+    // Anyone could settle right away, before a single bid
+    if args.end_time <= clock.unix_timestamp {
+      return Err(error::EscrowError::TimeoutInPast.into());
+    }
+
+    assert_owned_by(temp_token_account, &spl_token::id())?;
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+    if !is_nft(nft_mint_account, &temp_token_account_info.mint)? {
+      return Err(error::EscrowError::NotAnNft.into());
+    }
+    if temp_token_account_info.amount != 1 {
+      return Err(error::EscrowError::AuctionVaultEmpty.into());
+    }
+    assert_owned_by(bid_vault_account, &spl_token::id())?;
+    let bid_vault_account_info: TokenAccount =
+      TokenAccount::unpack(&bid_vault_account.data.borrow())?;
+    if bid_vault_account_info.amount != 0 {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (auction_key, auction_bump_seed) = find_auction_address(program_id, seller.key, args.seed);
+    if *auction_account.key != auction_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+
+    if !auction_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_or_allocate_account_raw(
+      *program_id,
+      auction_account,
+      rent_info,
+      system_program,
+      seller,
+      AUCTION_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        seller.key.as_ref(),
+        AUCTION.as_bytes(),
+        &args.seed.to_le_bytes(),
+        &[auction_bump_seed],
+      ],
+    )?;
+
+    let (pda_key, bump_seed) = find_vault_authority(program_id, auction_account.key);
+
+    let auction_info = Auction {
+      key: Key::AuctionV1,
+      seller: *seller.key,
+      mint: temp_token_account_info.mint,
+      vault: *temp_token_account.key,
+      bid_mint: bid_vault_account_info.mint,
+      bid_vault: *bid_vault_account.key,
+      seed: args.seed,
+      vault_authority_bump_seed: bump_seed,
+      reserve_price: args.reserve_price,
+      end_time: args.end_time,
+      extension_window: args.extension_window,
+      top_bidder: None,
+      top_bid: 0,
+      pending_refunds: 0,
+      settled: false,
+    };
+    auction_info.serialize(&mut &mut auction_account.data.borrow_mut()[..])?;
+
+    // Transfer the NFT and bid vaults ownership to PDA
+    for vault_account in [temp_token_account, bid_vault_account].iter() {
+      let owner_change_instruction = instruction::set_authority(
+        token_program.key,
+        vault_account.key,
+        Some(&pda_key),
+        instruction::AuthorityType::AccountOwner,
+        seller.key,
+        &[seller.key],
+      )?;
+      invoke(
+        &owner_change_instruction,
+        &[
+          (*vault_account).clone(),
+          seller.clone(),
+          token_program.clone(),
+        ],
+      )?;
+    }
+    Ok(())
+  }
+
+  fn process_place_bid(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let bidder = next_account_info(account_info_iter)?;
+    let bidder_token_account = next_account_info(account_info_iter)?;
+    let bid_vault_account = next_account_info(account_info_iter)?;
+    let bidder_refund_account = next_account_info(account_info_iter)?;
+    let previous_bidder_refund_account = next_account_info(account_info_iter)?;
+    let auction_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    assert_signer(bidder)?;
+    assert_token_program(token_program)?;
+
+    assert_owned_by(auction_account, program_id)?;
+    let mut auction_info = Auction::from_account_info(auction_account)?;
+    if auction_info.bid_vault != *bid_vault_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    assert_party_token_account(bidder_token_account, bidder.key, &auction_info.bid_mint)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      auction_account.key.as_ref(),
+      &[auction_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    if auction_info.has_ended(clock.unix_timestamp) {
+      return Err(error::EscrowError::AuctionEnded.into());
+    }
+    if amount < auction_info.reserve_price
+      || (auction_info.top_bidder.is_some() && amount <= auction_info.top_bid)
+    {
+      return Err(error::EscrowError::BidTooLow.into());
+    }
+
+    // The bidder can only be credited once outbid if its refund account exists by then
+    let bidder_refund = load_or_create_bid_refund(
+      program_id,
+      auction_account.key,
+      bidder,
+      bidder_refund_account,
+      rent_info,
+      system_program,
+    )?;
+    bidder_refund.serialize(&mut &mut bidder_refund_account.data.borrow_mut()[..])?;
+
+    // Refunding the previous top bidder here would let it block new bids by closing its token
+    // account, so the refund waits in the bid vault until it is withdrawn
+    if let Some(previous_bidder) = auction_info.top_bidder {
+      let mut previous_refund = load_bid_refund(
+        program_id,
+        auction_account.key,
+        &previous_bidder,
+        previous_bidder_refund_account,
+      )?;
+      msg!("Owe {} to previous top bidder", auction_info.top_bid);
+      previous_refund.amount = previous_refund
+        .amount
+        .checked_add(auction_info.top_bid)
+        .ok_or(error::EscrowError::AmountOverflow)?;
+      previous_refund.serialize(&mut &mut previous_bidder_refund_account.data.borrow_mut()[..])?;
+      auction_info.pending_refunds = auction_info
+        .pending_refunds
+        .checked_add(auction_info.top_bid)
+        .ok_or(error::EscrowError::AmountOverflow)?;
+    }
+
+    msg!("Escrow bid of {}", amount);
+    let transfer_instruction = spl_token::instruction::transfer(
+      token_program.key,
+      bidder_token_account.key,
+      bid_vault_account.key,
+      bidder.key,
+      &[bidder.key],
+      amount,
+    )?;
+    invoke(
+      &transfer_instruction,
+      &[
+        bidder_token_account.clone(),
+        bid_vault_account.clone(),
+        bidder.clone(),
+        token_program.clone(),
+      ],
+    )?;
+
+    // Anti-snipe: a late bid leaves the other bidders a full window to answer
+    let extended_end_time = clock
+      .unix_timestamp
+      .checked_add(auction_info.extension_window)
+      .ok_or(error::EscrowError::AmountOverflow)?;
+    if extended_end_time > auction_info.end_time {
+      msg!("Extend auction to {}", extended_end_time);
+      auction_info.end_time = extended_end_time;
+    }
+
+    auction_info.top_bidder = Some(*bidder.key);
+    auction_info.top_bid = amount;
+    auction_info.serialize(&mut &mut auction_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_settle_auction(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let seller_main_account = next_account_info(account_info_iter)?;
+    let seller_token_account = next_account_info(account_info_iter)?;
+    let winner_token_account = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let bid_vault_account = next_account_info(account_info_iter)?;
+    let auction_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let clock: &Clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    assert_token_program(token_program)?;
+
+    assert_owned_by(auction_account, program_id)?;
+    let mut auction_info = Auction::from_account_info(auction_account)?;
+    if auction_info.vault != *vault_account.key
+      || auction_info.bid_vault != *bid_vault_account.key
+      || auction_info.seller != *seller_main_account.key
+    {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    if auction_info.settled {
+      return Err(error::EscrowError::InvalidEscrowStatus.into());
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      auction_account.key.as_ref(),
+      &[auction_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    if !auction_info.has_ended(clock.unix_timestamp) {
+      return Err(error::EscrowError::AuctionNotEnded.into());
+    }
+
+    match auction_info.top_bidder {
+      Some(winner) => {
+        assert_party_token_account(winner_token_account, &winner, &auction_info.mint)?;
+        assert_party_token_account(
+          seller_token_account,
+          &auction_info.seller,
+          &auction_info.bid_mint,
+        )?;
+        msg!("Pay {} to seller", auction_info.top_bid);
+        transfer_from_vault(
+          SideKind::Token,
+          auction_account,
+          bid_vault_account,
+          seller_token_account,
+          pda_account,
+          token_program,
+          signers_seeds,
+          auction_info.top_bid,
+        )?;
+      }
+      None => {
+        msg!("No bids, return NFT to seller");
+        assert_party_token_account(
+          winner_token_account,
+          &auction_info.seller,
+          &auction_info.mint,
+        )?;
+      }
+    }
+
+    let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+    transfer_from_vault(
+      SideKind::Token,
+      auction_account,
+      vault_account,
+      winner_token_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+      vault_account_info.amount,
+    )?;
+
+    close_vault(
+      vault_account,
+      seller_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+
+    if auction_info.pending_refunds > 0 {
+      msg!(
+        "Keep the bid vault open for {} of refunds",
+        auction_info.pending_refunds
+      );
+      auction_info.settled = true;
+      auction_info.serialize(&mut &mut auction_account.data.borrow_mut()[..])?;
+      return Ok(());
+    }
+
+    msg!("Closing the bid vault and the auction account");
+    close_vault(
+      bid_vault_account,
+      seller_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(seller_main_account, auction_account)?;
+    Ok(())
+  }
+
+  fn process_withdraw_bid_refund(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let bidder = next_account_info(account_info_iter)?;
+    let bidder_token_account = next_account_info(account_info_iter)?;
+    let refund_account = next_account_info(account_info_iter)?;
+    let bid_vault_account = next_account_info(account_info_iter)?;
+    let auction_account = next_account_info(account_info_iter)?;
+    let seller_main_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+
+    assert_signer(bidder)?;
+    assert_token_program(token_program)?;
+
+    assert_owned_by(auction_account, program_id)?;
+    let mut auction_info = Auction::from_account_info(auction_account)?;
+    if auction_info.bid_vault != *bid_vault_account.key
+      || auction_info.seller != *seller_main_account.key
+    {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    // The top bid is only credited to the refund account once outbid
+    if auction_info.top_bidder == Some(*bidder.key) && !auction_info.settled {
+      return Err(error::EscrowError::TopBidderCannotWithdraw.into());
+    }
+    let refund_info = load_bid_refund(program_id, auction_account.key, bidder.key, refund_account)?;
+    assert_party_token_account(bidder_token_account, bidder.key, &auction_info.bid_mint)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      auction_account.key.as_ref(),
+      &[auction_info.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    if refund_info.amount > 0 {
+      msg!("Refund {} to bidder", refund_info.amount);
+      transfer_from_vault(
+        SideKind::Token,
+        auction_account,
+        bid_vault_account,
+        bidder_token_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+        refund_info.amount,
+      )?;
+    }
+    close_escrow_account(bidder, refund_account)?;
+
+    auction_info.pending_refunds = auction_info
+      .pending_refunds
+      .checked_sub(refund_info.amount)
+      .ok_or(error::EscrowError::AmountOverflow)?;
+    if !auction_info.settled || auction_info.pending_refunds > 0 {
+      auction_info.serialize(&mut &mut auction_account.data.borrow_mut()[..])?;
+      return Ok(());
+    }
+
+    msg!("Last refund withdrawn, closing the bid vault and the auction account");
+    close_vault(
+      bid_vault_account,
+      seller_main_account,
+      pda_account,
+      token_program,
+      signers_seeds,
+    )?;
+    close_escrow_account(seller_main_account, auction_account)?;
+    Ok(())
+  }
+
+  fn process_init_ring_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    seed: u64,
+    legs: Vec<RingLeg>,
+  ) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let creator = next_account_info(account_info_iter)?;
+    let ring_account = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    assert_signer(creator)?;
+    assert_not_paused(&load_config(program_id, config_account)?)?;
+
+    let distinct_parties = legs
+      .iter()
+      .enumerate()
+      .all(|(index, leg)| legs[..index].iter().all(|other| other.party != leg.party));
+    if legs.len() < MIN_RING_PARTIES
+      || legs.len() > MAX_RING_PARTIES
+      || !distinct_parties
+      || legs.iter().any(|leg| leg.amount == 0)
+    {
+      return Err(error::EscrowError::InvalidRingParties.into());
+    }
+    // A party would get back the mint it deposits
+    if legs
+      .iter()
+      .enumerate()
+      .any(|(index, leg)| legs[(index + 1) % legs.len()].mint == leg.mint)
+    {
+      return Err(error::EscrowError::RingLegsShareMint.into());
+    }
+
+    let (ring_key, ring_bump_seed) = find_ring_swap_address(program_id, creator.key, seed);
+    if *ring_account.key != ring_key {
+      return Err(error::EscrowError::InvalidEscrowKey.into());
+    }
+
+    if !ring_account.data_is_empty() {
+      return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_or_allocate_account_raw(
+      *program_id,
+      ring_account,
+      rent_info,
+      system_program,
+      creator,
+      MAX_RING_SWAP_LEN,
+      &[
+        PREFIX.as_bytes(),
+        program_id.as_ref(),
+        creator.key.as_ref(),
+        RING.as_bytes(),
+        &seed.to_le_bytes(),
+        &[ring_bump_seed],
+      ],
+    )?;
+
+    let (_, bump_seed) = find_vault_authority(program_id, ring_account.key);
+
+    let ring = RingSwap {
+      key: Key::RingSwapV1,
+      creator: *creator.key,
+      seed,
+      vault_authority_bump_seed: bump_seed,
+      participants: legs
+        .into_iter()
+        .map(|leg| RingParticipant {
+          party: leg.party,
+          mint: leg.mint,
+          amount: leg.amount,
+          vault: None,
+          receive_account: None,
+        })
+        .collect(),
+    };
+    ring.serialize(&mut &mut ring_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_deposit_ring(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let party = next_account_info(account_info_iter)?;
+    let temp_token_account = next_account_info(account_info_iter)?;
+    let receive_token_account = next_account_info(account_info_iter)?;
+    let ring_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    assert_signer(party)?;
+    assert_token_program(token_program)?;
+
+    assert_owned_by(ring_account, program_id)?;
+    let mut ring = RingSwap::from_account_info(ring_account)?;
+    let index = ring
+      .participants
+      .iter()
+      .position(|participant| participant.party == *party.key)
+      .ok_or(error::EscrowError::NotEscrowParty)?;
+    if ring.participants[index].vault.is_some() {
+      return Err(error::EscrowError::InvalidEscrowStatus.into());
+    }
+
+    assert_owned_by(temp_token_account, &spl_token::id())?;
+    let temp_token_account_info: TokenAccount =
+      TokenAccount::unpack(&temp_token_account.data.borrow())?;
+    if temp_token_account_info.mint != ring.participants[index].mint {
+      return Err(error::EscrowError::OfferedMintMismatch.into());
+    }
+    if temp_token_account_info.amount != ring.participants[index].amount {
+      return Err(error::EscrowError::ExpectedAmountMismatch.into());
+    }
+    let previous_mint = ring.participants[ring.previous(index)].mint;
+    assert_owned_by(receive_token_account, &spl_token::id())?;
+    assert_party_token_account(receive_token_account, party.key, &previous_mint)?;
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      ring_account.key.as_ref(),
+      &[ring.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+
+    ring.participants[index].vault = Some(*temp_token_account.key);
+    ring.participants[index].receive_account = Some(*receive_token_account.key);
+    ring.serialize(&mut &mut ring_account.data.borrow_mut()[..])?;
+
+    let owner_change_instruction = instruction::set_authority(
+      token_program.key,
+      temp_token_account.key,
+      Some(&pda_key),
+      instruction::AuthorityType::AccountOwner,
+      party.key,
+      &[party.key],
+    )?;
+
+    // Transfer temporary token account ownership to PDA
+    invoke(
+      &owner_change_instruction,
+      &[
+        temp_token_account.clone(),
+        party.clone(),
+        token_program.clone(),
+      ],
+    )?;
+    Ok(())
+  }
+
+  fn process_withdraw_ring(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let party = next_account_info(account_info_iter)?;
+    let vault_account = next_account_info(account_info_iter)?;
+    let ring_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+
+    assert_signer(party)?;
+    assert_token_program(token_program)?;
+
+    assert_owned_by(ring_account, program_id)?;
+    let mut ring = RingSwap::from_account_info(ring_account)?;
+    let index = ring
+      .participants
+      .iter()
+      .position(|participant| participant.party == *party.key)
+      .ok_or(error::EscrowError::NotEscrowParty)?;
+    match ring.participants[index].vault {
+      Some(vault) if vault == *vault_account.key => (),
+      Some(_) => return Err(ProgramError::InvalidAccountData),
+      None => return Err(error::EscrowError::InvalidEscrowStatus.into()),
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      ring_account.key.as_ref(),
+      &[ring.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    msg!("Change vault owner back to party");
+    let owner_change_instruction = instruction::set_authority(
+      token_program.key,
+      vault_account.key,
+      Some(party.key),
+      instruction::AuthorityType::AccountOwner,
+      &pda_key,
+      &[&pda_key],
+    )?;
+    invoke_signed(
+      &owner_change_instruction,
+      &[
+        vault_account.clone(),
+        party.clone(),
+        pda_account.clone(),
+        token_program.clone(),
+      ],
+      &[signers_seeds],
+    )?;
+
+    ring.participants[index].vault = None;
+    ring.participants[index].receive_account = None;
+    ring.serialize(&mut &mut ring_account.data.borrow_mut()[..])?;
+    Ok(())
+  }
+
+  fn process_settle_ring(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let creator_main_account = next_account_info(account_info_iter)?;
+    let ring_account = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+
+    assert_token_program(token_program)?;
+
+    assert_owned_by(ring_account, program_id)?;
+    let ring = RingSwap::from_account_info(ring_account)?;
+    if ring.creator != *creator_main_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    if !ring.is_complete() {
+      return Err(error::EscrowError::RingIncomplete.into());
+    }
+
+    let signers_seeds = &[
+      PREFIX.as_bytes(),
+      program_id.as_ref(),
+      ring_account.key.as_ref(),
+      &[ring.vault_authority_bump_seed],
+    ];
+    let pda_key = Pubkey::create_program_address(signers_seeds, program_id)?;
+    assert_vault_authority(pda_account, &pda_key)?;
+
+    for (index, participant) in ring.participants.iter().enumerate() {
+      let vault_account = next_account_info(account_info_iter)?;
+      let next_receive_account = next_account_info(account_info_iter)?;
+      let party_main_account = next_account_info(account_info_iter)?;
+
+      let next = &ring.participants[(index + 1) % ring.participants.len()];
+      if participant.vault != Some(*vault_account.key)
+        || next.receive_account != Some(*next_receive_account.key)
+        || participant.party != *party_main_account.key
+      {
+        return Err(ProgramError::InvalidAccountData);
+      }
+
+      let vault_account_info: TokenAccount = TokenAccount::unpack(&vault_account.data.borrow())?;
+      msg!("Pay {} to {}", vault_account_info.amount, next.party);
+      transfer_from_vault(
+        SideKind::Token,
+        ring_account,
+        vault_account,
+        next_receive_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+        vault_account_info.amount,
+      )?;
+      close_vault(
+        vault_account,
+        party_main_account,
+        pda_account,
+        token_program,
+        signers_seeds,
+      )?;
+    }
+
+    close_escrow_account(creator_main_account, ring_account)?;
+    Ok(())
+  }
+
+  fn process_close_order_book_page(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let page_account = next_account_info(account_info_iter)?;
+    let payer_account = next_account_info(account_info_iter)?;
+    let previous_page_account = next_account_info(account_info_iter)?;
+
+    assert_owned_by(page_account, program_id)?;
+    let page = OrderBookPage::from_account_info(page_account)?;
+    let page = load_order_book_page(
+      program_id,
+      page_account,
+      &page.offered_mint,
+      &page.requested_mint,
+    )?;
+    // Closing a page in the middle would break the chain
+    if !page.escrows.is_empty() || page.next_page.is_some() {
+      return Err(error::EscrowError::OrderBookPageInUse.into());
+    }
+    if page.payer != *payer_account.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    if page.page_index > 0 {
+      let mut previous_page = load_order_book_page(
+        program_id,
+        previous_page_account,
+        &page.offered_mint,
+        &page.requested_mint,
+      )?;
+      if previous_page.next_page != Some(*page_account.key) {
+        return Err(error::EscrowError::InvalidOrderBookPage.into());
+      }
+      previous_page.next_page = None;
+      previous_page.serialize(&mut &mut previous_page_account.data.borrow_mut()[..])?;
+    }
+
+    msg!("Close order book page {}", page.page_index);
+    close_escrow_account(payer_account, page_account)?;
+    Ok(())
+  }
+
+  fn process_close_ring_swap(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let creator = next_account_info(account_info_iter)?;
+    let ring_account = next_account_info(account_info_iter)?;
+
+    assert_signer(creator)?;
+
+    assert_owned_by(ring_account, program_id)?;
+    let ring = RingSwap::from_account_info(ring_account)?;
+    if ring.creator != *creator.key {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    // Parties withdraw their own deposits, closing can't leave a vault behind
+    if ring
+      .participants
+      .iter()
+      .any(|participant| participant.vault.is_some())
+    {
+      return Err(error::EscrowError::RingHasDeposits.into());
+    }
+
+    msg!("Close Ring Swap Account");
+    close_escrow_account(creator, ring_account)?;
+    Ok(())
+  }
 }
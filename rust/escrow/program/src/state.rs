@@ -1,13 +1,111 @@
 use {
+  crate::{error::EscrowError, util::try_from_slice_checked},
   arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs},
   borsh::{BorshDeserialize, BorshSerialize},
+  num_derive::FromPrimitive,
+  num_traits::FromPrimitive,
   solana_program::{
+    account_info::AccountInfo,
+    clock::UnixTimestamp,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
   },
+  std::convert::TryFrom,
 };
 
+/// prefix used for PDAs to avoid certain collision attacks (https://en.wikipedia.org/wiki/Collision_attack#Chosen-prefix_collision_attack)
+pub const PREFIX: &str = "escrow";
+
+/// Used in seeds to make Config model pda address
+pub const CONFIG: &str = "config";
+
+pub const MAX_FEE_RECIPIENTS: usize = 16;
+
+pub const MAX_FEE_RECIPIENT_LEN: usize = 1 + 32 + 32;
+
+pub const MAX_CONFIG_LEN: usize =
+  1 + 32 + 2 + 1 + 33 + 4 + MAX_FEE_RECIPIENTS * MAX_FEE_RECIPIENT_LEN;
+
+/// Used in seeds to make BasketEscrow model pda address
+pub const BASKET: &str = "basket";
+
+pub const MAX_BASKET_LEGS: usize = 8;
+
+pub const BASKET_LEG_LEN: usize = 32 + 8 + 32;
+
+/// Used in seeds to make the signed order delegate pda address
+pub const ORDER: &str = "order";
+
+/// Used in seeds to make OrderNonce model pda address
+pub const NONCE: &str = "nonce";
+
+/// Nonces above the lowest unused one a maker's signed orders can use, one bit each
+pub const ORDER_NONCE_WINDOW: u64 = 128;
+
+pub const ORDER_NONCE_LEN: usize = 1 + 32 + 8 + 16;
+
+/// Used in seeds to make ArbitratedEscrow model pda address
+pub const ARBITRATED: &str = "arbitrated";
+
+pub const ARBITRATED_ESCROW_LEN: usize = 1 + 32 + 32 + 32 + 32 + 32 + 8 + 1 + 1;
+
+/// Used in seeds to make VestingEscrow model pda address
+pub const VESTING: &str = "vesting";
+
+pub const MAX_VESTING_TRANCHES: usize = 32;
+
+/// Used in seeds to make HashLockedEscrow model pda address
+pub const HASH_LOCK: &str = "hashlock";
+
+pub const HASH_LOCKED_ESCROW_LEN: usize = 1 + 32 + 32 + 32 + 32 + 8 + 1 + 1 + 32 + 8;
+
+/// Used in seeds to make Auction model pda address
+pub const AUCTION: &str = "auction";
+
+/// Used in seeds to make OrderBookPage model pda address
+pub const ORDER_BOOK: &str = "orderbook";
+
+pub const MAX_ORDER_BOOK_PAGE_ENTRIES: usize = 32;
+
+pub const ORDER_BOOK_PAGE_LEN: usize =
+  1 + 32 + 32 + 4 + 32 + 33 + 4 + 32 * MAX_ORDER_BOOK_PAGE_ENTRIES;
+
+pub const AUCTION_LEN: usize = 1 + 32 + 32 + 32 + 32 + 32 + 8 + 1 + 8 + 8 + 8 + 33 + 8 + 8 + 1;
+
+/// Used in seeds to make BidRefund model pda address
+pub const REFUND: &str = "refund";
+
+pub const BID_REFUND_LEN: usize = 1 + 32 + 32 + 8;
+
+/// Used in seeds to make RingSwap model pda address
+pub const RING: &str = "ring";
+
+pub const MIN_RING_PARTIES: usize = 3;
+
+pub const MAX_RING_PARTIES: usize = 8;
+
+pub const RING_PARTICIPANT_LEN: usize = 32 + 32 + 8 + 33 + 33;
+
+pub const MAX_RING_SWAP_LEN: usize = 1 + 32 + 8 + 1 + 4 + RING_PARTICIPANT_LEN * MAX_RING_PARTIES;
+
+/// Define the type of borsh state stored in accounts
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Key {
+  Uninitialized,
+  ConfigV1,
+  BasketEscrowV1,
+  OrderNonceV1,
+  ArbitratedEscrowV1,
+  VestingEscrowV1,
+  HashLockedEscrowV1,
+  AuctionV1,
+  OrderBookPageV1,
+  RingSwapV1,
+  BidRefundV1,
+}
+
 #[repr(C)]
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct EscrowReceive {
@@ -15,15 +113,95 @@ pub struct EscrowReceive {
   pub amount: u64,
 }
 
+/// What an escrow side is denominated in
+#[repr(u8)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum SideKind {
+  /// SPL tokens moved through token accounts
+  Token,
+  /// Native SOL moved as lamports, without wrapping. Offered lamports are held by the escrow account itself
+  Lamports,
+  /// Offered SPL tokens stay in the initializer's token account, which approves the vault authority
+  /// as delegate. Only valid for the offered side
+  Delegate,
+}
+
 pub struct Escrow {
   pub is_initialized: bool,
   pub initializer_pubkey: Pubkey,
-  /// For Sending token to receiver afterward
+  /// For Sending token to receiver afterward. The initializer's own token account in delegate mode
   pub temp_token_account_pubkey: Pubkey,
   /// For receiving token receiver
   pub initializer_token_to_receive_account_pubkey: Pubkey,
   pub expected_amount: u64,
+  /// Bump seed of the vault authority PDA (['escrow', program id, escrow account])
+  pub vault_authority_bump_seed: u8,
+  /// Client chosen seed of the escrow PDA (['escrow', program id, initializer, mint a, mint b, seed])
+  pub seed: u64,
+  /// Mint of the tokens held in the temp token account
+  pub offered_mint: Pubkey,
+  /// Mint of the tokens the initializer expects to receive
+  pub requested_mint: Pubkey,
+  /// Amount deposited at init, or left in the vault at the last repricing update. Together with `expected_amount` it fixes the
+  /// price ratio (offered:requested). What is still available is read from the vault instead
+  pub offered_amount: u64,
+  /// Amount of the offered token paid out to takers so far
+  pub filled_amount: u64,
+  /// Unix timestamp after which the escrow can no longer be taken and anyone can refund it
+  pub expires_at: Option<UnixTimestamp>,
+  /// If set, the only key allowed to take the escrow
+  pub allowed_taker: Option<Pubkey>,
+  /// How the offered side is held
+  pub offered_kind: SideKind,
+  /// How the requested side is paid
+  pub requested_kind: SideKind,
+  /// If set, the price for `offered_amount` decays along it instead of being `expected_amount`
+  pub dutch_pricing: Option<DutchPricing>,
 }
+impl Escrow {
+  /// Amount of the requested token a taker owes for `fill_amount` of the offered token.
+  /// Rounds up so that a partial fill never favors the taker.
+  pub fn payment_for(&self, fill_amount: u64) -> Result<u64, ProgramError> {
+    self.pro_rata(self.expected_amount, fill_amount)
+  }
+
+  /// Same as `payment_for`, at the price of the Dutch pricing at `now` if there is one
+  pub fn payment_at(&self, fill_amount: u64, now: UnixTimestamp) -> Result<u64, ProgramError> {
+    match &self.dutch_pricing {
+      Some(dutch_pricing) => self.pro_rata(dutch_pricing.price_at(now)?, fill_amount),
+      None => self.payment_for(fill_amount),
+    }
+  }
+
+  /// Share of `requested_amount`, the price of the whole `offered_amount`, owed for `fill_amount`.
+  /// Rounds up
+  pub fn pro_rata(&self, requested_amount: u64, fill_amount: u64) -> Result<u64, ProgramError> {
+    let offered_amount = self.offered_amount as u128;
+    let payment = (fill_amount as u128)
+      .checked_mul(requested_amount as u128)
+      .and_then(|n| n.checked_add(offered_amount.checked_sub(1)?))
+      .and_then(|n| n.checked_div(offered_amount))
+      .ok_or(EscrowError::AmountOverflow)?;
+    u64::try_from(payment).map_err(|_| EscrowError::AmountOverflow.into())
+  }
+
+  /// Checks if `taker` may take the escrow
+  pub fn is_taker_allowed(&self, taker: &Pubkey) -> bool {
+    match self.allowed_taker {
+      Some(allowed_taker) => allowed_taker == *taker,
+      None => true,
+    }
+  }
+
+  /// Checks if the escrow has passed its deadline
+  pub fn is_expired(&self, now: UnixTimestamp) -> bool {
+    match self.expires_at {
+      Some(expires_at) => now >= expires_at,
+      None => false,
+    }
+  }
+}
+
 impl Sealed for Escrow {}
 
 impl IsInitialized for Escrow {
@@ -33,8 +211,9 @@ impl IsInitialized for Escrow {
 }
 
 impl Pack for Escrow {
-  /// 1 (bool) + 3 * 32 (Pubkey) + 1 * 8 (u64) = 105
-  const LEN: usize = 105;
+  /// 1 (bool) + 3 * 32 (Pubkey) + 1 * 8 (u64) + 1 (u8) + 1 * 8 (u64) + 2 * 32 (Pubkey) + 2 * 8 (u64)
+  /// + 12 (Option<i64>) + 36 (Option<Pubkey>) + 2 * 1 (SideKind) + 36 (Option<DutchPricing>) = 280
+  const LEN: usize = 280;
   fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
     let src = array_ref![src, 0, Escrow::LEN];
     let (
@@ -43,7 +222,18 @@ impl Pack for Escrow {
       temp_token_account_pubkey,
       initializer_token_to_receive_account_pubkey,
       expected_amount,
-    ) = array_refs![src, 1, 32, 32, 32, 8];
+      vault_authority_bump_seed,
+      seed,
+      offered_mint,
+      requested_mint,
+      offered_amount,
+      filled_amount,
+      expires_at,
+      allowed_taker,
+      offered_kind,
+      requested_kind,
+      dutch_pricing,
+    ) = array_refs![src, 1, 32, 32, 32, 8, 1, 8, 32, 32, 8, 8, 12, 36, 1, 1, 36];
 
     let is_initialized = match is_initialized {
       [0] => false,
@@ -58,6 +248,18 @@ impl Pack for Escrow {
         *initializer_token_to_receive_account_pubkey,
       ),
       expected_amount: u64::from_le_bytes(*expected_amount),
+      vault_authority_bump_seed: vault_authority_bump_seed[0],
+      seed: u64::from_le_bytes(*seed),
+      offered_mint: Pubkey::new_from_array(*offered_mint),
+      requested_mint: Pubkey::new_from_array(*requested_mint),
+      offered_amount: u64::from_le_bytes(*offered_amount),
+      filled_amount: u64::from_le_bytes(*filled_amount),
+      expires_at: unpack_option_i64(expires_at)?,
+      allowed_taker: unpack_option_key(allowed_taker)?,
+      offered_kind: SideKind::from_u8(offered_kind[0]).ok_or(ProgramError::InvalidAccountData)?,
+      requested_kind: SideKind::from_u8(requested_kind[0])
+        .ok_or(ProgramError::InvalidAccountData)?,
+      dutch_pricing: unpack_option_dutch_pricing(dutch_pricing)?,
     })
   }
 
@@ -70,7 +272,18 @@ impl Pack for Escrow {
       temp_token_account_pubkey_dst,
       initializer_token_to_receive_account_pubkey_dst,
       expected_amount_dst,
-    ) = mut_array_refs![dst, 1, 32, 32, 32, 8];
+      vault_authority_bump_seed_dst,
+      seed_dst,
+      offered_mint_dst,
+      requested_mint_dst,
+      offered_amount_dst,
+      filled_amount_dst,
+      expires_at_dst,
+      allowed_taker_dst,
+      offered_kind_dst,
+      requested_kind_dst,
+      dutch_pricing_dst,
+    ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 1, 8, 32, 32, 8, 8, 12, 36, 1, 1, 36];
 
     let Escrow {
       is_initialized,
@@ -78,6 +291,17 @@ impl Pack for Escrow {
       temp_token_account_pubkey,
       initializer_token_to_receive_account_pubkey,
       expected_amount,
+      vault_authority_bump_seed,
+      seed,
+      offered_mint,
+      requested_mint,
+      offered_amount,
+      filled_amount,
+      expires_at,
+      allowed_taker,
+      offered_kind,
+      requested_kind,
+      dutch_pricing,
     } = self;
 
     is_initialized_dst[0] = *is_initialized as u8;
@@ -86,5 +310,570 @@ impl Pack for Escrow {
     initializer_token_to_receive_account_pubkey_dst
       .copy_from_slice(initializer_token_to_receive_account_pubkey.as_ref());
     *expected_amount_dst = expected_amount.to_le_bytes();
+    vault_authority_bump_seed_dst[0] = *vault_authority_bump_seed;
+    *seed_dst = seed.to_le_bytes();
+    offered_mint_dst.copy_from_slice(offered_mint.as_ref());
+    requested_mint_dst.copy_from_slice(requested_mint.as_ref());
+    *offered_amount_dst = offered_amount.to_le_bytes();
+    *filled_amount_dst = filled_amount.to_le_bytes();
+    pack_option_i64(expires_at, expires_at_dst);
+    pack_option_key(allowed_taker, allowed_taker_dst);
+    offered_kind_dst[0] = *offered_kind as u8;
+    requested_kind_dst[0] = *requested_kind as u8;
+    pack_option_dutch_pricing(dutch_pricing, dutch_pricing_dst);
+  }
+}
+
+/// Price of an escrow decaying linearly from `start_price` at `start_time` to `end_price` at `end_time`
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub struct DutchPricing {
+  pub start_price: u64,
+  pub end_price: u64,
+  pub start_time: UnixTimestamp,
+  pub end_time: UnixTimestamp,
+}
+
+impl DutchPricing {
+  pub fn is_valid(&self) -> bool {
+    self.start_time < self.end_time && self.start_price >= self.end_price
+  }
+
+  /// Requested amount for the whole offer at `now`. Rounds up
+  pub fn price_at(&self, now: UnixTimestamp) -> Result<u64, ProgramError> {
+    if now <= self.start_time {
+      return Ok(self.start_price);
+    }
+    if now >= self.end_time {
+      return Ok(self.end_price);
+    }
+    let decay = ((self.start_price - self.end_price) as u128)
+      .checked_mul((now - self.start_time) as u128)
+      .and_then(|n| n.checked_div((self.end_time - self.start_time) as u128))
+      .ok_or(EscrowError::AmountOverflow)?;
+    Ok(self.start_price - decay as u64)
+  }
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct FeeRecipient {
+  /// Lamports or Token, delegated tokens are paid like any other token
+  pub kind: SideKind,
+  /// Native mint stands for lamports, and for wrapped SOL when `kind` is Token
+  pub mint: Pubkey,
+  /// Token account of `mint` receiving fees, or a wallet for lamport sides
+  pub account: Pubkey,
+}
+
+/// Program wide settings, a single pda of ['escrow', program id, 'config']
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct Config {
+  pub key: Key,
+  pub admin: Pubkey,
+  /// Fee taken from both legs of every exchange (0-10000)
+  pub fee_basis_points: u16,
+  /// Stops new escrows and exchanges, cancelling stays possible
+  pub paused: bool,
+  /// Token metadata program whose creators get royalties when their NFTs trade, none disables royalties
+  pub token_metadata_program: Option<Pubkey>,
+  /// Fees of a side kind and mint without recipient are not charged
+  pub fee_recipients: Vec<FeeRecipient>,
+}
+
+impl Config {
+  pub fn from_account_info(a: &AccountInfo) -> Result<Config, ProgramError> {
+    let config: Config = try_from_slice_checked(&a.data.borrow(), Key::ConfigV1)?;
+    Ok(config)
+  }
+
+  /// Fee owed on `amount` of `mint` paid as `kind`, paid to `recipient`
+  pub fn fee_for(
+    &self,
+    kind: SideKind,
+    mint: &Pubkey,
+    recipient: &Pubkey,
+    amount: u64,
+  ) -> Result<u64, ProgramError> {
+    let kind = match kind {
+      SideKind::Delegate => SideKind::Token,
+      kind => kind,
+    };
+    let fee_recipient = match self
+      .fee_recipients
+      .iter()
+      .find(|r| r.kind == kind && r.mint == *mint)
+    {
+      Some(fee_recipient) => fee_recipient,
+      None => return Ok(0),
+    };
+    if fee_recipient.account != *recipient {
+      return Err(EscrowError::InvalidFeeRecipient.into());
+    }
+    let fee = (amount as u128)
+      .checked_mul(self.fee_basis_points as u128)
+      .and_then(|n| n.checked_div(10000))
+      .ok_or(EscrowError::AmountOverflow)?;
+    u64::try_from(fee).map_err(|_| EscrowError::AmountOverflow.into())
+  }
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct BasketLeg {
+  /// Native mint stands for lamports on the requested side
+  pub mint: Pubkey,
+  pub amount: u64,
+  /// Vault of an offered leg, or the initializer's account receiving a requested leg
+  pub account: Pubkey,
+}
+
+/// Escrow of several offered vaults against several requested mints, settled all at once.
+/// A pda of ['escrow', program id, initializer, 'basket', seed]
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct BasketEscrow {
+  pub key: Key,
+  pub initializer: Pubkey,
+  pub seed: u64,
+  pub vault_authority_bump_seed: u8,
+  pub offered: Vec<BasketLeg>,
+  pub requested: Vec<BasketLeg>,
+}
+
+impl BasketEscrow {
+  pub fn from_account_info(a: &AccountInfo) -> Result<BasketEscrow, ProgramError> {
+    let basket: BasketEscrow = try_from_slice_checked(&a.data.borrow(), Key::BasketEscrowV1)?;
+    Ok(basket)
+  }
+
+  /// Account size of a basket with the given number of legs
+  pub fn space(offered_legs: usize, requested_legs: usize) -> usize {
+    1 + 32 + 8 + 1 + 4 + offered_legs * BASKET_LEG_LEN + 4 + requested_legs * BASKET_LEG_LEN
+  }
+}
+
+/// Order a maker signs off-chain. Its borsh encoding is the message verified by the ed25519 program
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SignedOrder {
+  /// Escrow program deployment the order is for, so it can't be replayed against another one
+  pub program_id: Pubkey,
+  pub maker: Pubkey,
+  pub offered_mint: Pubkey,
+  pub offered_amount: u64,
+  pub requested_mint: Pubkey,
+  pub requested_amount: u64,
+  /// Unix timestamp after which the order can no longer be filled
+  pub expires_at: UnixTimestamp,
+  /// Fills a single order of the maker. Has to be unused and within `ORDER_NONCE_WINDOW` of the maker's lowest unused nonce
+  pub nonce: u64,
+}
+
+/// Signed order nonces of a maker, a pda of ['escrow', program id, maker, 'nonce']
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct OrderNonce {
+  pub key: Key,
+  pub maker: Pubkey,
+  /// Every nonce below this one is used or cancelled
+  pub nonce: u64,
+  /// Bit i is set once `nonce + i` is used
+  pub used: u128,
+}
+
+impl OrderNonce {
+  pub fn from_account_info(a: &AccountInfo) -> Result<OrderNonce, ProgramError> {
+    let order_nonce: OrderNonce = try_from_slice_checked(&a.data.borrow(), Key::OrderNonceV1)?;
+    Ok(order_nonce)
+  }
+
+  pub fn is_used(&self, nonce: u64) -> bool {
+    match nonce.checked_sub(self.nonce) {
+      Some(offset) => offset < ORDER_NONCE_WINDOW && self.used & (1 << offset) != 0,
+      None => true,
+    }
+  }
+
+  /// Marks `nonce` as used, the window then starts at the lowest unused nonce again
+  pub fn use_nonce(&mut self, nonce: u64) -> Result<(), ProgramError> {
+    if self.is_used(nonce) {
+      return Err(EscrowError::OrderNonceUsed.into());
+    }
+    let offset = nonce - self.nonce;
+    if offset >= ORDER_NONCE_WINDOW {
+      return Err(EscrowError::OrderNonceOutsideWindow.into());
+    }
+    let used = self.used | 1 << offset;
+    let lowest_unused = self
+      .nonce
+      .checked_add(used.trailing_ones() as u64)
+      .ok_or(EscrowError::AmountOverflow)?;
+    self.used = used;
+    self.cancel_below(lowest_unused);
+    Ok(())
+  }
+
+  /// Cancels every nonce below `nonce`
+  pub fn cancel_below(&mut self, nonce: u64) {
+    let shift = nonce.saturating_sub(self.nonce);
+    self.used = if shift < ORDER_NONCE_WINDOW {
+      self.used >> shift
+    } else {
+      0
+    };
+    self.nonce = self.nonce.max(nonce);
+  }
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub enum ArbitrationStatus {
+  /// Tokens wait for the buyer to release them
+  Funded,
+  /// Only the arbiter can settle the escrow now, unless the buyer still releases it
+  Disputed,
+}
+
+/// Goods and services escrow, a pda of ['escrow', program id, buyer, 'arbitrated', seed]
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct ArbitratedEscrow {
+  pub key: Key,
+  pub buyer: Pubkey,
+  pub seller: Pubkey,
+  /// Splits the vault between buyer and seller once a dispute is opened
+  pub arbiter: Pubkey,
+  pub mint: Pubkey,
+  /// Temp token account holding the buyer's tokens, owned by the vault authority
+  pub vault: Pubkey,
+  pub seed: u64,
+  /// Bump seed of the vault authority PDA (['escrow', program id, escrow account])
+  pub vault_authority_bump_seed: u8,
+  pub status: ArbitrationStatus,
+}
+
+impl ArbitratedEscrow {
+  pub fn from_account_info(a: &AccountInfo) -> Result<ArbitratedEscrow, ProgramError> {
+    let escrow: ArbitratedEscrow =
+      try_from_slice_checked(&a.data.borrow(), Key::ArbitratedEscrowV1)?;
+    Ok(escrow)
+  }
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct VestingTranche {
+  /// Unix timestamp from which `amount` can be claimed
+  pub timestamp: UnixTimestamp,
+  pub amount: u64,
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub enum VestingSchedule {
+  /// Fixed amounts unlocking at given times
+  Tranches(Vec<VestingTranche>),
+  /// Nothing vests before `start + cliff`, then the vault vests linearly until `start + duration`
+  Linear {
+    start: UnixTimestamp,
+    cliff: i64,
+    duration: i64,
+  },
+}
+
+/// Tokens released to a beneficiary over time, a pda of ['escrow', program id, funder, 'vesting', seed]
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct VestingEscrow {
+  pub key: Key,
+  pub funder: Pubkey,
+  pub beneficiary: Pubkey,
+  /// May claw back what has not vested yet
+  pub revocation_authority: Option<Pubkey>,
+  pub mint: Pubkey,
+  /// Temp token account holding the grant, owned by the vault authority
+  pub vault: Pubkey,
+  pub seed: u64,
+  /// Bump seed of the vault authority PDA (['escrow', program id, escrow account])
+  pub vault_authority_bump_seed: u8,
+  pub total_amount: u64,
+  pub claimed_amount: u64,
+  pub schedule: VestingSchedule,
+}
+
+impl VestingEscrow {
+  pub fn from_account_info(a: &AccountInfo) -> Result<VestingEscrow, ProgramError> {
+    let escrow: VestingEscrow = try_from_slice_checked(&a.data.borrow(), Key::VestingEscrowV1)?;
+    Ok(escrow)
+  }
+
+  /// Amount vested at `now`, claimed or not
+  pub fn vested_amount(&self, now: UnixTimestamp) -> Result<u64, ProgramError> {
+    let vested = match &self.schedule {
+      VestingSchedule::Tranches(tranches) => tranches
+        .iter()
+        .filter(|tranche| tranche.timestamp <= now)
+        .try_fold(0u64, |vested, tranche| vested.checked_add(tranche.amount))
+        .ok_or(EscrowError::AmountOverflow)?,
+      VestingSchedule::Linear {
+        start,
+        cliff,
+        duration,
+      } => {
+        let elapsed = now.saturating_sub(*start);
+        if elapsed < *cliff {
+          0
+        } else if elapsed >= *duration {
+          self.total_amount
+        } else {
+          let vested = (self.total_amount as u128)
+            .checked_mul(elapsed as u128)
+            .and_then(|n| n.checked_div(*duration as u128))
+            .ok_or(EscrowError::AmountOverflow)?;
+          u64::try_from(vested).map_err(|_| EscrowError::AmountOverflow)?
+        }
+      }
+    };
+    Ok(vested.min(self.total_amount))
+  }
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub enum HashAlgorithm {
+  Sha256,
+  Keccak256,
+}
+
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct HashLockedEscrow {
+  pub key: Key,
+  pub maker: Pubkey,
+  /// Receives the vault when revealing the secret before the timeout
+  pub taker: Pubkey,
+  pub mint: Pubkey,
+  /// Temp token account holding the maker's tokens, owned by the vault authority
+  pub vault: Pubkey,
+  pub seed: u64,
+  /// Bump seed of the vault authority PDA (['escrow', program id, escrow account])
+  pub vault_authority_bump_seed: u8,
+  pub hash_algorithm: HashAlgorithm,
+  pub hash: [u8; 32],
+  /// The maker can refund the escrow from this time on
+  pub timeout: UnixTimestamp,
+}
+
+impl HashLockedEscrow {
+  pub fn from_account_info(a: &AccountInfo) -> Result<HashLockedEscrow, ProgramError> {
+    let escrow: HashLockedEscrow =
+      try_from_slice_checked(&a.data.borrow(), Key::HashLockedEscrowV1)?;
+    Ok(escrow)
+  }
+
+  pub fn is_expired(&self, now: UnixTimestamp) -> bool {
+    now >= self.timeout
+  }
+}
+
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct Auction {
+  pub key: Key,
+  pub seller: Pubkey,
+  /// Mint of the auctioned NFT
+  pub mint: Pubkey,
+  /// Temp token account holding the NFT, owned by the vault authority
+  pub vault: Pubkey,
+  /// Mint bids are paid in
+  pub bid_mint: Pubkey,
+  /// Token account holding the top bid, owned by the vault authority
+  pub bid_vault: Pubkey,
+  pub seed: u64,
+  /// Bump seed of the vault authority PDA (['escrow', program id, escrow account])
+  pub vault_authority_bump_seed: u8,
+  /// Lowest bid accepted
+  pub reserve_price: u64,
+  pub end_time: UnixTimestamp,
+  /// A bid this close to the end pushes the end back to this long after the bid
+  pub extension_window: i64,
+  pub top_bidder: Option<Pubkey>,
+  pub top_bid: u64,
+  /// Outbid amounts still in the bid vault, waiting for their bidders to withdraw them
+  pub pending_refunds: u64,
+  /// Seller is paid and the NFT delivered. The account only stays open until the pending refunds are withdrawn
+  pub settled: bool,
+}
+
+impl Auction {
+  pub fn from_account_info(a: &AccountInfo) -> Result<Auction, ProgramError> {
+    let auction: Auction = try_from_slice_checked(&a.data.borrow(), Key::AuctionV1)?;
+    Ok(auction)
+  }
+
+  pub fn has_ended(&self, now: UnixTimestamp) -> bool {
+    now >= self.end_time
+  }
+}
+
+/// What an auction owes a bidder it outbid, a pda of ['escrow', program id, auction, 'refund', bidder]
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct BidRefund {
+  pub key: Key,
+  pub auction: Pubkey,
+  pub bidder: Pubkey,
+  pub amount: u64,
+}
+
+impl BidRefund {
+  pub fn from_account_info(a: &AccountInfo) -> Result<BidRefund, ProgramError> {
+    let refund: BidRefund = try_from_slice_checked(&a.data.borrow(), Key::BidRefundV1)?;
+    Ok(refund)
+  }
+}
+
+/// Open escrows of a mint pair. Pages are chained once the previous one is full
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct OrderBookPage {
+  pub key: Key,
+  pub offered_mint: Pubkey,
+  pub requested_mint: Pubkey,
+  pub page_index: u32,
+  /// Paid rent for the page and gets it back once the empty page is closed
+  pub payer: Pubkey,
+  /// Page created after this one, with the following index
+  pub next_page: Option<Pubkey>,
+  /// At most MAX_ORDER_BOOK_PAGE_ENTRIES escrows, in no particular order
+  pub escrows: Vec<Pubkey>,
+}
+
+impl OrderBookPage {
+  pub fn from_account_info(a: &AccountInfo) -> Result<OrderBookPage, ProgramError> {
+    let page: OrderBookPage = try_from_slice_checked(&a.data.borrow(), Key::OrderBookPageV1)?;
+    Ok(page)
+  }
+
+  pub fn is_full(&self) -> bool {
+    self.escrows.len() >= MAX_ORDER_BOOK_PAGE_ENTRIES
+  }
+}
+
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct RingParticipant {
+  pub party: Pubkey,
+  /// Mint the party deposits, received by the next party in the ring
+  pub mint: Pubkey,
+  pub amount: u64,
+  /// Temp token account holding the deposit, owned by the vault authority. None until the party deposits
+  pub vault: Option<Pubkey>,
+  /// The party's account receiving the previous party's deposit, set when depositing
+  pub receive_account: Option<Pubkey>,
+}
+
+/// Cyclic swap where each participant pays the next one and is paid by the previous one
+#[repr(C)]
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug)]
+pub struct RingSwap {
+  pub key: Key,
+  /// Paid rent for the ring account and gets it back at settlement, or by closing the ring once no deposit is left
+  pub creator: Pubkey,
+  pub seed: u64,
+  /// Bump seed of the vault authority PDA (['escrow', program id, escrow account])
+  pub vault_authority_bump_seed: u8,
+  /// Between MIN_RING_PARTIES and MAX_RING_PARTIES distinct parties, in ring order
+  pub participants: Vec<RingParticipant>,
+}
+
+impl RingSwap {
+  pub fn from_account_info(a: &AccountInfo) -> Result<RingSwap, ProgramError> {
+    let ring: RingSwap = try_from_slice_checked(&a.data.borrow(), Key::RingSwapV1)?;
+    Ok(ring)
+  }
+
+  /// Index of the participant preceding `index`, whose deposit it receives
+  pub fn previous(&self, index: usize) -> usize {
+    (index + self.participants.len() - 1) % self.participants.len()
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self
+      .participants
+      .iter()
+      .all(|participant| participant.vault.is_some())
+  }
+}
+
+// Helpers
+fn pack_option_i64(src: &Option<i64>, dst: &mut [u8; 12]) {
+  let (tag, body) = mut_array_refs![dst, 4, 8];
+  match src {
+    Some(value) => {
+      *tag = [1, 0, 0, 0];
+      *body = value.to_le_bytes();
+    }
+    None => {
+      *tag = [0; 4];
+    }
+  }
+}
+fn unpack_option_i64(src: &[u8; 12]) -> Result<Option<i64>, ProgramError> {
+  let (tag, body) = array_refs![src, 4, 8];
+  match *tag {
+    [0, 0, 0, 0] => Ok(None),
+    [1, 0, 0, 0] => Ok(Some(i64::from_le_bytes(*body))),
+    _ => Err(ProgramError::InvalidAccountData),
+  }
+}
+fn pack_option_dutch_pricing(src: &Option<DutchPricing>, dst: &mut [u8; 36]) {
+  let (tag, start_price, end_price, start_time, end_time) = mut_array_refs![dst, 4, 8, 8, 8, 8];
+  match src {
+    Some(dutch_pricing) => {
+      *tag = [1, 0, 0, 0];
+      *start_price = dutch_pricing.start_price.to_le_bytes();
+      *end_price = dutch_pricing.end_price.to_le_bytes();
+      *start_time = dutch_pricing.start_time.to_le_bytes();
+      *end_time = dutch_pricing.end_time.to_le_bytes();
+    }
+    None => {
+      *tag = [0; 4];
+    }
+  }
+}
+fn unpack_option_dutch_pricing(src: &[u8; 36]) -> Result<Option<DutchPricing>, ProgramError> {
+  let (tag, start_price, end_price, start_time, end_time) = array_refs![src, 4, 8, 8, 8, 8];
+  match *tag {
+    [0, 0, 0, 0] => Ok(None),
+    [1, 0, 0, 0] => Ok(Some(DutchPricing {
+      start_price: u64::from_le_bytes(*start_price),
+      end_price: u64::from_le_bytes(*end_price),
+      start_time: i64::from_le_bytes(*start_time),
+      end_time: i64::from_le_bytes(*end_time),
+    })),
+    _ => Err(ProgramError::InvalidAccountData),
+  }
+}
+fn pack_option_key(src: &Option<Pubkey>, dst: &mut [u8; 36]) {
+  let (tag, body) = mut_array_refs![dst, 4, 32];
+  match src {
+    Some(key) => {
+      *tag = [1, 0, 0, 0];
+      body.copy_from_slice(key.as_ref());
+    }
+    None => {
+      *tag = [0; 4];
+    }
+  }
+}
+fn unpack_option_key(src: &[u8; 36]) -> Result<Option<Pubkey>, ProgramError> {
+  let (tag, body) = array_refs![src, 4, 32];
+  match *tag {
+    [0, 0, 0, 0] => Ok(None),
+    [1, 0, 0, 0] => Ok(Some(Pubkey::new_from_array(*body))),
+    _ => Err(ProgramError::InvalidAccountData),
   }
 }
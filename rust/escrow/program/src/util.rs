@@ -0,0 +1,75 @@
+use {
+  crate::{error::EscrowError, state::Key},
+  borsh::BorshDeserialize,
+  solana_program::{
+    account_info::AccountInfo,
+    borsh::try_from_slice_unchecked,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+  },
+  std::convert::TryInto,
+};
+
+/// Native program verifying ed25519 signatures, not exported by this version of solana-program
+pub mod ed25519_program {
+  solana_program::declare_id!("Ed25519SigVerify111111111111111111111111111");
+}
+
+#[inline(always)]
+pub fn create_or_allocate_account_raw<'a>(
+  program_id: Pubkey,
+  new_account_info: &AccountInfo<'a>,
+  rent_sysvar_info: &AccountInfo<'a>,
+  system_program_info: &AccountInfo<'a>,
+  payer_info: &AccountInfo<'a>,
+  size: usize,
+  signer_seeds: &[&[u8]],
+) -> ProgramResult {
+  let rent = &Rent::from_account_info(rent_sysvar_info)?;
+  let required_lamports = rent
+    .minimum_balance(size)
+    .max(1)
+    .saturating_sub(new_account_info.lamports());
+
+  if required_lamports > 0 {
+    invoke(
+      &system_instruction::transfer(&payer_info.key, new_account_info.key, required_lamports),
+      &[
+        payer_info.clone(),
+        new_account_info.clone(),
+        system_program_info.clone(),
+      ],
+    )?;
+  }
+
+  let accounts = &[new_account_info.clone(), system_program_info.clone()];
+
+  invoke_signed(
+    &system_instruction::allocate(new_account_info.key, size.try_into().unwrap()),
+    accounts,
+    &[&signer_seeds],
+  )?;
+
+  invoke_signed(
+    &system_instruction::assign(new_account_info.key, &program_id),
+    accounts,
+    &[&signer_seeds],
+  )?;
+  Ok(())
+}
+
+pub fn try_from_slice_checked<T: BorshDeserialize>(
+  data: &[u8],
+  data_type: Key,
+) -> Result<T, ProgramError> {
+  if data.is_empty() || data[0] != data_type as u8 {
+    return Err(EscrowError::DataTypeMismatch.into());
+  }
+
+  let result: T = try_from_slice_unchecked(data)?;
+  Ok(result)
+}
@@ -0,0 +1,309 @@
+use {
+  borsh::BorshSerialize,
+  escrow::{
+    error::EscrowError,
+    instruction::{EscrowInstruction, InitArbitratedEscrowArgs, ResolveDisputeArgs},
+    pda::find_arbitrated_escrow_address,
+    state::{ArbitratedEscrow, ArbitrationStatus, Key},
+  },
+  solana_program::{program_error::ProgramError, pubkey::Pubkey, system_program},
+};
+
+mod common;
+
+use common::{
+  any_account, assert_escrow_error, process, rent_account, token_account, token_program_account,
+  Fixture, TestAccount,
+};
+
+impl Fixture {
+  /// Arbitrated escrow of the fixture's initializer as buyer, with the temp token account as vault
+  fn arbitrated_escrow_account(
+    &self,
+    seller: Pubkey,
+    arbiter: Pubkey,
+    status: ArbitrationStatus,
+  ) -> TestAccount {
+    let escrow_info = ArbitratedEscrow {
+      key: Key::ArbitratedEscrowV1,
+      buyer: self.initializer,
+      seller,
+      arbiter,
+      mint: self.offered_mint,
+      vault: self.temp_token_account,
+      seed: 0,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      status,
+    };
+    TestAccount::new(
+      self.escrow,
+      self.program_id,
+      escrow_info.try_to_vec().unwrap(),
+    )
+  }
+}
+
+fn init_arbitrated_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  let (escrow, _) = find_arbitrated_escrow_address(&fixture.program_id, &fixture.initializer, 0);
+  vec![
+    fixture.initializer_account(),
+    token_account(
+      fixture.temp_token_account,
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    TestAccount::new(escrow, system_program::id(), vec![]),
+    rent_account(),
+    token_program_account(),
+    any_account(),
+    fixture.config_account(),
+  ]
+}
+
+fn init_arbitrated_instruction(seller: Pubkey, arbiter: Pubkey) -> EscrowInstruction {
+  EscrowInstruction::InitArbitratedEscrow(InitArbitratedEscrowArgs {
+    seed: 0,
+    seller,
+    arbiter,
+  })
+}
+
+#[test]
+fn init_arbitrated_rejects_buyer_as_arbiter() {
+  let fixture = Fixture::new();
+  let mut accounts = init_arbitrated_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_arbitrated_instruction(Pubkey::new_unique(), fixture.initializer),
+  );
+  assert_escrow_error(result, EscrowError::ArbiterIsParty);
+}
+
+#[test]
+fn init_arbitrated_rejects_seller_as_arbiter() {
+  let fixture = Fixture::new();
+  let mut accounts = init_arbitrated_accounts(&fixture);
+  let seller = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_arbitrated_instruction(seller, seller),
+  );
+  assert_escrow_error(result, EscrowError::ArbiterIsParty);
+}
+
+fn release_accounts(fixture: &Fixture, seller: Pubkey) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    fixture.temp_token_account(),
+    token_account(Pubkey::new_unique(), fixture.offered_mint, seller),
+    fixture.arbitrated_escrow_account(seller, Pubkey::new_unique(), ArbitrationStatus::Funded),
+    token_program_account(),
+    fixture.vault_authority_account(),
+  ]
+}
+
+#[test]
+fn release_requires_buyer() {
+  let fixture = Fixture::new();
+  let seller = Pubkey::new_unique();
+  let mut accounts = release_accounts(&fixture, seller);
+  accounts[0] = TestAccount::new(seller, system_program::id(), vec![]).signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Release(),
+  );
+  assert_escrow_error(result, EscrowError::NotEscrowParty);
+}
+
+#[test]
+fn release_requires_buyer_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = release_accounts(&fixture, Pubkey::new_unique());
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Release(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn release_rejects_account_of_another_seller() {
+  let fixture = Fixture::new();
+  let mut accounts = release_accounts(&fixture, Pubkey::new_unique());
+  accounts[2] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Release(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn release_rejects_wrong_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = release_accounts(&fixture, Pubkey::new_unique());
+  accounts[1] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    fixture.vault_authority,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Release(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn release_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = release_accounts(&fixture, Pubkey::new_unique());
+  accounts[5] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Release(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+fn open_dispute_accounts(fixture: &Fixture, party: Pubkey, seller: Pubkey) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(party, system_program::id(), vec![]).signer(),
+    fixture.arbitrated_escrow_account(seller, Pubkey::new_unique(), ArbitrationStatus::Funded),
+  ]
+}
+
+#[test]
+fn open_dispute_requires_party() {
+  let fixture = Fixture::new();
+  let mut accounts = open_dispute_accounts(&fixture, Pubkey::new_unique(), Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::OpenDispute(),
+  );
+  assert_escrow_error(result, EscrowError::NotEscrowParty);
+}
+
+#[test]
+fn open_dispute_requires_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = open_dispute_accounts(&fixture, fixture.initializer, Pubkey::new_unique());
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::OpenDispute(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn open_dispute_rejects_escrow_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = open_dispute_accounts(&fixture, fixture.initializer, Pubkey::new_unique());
+  accounts[1].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::OpenDispute(),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+fn resolve_accounts(
+  fixture: &Fixture,
+  arbiter: Pubkey,
+  status: ArbitrationStatus,
+) -> Vec<TestAccount> {
+  let seller = Pubkey::new_unique();
+  vec![
+    TestAccount::new(arbiter, system_program::id(), vec![]).signer(),
+    fixture.temp_token_account(),
+    token_account(
+      Pubkey::new_unique(),
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    token_account(Pubkey::new_unique(), fixture.offered_mint, seller),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    fixture.arbitrated_escrow_account(seller, arbiter, status),
+    token_program_account(),
+    fixture.vault_authority_account(),
+  ]
+}
+
+fn resolve_instruction(buyer_amount: u64) -> EscrowInstruction {
+  EscrowInstruction::ResolveDispute(ResolveDisputeArgs { buyer_amount })
+}
+
+#[test]
+fn resolve_rejects_split_above_vault_balance() {
+  let fixture = Fixture::new();
+  let mut accounts = resolve_accounts(&fixture, Pubkey::new_unique(), ArbitrationStatus::Disputed);
+  let result = process(&fixture.program_id, &mut accounts, resolve_instruction(101));
+  assert_escrow_error(result, EscrowError::SplitAboveVaultBalance);
+}
+
+#[test]
+fn resolve_requires_dispute() {
+  let fixture = Fixture::new();
+  let mut accounts = resolve_accounts(&fixture, Pubkey::new_unique(), ArbitrationStatus::Funded);
+  let result = process(&fixture.program_id, &mut accounts, resolve_instruction(30));
+  assert_escrow_error(result, EscrowError::InvalidEscrowStatus);
+}
+
+#[test]
+fn resolve_requires_arbiter() {
+  let fixture = Fixture::new();
+  let mut accounts = resolve_accounts(&fixture, Pubkey::new_unique(), ArbitrationStatus::Disputed);
+  accounts[0] = any_account().signer();
+  let result = process(&fixture.program_id, &mut accounts, resolve_instruction(30));
+  assert_escrow_error(result, EscrowError::ArbiterMismatch);
+}
+
+#[test]
+fn resolve_requires_arbiter_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = resolve_accounts(&fixture, Pubkey::new_unique(), ArbitrationStatus::Disputed);
+  accounts[0].is_signer = false;
+  let result = process(&fixture.program_id, &mut accounts, resolve_instruction(30));
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn resolve_rejects_accounts_of_other_owners() {
+  let fixture = Fixture::new();
+  for &index in &[2, 3] {
+    let mut accounts =
+      resolve_accounts(&fixture, Pubkey::new_unique(), ArbitrationStatus::Disputed);
+    accounts[index] = token_account(
+      Pubkey::new_unique(),
+      fixture.offered_mint,
+      Pubkey::new_unique(),
+    );
+    let result = process(&fixture.program_id, &mut accounts, resolve_instruction(30));
+    assert_eq!(result, Err(ProgramError::InvalidAccountData));
+  }
+}
+
+#[test]
+fn resolve_rejects_another_rent_receiver() {
+  let fixture = Fixture::new();
+  let mut accounts = resolve_accounts(&fixture, Pubkey::new_unique(), ArbitrationStatus::Disputed);
+  accounts[4] = any_account();
+  let result = process(&fixture.program_id, &mut accounts, resolve_instruction(30));
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
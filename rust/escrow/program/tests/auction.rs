@@ -0,0 +1,403 @@
+use {
+  borsh::BorshSerialize,
+  escrow::{
+    error::EscrowError,
+    instruction::{EscrowInstruction, InitAuctionArgs, PlaceBidArgs},
+    pda::{find_auction_address, find_bid_refund_address},
+    state::{Auction, BidRefund, Key, AUCTION_LEN},
+  },
+  solana_program::{
+    clock::UnixTimestamp, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+    system_program,
+  },
+  spl_token::state::Account as TokenAccount,
+};
+
+mod common;
+
+use common::{
+  any_account, assert_escrow_error, clock_account, clock_account_at, mint_account, process,
+  rent_account, token_account, token_program_account, Fixture, TestAccount,
+};
+
+fn init_auction_accounts(fixture: &Fixture, nft_mint: TestAccount) -> Vec<TestAccount> {
+  let (auction, _) = find_auction_address(&fixture.program_id, &fixture.initializer, 0);
+  let mut bid_vault = token_account(
+    Pubkey::new_unique(),
+    fixture.requested_mint,
+    fixture.initializer,
+  );
+  let mut bid_vault_info = TokenAccount::unpack(&bid_vault.data).unwrap();
+  bid_vault_info.amount = 0;
+  TokenAccount::pack(bid_vault_info, &mut bid_vault.data).unwrap();
+  let mut vault = token_account(
+    fixture.temp_token_account,
+    fixture.offered_mint,
+    fixture.initializer,
+  );
+  let mut vault_info = TokenAccount::unpack(&vault.data).unwrap();
+  vault_info.amount = 1;
+  TokenAccount::pack(vault_info, &mut vault.data).unwrap();
+  vec![
+    fixture.initializer_account(),
+    vault,
+    bid_vault,
+    TestAccount::new(auction, system_program::id(), vec![]),
+    rent_account(),
+    token_program_account(),
+    any_account(),
+    fixture.config_account(),
+    nft_mint,
+    clock_account(),
+  ]
+}
+
+fn init_auction_instruction() -> EscrowInstruction {
+  EscrowInstruction::InitAuction(InitAuctionArgs {
+    seed: 0,
+    reserve_price: 10,
+    end_time: 100,
+    extension_window: 30,
+  })
+}
+
+#[test]
+fn init_auction_rejects_fungible_token() {
+  let fixture = Fixture::new();
+  for &(supply, decimals) in &[(2, 0), (1, 6)] {
+    let mut accounts = init_auction_accounts(
+      &fixture,
+      mint_account(fixture.offered_mint, supply, decimals),
+    );
+    let result = process(
+      &fixture.program_id,
+      &mut accounts,
+      init_auction_instruction(),
+    );
+    assert_escrow_error(result, EscrowError::NotAnNft);
+  }
+}
+
+#[test]
+fn init_auction_checks_nft_mint_account() {
+  let fixture = Fixture::new();
+  let mut accounts = init_auction_accounts(&fixture, mint_account(Pubkey::new_unique(), 1, 0));
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_auction_instruction(),
+  );
+  assert_escrow_error(result, EscrowError::OfferedMintMismatch);
+}
+
+#[test]
+fn init_auction_rejects_empty_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = init_auction_accounts(&fixture, mint_account(fixture.offered_mint, 1, 0));
+  let mut vault_info = TokenAccount::unpack(&accounts[1].data).unwrap();
+  vault_info.amount = 0;
+  TokenAccount::pack(vault_info, &mut accounts[1].data).unwrap();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_auction_instruction(),
+  );
+  assert_escrow_error(result, EscrowError::AuctionVaultEmpty);
+}
+
+#[test]
+fn init_auction_rejects_end_time_in_past() {
+  let fixture = Fixture::new();
+  for &now in &[100, 101] {
+    let mut accounts = init_auction_accounts(&fixture, mint_account(fixture.offered_mint, 1, 0));
+    accounts[9] = clock_account_at(now);
+    let result = process(
+      &fixture.program_id,
+      &mut accounts,
+      init_auction_instruction(),
+    );
+    assert_escrow_error(result, EscrowError::TimeoutInPast);
+  }
+}
+
+impl Fixture {
+  /// Auction of the temp token account's NFT by the fixture's initializer, for bids in the requested mint
+  fn auction(&self) -> Auction {
+    Auction {
+      key: Key::AuctionV1,
+      seller: self.initializer,
+      mint: self.offered_mint,
+      vault: self.temp_token_account,
+      bid_mint: self.requested_mint,
+      bid_vault: self.receive_token_account,
+      seed: 0,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      reserve_price: 10,
+      end_time: 100,
+      extension_window: 30,
+      top_bidder: None,
+      top_bid: 0,
+      pending_refunds: 0,
+      settled: false,
+    }
+  }
+
+  fn pack_auction(&self, auction_info: Auction) -> TestAccount {
+    let mut data = auction_info.try_to_vec().unwrap();
+    data.resize(AUCTION_LEN, 0);
+    TestAccount::new(self.escrow, self.program_id, data)
+  }
+
+  fn bid_vault_account(&self) -> TestAccount {
+    token_account(
+      self.receive_token_account,
+      self.requested_mint,
+      self.vault_authority,
+    )
+  }
+
+  fn bid_refund_account(&self, bidder: Pubkey, amount: u64) -> TestAccount {
+    let (refund, _) = find_bid_refund_address(&self.program_id, &self.escrow, &bidder);
+    let refund_info = BidRefund {
+      key: Key::BidRefundV1,
+      auction: self.escrow,
+      bidder,
+      amount,
+    };
+    TestAccount::new(refund, self.program_id, refund_info.try_to_vec().unwrap())
+  }
+}
+
+fn outbid_auction(fixture: &Fixture, top_bidder: Pubkey) -> Auction {
+  Auction {
+    top_bidder: Some(top_bidder),
+    top_bid: 50,
+    ..fixture.auction()
+  }
+}
+
+fn place_bid_accounts(
+  fixture: &Fixture,
+  auction_info: Auction,
+  now: UnixTimestamp,
+) -> Vec<TestAccount> {
+  let bidder = Pubkey::new_unique();
+  let previous_refund = match auction_info.top_bidder {
+    Some(previous_bidder) => fixture.bid_refund_account(previous_bidder, 0),
+    None => any_account(),
+  };
+  vec![
+    TestAccount::new(bidder, system_program::id(), vec![]).signer(),
+    token_account(Pubkey::new_unique(), fixture.requested_mint, bidder),
+    fixture.bid_vault_account(),
+    fixture.bid_refund_account(bidder, 0),
+    previous_refund,
+    fixture.pack_auction(auction_info),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account_at(now),
+    rent_account(),
+    any_account(),
+  ]
+}
+
+fn place_bid_instruction(amount: u64) -> EscrowInstruction {
+  EscrowInstruction::PlaceBid(PlaceBidArgs { amount })
+}
+
+#[test]
+fn bid_rejects_wrong_previous_refund_account() {
+  let fixture = Fixture::new();
+  let mut accounts =
+    place_bid_accounts(&fixture, outbid_auction(&fixture, Pubkey::new_unique()), 0);
+  accounts[4] = fixture.bid_refund_account(Pubkey::new_unique(), 0);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    place_bid_instruction(51),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBidRefundKey);
+}
+
+#[test]
+fn bid_rejects_refund_account_of_another_bidder() {
+  let fixture = Fixture::new();
+  let mut accounts = place_bid_accounts(&fixture, fixture.auction(), 0);
+  accounts[3] = fixture.bid_refund_account(Pubkey::new_unique(), 0);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    place_bid_instruction(10),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBidRefundKey);
+}
+
+#[test]
+fn bid_must_beat_reserve_and_top_bid() {
+  let fixture = Fixture::new();
+  let mut accounts = place_bid_accounts(&fixture, fixture.auction(), 0);
+  let result = process(&fixture.program_id, &mut accounts, place_bid_instruction(9));
+  assert_escrow_error(result, EscrowError::BidTooLow);
+
+  let mut accounts =
+    place_bid_accounts(&fixture, outbid_auction(&fixture, Pubkey::new_unique()), 0);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    place_bid_instruction(50),
+  );
+  assert_escrow_error(result, EscrowError::BidTooLow);
+}
+
+#[test]
+fn bid_rejects_after_end() {
+  let fixture = Fixture::new();
+  let mut accounts = place_bid_accounts(&fixture, fixture.auction(), 100);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    place_bid_instruction(10),
+  );
+  assert_escrow_error(result, EscrowError::AuctionEnded);
+}
+
+fn settle_accounts(
+  fixture: &Fixture,
+  auction_info: Auction,
+  now: UnixTimestamp,
+) -> Vec<TestAccount> {
+  let winner = auction_info.top_bidder.unwrap_or(fixture.initializer);
+  vec![
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    token_account(
+      Pubkey::new_unique(),
+      fixture.requested_mint,
+      fixture.initializer,
+    ),
+    token_account(Pubkey::new_unique(), fixture.offered_mint, winner),
+    fixture.temp_token_account(),
+    fixture.bid_vault_account(),
+    fixture.pack_auction(auction_info),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account_at(now),
+  ]
+}
+
+#[test]
+fn settle_rejects_before_end() {
+  let fixture = Fixture::new();
+  let mut accounts = settle_accounts(&fixture, outbid_auction(&fixture, Pubkey::new_unique()), 99);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleAuction(),
+  );
+  assert_escrow_error(result, EscrowError::AuctionNotEnded);
+}
+
+#[test]
+fn settle_rejects_nft_account_not_of_seller_without_bids() {
+  let fixture = Fixture::new();
+  let mut accounts = settle_accounts(&fixture, fixture.auction(), 100);
+  accounts[2] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleAuction(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn settle_rejects_nft_account_not_of_winner() {
+  let fixture = Fixture::new();
+  let mut accounts = settle_accounts(
+    &fixture,
+    outbid_auction(&fixture, Pubkey::new_unique()),
+    100,
+  );
+  accounts[2] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    fixture.initializer,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleAuction(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+fn withdraw_bid_refund_accounts(
+  fixture: &Fixture,
+  bidder: Pubkey,
+  auction_info: Auction,
+) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(bidder, system_program::id(), vec![]).signer(),
+    token_account(Pubkey::new_unique(), fixture.requested_mint, bidder),
+    fixture.bid_refund_account(bidder, 30),
+    fixture.bid_vault_account(),
+    fixture.pack_auction(auction_info),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    token_program_account(),
+    fixture.vault_authority_account(),
+  ]
+}
+
+#[test]
+fn top_bidder_cannot_withdraw_before_settlement() {
+  let fixture = Fixture::new();
+  let bidder = Pubkey::new_unique();
+  let auction_info = Auction {
+    pending_refunds: 30,
+    ..outbid_auction(&fixture, bidder)
+  };
+  let mut accounts = withdraw_bid_refund_accounts(&fixture, bidder, auction_info);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawBidRefund(),
+  );
+  assert_escrow_error(result, EscrowError::TopBidderCannotWithdraw);
+}
+
+#[test]
+fn withdraw_rejects_refund_account_of_another_bidder() {
+  let fixture = Fixture::new();
+  let auction_info = Auction {
+    pending_refunds: 30,
+    ..outbid_auction(&fixture, Pubkey::new_unique())
+  };
+  let mut accounts = withdraw_bid_refund_accounts(&fixture, Pubkey::new_unique(), auction_info);
+  accounts[2] = fixture.bid_refund_account(Pubkey::new_unique(), 30);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawBidRefund(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBidRefundKey);
+}
+
+#[test]
+fn withdraw_requires_bidder_signature() {
+  let fixture = Fixture::new();
+  let auction_info = Auction {
+    pending_refunds: 30,
+    ..outbid_auction(&fixture, Pubkey::new_unique())
+  };
+  let mut accounts = withdraw_bid_refund_accounts(&fixture, Pubkey::new_unique(), auction_info);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawBidRefund(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
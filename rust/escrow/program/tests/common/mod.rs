@@ -0,0 +1,275 @@
+// Account fixtures and the stubbed runtime shared by the unit test suites, each using only part of them
+#![allow(dead_code)]
+
+use {
+  borsh::BorshSerialize,
+  escrow::{
+    error::EscrowError,
+    instruction::EscrowInstruction,
+    pda::find_config_address,
+    processor::Processor,
+    state::{Config, Escrow, Key, SideKind, MAX_CONFIG_LEN, PREFIX},
+  },
+  solana_program::{
+    account_info::AccountInfo,
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{self, clock::Clock, Sysvar},
+  },
+  spl_token::state::{Account as TokenAccount, AccountState, Mint},
+};
+
+pub struct TestAccount {
+  pub key: Pubkey,
+  pub is_signer: bool,
+  pub lamports: u64,
+  pub data: Vec<u8>,
+  pub owner: Pubkey,
+}
+
+impl TestAccount {
+  pub fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+    TestAccount {
+      key,
+      is_signer: false,
+      lamports: 1_000_000,
+      data,
+      owner,
+    }
+  }
+
+  pub fn signer(mut self) -> Self {
+    self.is_signer = true;
+    self
+  }
+}
+
+pub struct Fixture {
+  pub program_id: Pubkey,
+  pub initializer: Pubkey,
+  pub temp_token_account: Pubkey,
+  pub receive_token_account: Pubkey,
+  pub escrow: Pubkey,
+  pub vault_authority: Pubkey,
+  pub offered_mint: Pubkey,
+  pub requested_mint: Pubkey,
+  pub vault_authority_bump_seed: u8,
+}
+
+impl Fixture {
+  pub fn new() -> Self {
+    let program_id = Pubkey::new_unique();
+    let escrow = Pubkey::new_unique();
+    let (vault_authority, vault_authority_bump_seed) = Pubkey::find_program_address(
+      &[PREFIX.as_bytes(), program_id.as_ref(), escrow.as_ref()],
+      &program_id,
+    );
+    let initializer = Pubkey::new_unique();
+    let temp_token_account = Pubkey::new_unique();
+    let receive_token_account = Pubkey::new_unique();
+    let offered_mint = Pubkey::new_unique();
+    let requested_mint = Pubkey::new_unique();
+    Fixture {
+      program_id,
+      initializer,
+      temp_token_account,
+      receive_token_account,
+      escrow,
+      vault_authority,
+      offered_mint,
+      requested_mint,
+      vault_authority_bump_seed,
+    }
+  }
+
+  pub fn escrow_account(&self) -> TestAccount {
+    self.pack_escrow(self.escrow())
+  }
+
+  pub fn escrow(&self) -> Escrow {
+    Escrow {
+      is_initialized: true,
+      initializer_pubkey: self.initializer,
+      temp_token_account_pubkey: self.temp_token_account,
+      initializer_token_to_receive_account_pubkey: self.receive_token_account,
+      expected_amount: 10,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      seed: 0,
+      offered_mint: self.offered_mint,
+      requested_mint: self.requested_mint,
+      offered_amount: 100,
+      filled_amount: 0,
+      expires_at: None,
+      allowed_taker: None,
+      offered_kind: SideKind::Token,
+      requested_kind: SideKind::Token,
+      dutch_pricing: None,
+    }
+  }
+
+  pub fn pack_escrow(&self, escrow_info: Escrow) -> TestAccount {
+    let mut data = vec![0; Escrow::LEN];
+    Escrow::pack(escrow_info, &mut data).unwrap();
+    TestAccount::new(self.escrow, self.program_id, data)
+  }
+
+  pub fn uninitialized_escrow_account(&self) -> TestAccount {
+    TestAccount::new(self.escrow, self.program_id, vec![0; Escrow::LEN])
+  }
+
+  pub fn initializer_account(&self) -> TestAccount {
+    TestAccount::new(self.initializer, system_program::id(), vec![]).signer()
+  }
+
+  pub fn temp_token_account(&self) -> TestAccount {
+    token_account(
+      self.temp_token_account,
+      self.offered_mint,
+      self.vault_authority,
+    )
+  }
+
+  pub fn receive_token_account(&self) -> TestAccount {
+    token_account(
+      self.receive_token_account,
+      self.requested_mint,
+      self.initializer,
+    )
+  }
+
+  pub fn vault_authority_account(&self) -> TestAccount {
+    TestAccount::new(self.vault_authority, system_program::id(), vec![])
+  }
+
+  pub fn config_account(&self) -> TestAccount {
+    let (config, _) = find_config_address(&self.program_id);
+    TestAccount::new(config, system_program::id(), vec![])
+  }
+
+  pub fn initialized_config_account(&self, admin: Pubkey) -> TestAccount {
+    self.pack_config(Config {
+      key: Key::ConfigV1,
+      admin,
+      fee_basis_points: 100,
+      paused: false,
+      token_metadata_program: None,
+      fee_recipients: vec![],
+    })
+  }
+
+  pub fn paused_config_account(&self) -> TestAccount {
+    self.pack_config(Config {
+      key: Key::ConfigV1,
+      admin: Pubkey::new_unique(),
+      fee_basis_points: 0,
+      paused: true,
+      token_metadata_program: None,
+      fee_recipients: vec![],
+    })
+  }
+
+  pub fn pack_config(&self, config: Config) -> TestAccount {
+    let (config_key, _) = find_config_address(&self.program_id);
+    let mut data = config.try_to_vec().unwrap();
+    data.resize(MAX_CONFIG_LEN, 0);
+    TestAccount::new(config_key, self.program_id, data)
+  }
+}
+
+pub fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey) -> TestAccount {
+  let mut data = vec![0; TokenAccount::LEN];
+  TokenAccount::pack(
+    TokenAccount {
+      mint,
+      owner,
+      amount: 100,
+      delegate: COption::None,
+      state: AccountState::Initialized,
+      is_native: COption::None,
+      delegated_amount: 0,
+      close_authority: COption::None,
+    },
+    &mut data,
+  )
+  .unwrap();
+  TestAccount::new(key, spl_token::id(), data)
+}
+
+pub fn mint_account(key: Pubkey, supply: u64, decimals: u8) -> TestAccount {
+  let mut data = vec![0; Mint::LEN];
+  Mint::pack(
+    Mint {
+      mint_authority: COption::None,
+      supply,
+      decimals,
+      is_initialized: true,
+      freeze_authority: COption::None,
+    },
+    &mut data,
+  )
+  .unwrap();
+  TestAccount::new(key, spl_token::id(), data)
+}
+
+pub fn any_account() -> TestAccount {
+  TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![])
+}
+
+pub fn token_program_account() -> TestAccount {
+  TestAccount::new(spl_token::id(), Pubkey::default(), vec![])
+}
+
+pub fn clock_account() -> TestAccount {
+  clock_account_at(0)
+}
+
+pub fn clock_account_at(unix_timestamp: UnixTimestamp) -> TestAccount {
+  let mut data = vec![0; Clock::size_of()];
+  // unix_timestamp is the last field of the bincode encoded clock
+  data[32..40].copy_from_slice(&unix_timestamp.to_le_bytes());
+  TestAccount::new(sysvar::clock::id(), sysvar::id(), data)
+}
+
+pub fn process(
+  program_id: &Pubkey,
+  accounts: &mut [TestAccount],
+  instruction: EscrowInstruction,
+) -> ProgramResult {
+  let account_infos: Vec<AccountInfo> = accounts
+    .iter_mut()
+    .map(|account| {
+      AccountInfo::new(
+        &account.key,
+        account.is_signer,
+        true,
+        &mut account.lamports,
+        &mut account.data,
+        &account.owner,
+        false,
+        0,
+      )
+    })
+    .collect();
+  Processor::process(
+    program_id,
+    &account_infos,
+    &instruction.try_to_vec().unwrap(),
+  )
+}
+
+pub fn assert_escrow_error(result: ProgramResult, error: EscrowError) {
+  assert_eq!(result, Err(ProgramError::Custom(error as u32)));
+}
+
+pub fn rent_account() -> TestAccount {
+  // bincode encoded Rent: lamports per byte year, exemption threshold and burn percent
+  let mut data = 3480u64.to_le_bytes().to_vec();
+  data.extend_from_slice(&2.0f64.to_le_bytes());
+  data.push(50);
+  TestAccount::new(sysvar::rent::id(), sysvar::id(), data)
+}
@@ -0,0 +1,213 @@
+use {
+  borsh::BorshSerialize,
+  escrow::{
+    error::EscrowError,
+    instruction::{ClaimWithSecretArgs, EscrowInstruction, InitHashLockedEscrowArgs},
+    pda::find_hash_locked_escrow_address,
+    state::{HashAlgorithm, HashLockedEscrow, Key},
+  },
+  solana_program::{
+    clock::UnixTimestamp, hash, keccak, program_error::ProgramError, program_pack::Pack,
+    pubkey::Pubkey, system_program,
+  },
+  spl_token::state::Account as TokenAccount,
+};
+
+mod common;
+
+use common::{
+  any_account, assert_escrow_error, clock_account_at, process, rent_account, token_account,
+  token_program_account, Fixture, TestAccount,
+};
+
+const SECRET: &[u8] = b"preimage";
+
+fn init_hash_locked_accounts(fixture: &Fixture, now: UnixTimestamp) -> Vec<TestAccount> {
+  let (escrow, _) = find_hash_locked_escrow_address(&fixture.program_id, &fixture.initializer, 0);
+  vec![
+    fixture.initializer_account(),
+    token_account(
+      fixture.temp_token_account,
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    TestAccount::new(escrow, system_program::id(), vec![]),
+    rent_account(),
+    token_program_account(),
+    any_account(),
+    fixture.config_account(),
+    clock_account_at(now),
+  ]
+}
+
+fn init_hash_locked_instruction(timeout: UnixTimestamp) -> EscrowInstruction {
+  EscrowInstruction::InitHashLockedEscrow(InitHashLockedEscrowArgs {
+    seed: 0,
+    taker: Pubkey::new_unique(),
+    hash_algorithm: HashAlgorithm::Sha256,
+    hash: hash::hash(SECRET).to_bytes(),
+    timeout,
+  })
+}
+
+#[test]
+fn init_hash_locked_rejects_past_timeout() {
+  let fixture = Fixture::new();
+  for &timeout in &[99, 100] {
+    let mut accounts = init_hash_locked_accounts(&fixture, 100);
+    let result = process(
+      &fixture.program_id,
+      &mut accounts,
+      init_hash_locked_instruction(timeout),
+    );
+    assert_escrow_error(result, EscrowError::TimeoutInPast);
+  }
+}
+
+#[test]
+fn init_hash_locked_rejects_empty_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = init_hash_locked_accounts(&fixture, 100);
+  let mut vault_info = TokenAccount::unpack(&accounts[1].data).unwrap();
+  vault_info.amount = 0;
+  TokenAccount::pack(vault_info, &mut accounts[1].data).unwrap();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_hash_locked_instruction(101),
+  );
+  assert_escrow_error(result, EscrowError::EmptyOffer);
+}
+
+impl Fixture {
+  /// Hash locked escrow of the temp token account from the fixture's initializer as maker, timing out at 100
+  fn hash_locked_escrow_account(
+    &self,
+    taker: Pubkey,
+    hash_algorithm: HashAlgorithm,
+  ) -> TestAccount {
+    let hash = match hash_algorithm {
+      HashAlgorithm::Sha256 => hash::hash(SECRET).to_bytes(),
+      HashAlgorithm::Keccak256 => keccak::hash(SECRET).to_bytes(),
+    };
+    let escrow_info = HashLockedEscrow {
+      key: Key::HashLockedEscrowV1,
+      maker: self.initializer,
+      taker,
+      mint: self.offered_mint,
+      vault: self.temp_token_account,
+      seed: 0,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      hash_algorithm,
+      hash,
+      timeout: 100,
+    };
+    TestAccount::new(
+      self.escrow,
+      self.program_id,
+      escrow_info.try_to_vec().unwrap(),
+    )
+  }
+}
+
+fn claim_with_secret_accounts(
+  fixture: &Fixture,
+  hash_algorithm: HashAlgorithm,
+  now: UnixTimestamp,
+) -> Vec<TestAccount> {
+  let taker = Pubkey::new_unique();
+  vec![
+    TestAccount::new(taker, system_program::id(), vec![]).signer(),
+    token_account(Pubkey::new_unique(), fixture.offered_mint, taker),
+    fixture.temp_token_account(),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    fixture.hash_locked_escrow_account(taker, hash_algorithm),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account_at(now),
+  ]
+}
+
+fn claim_with_secret_instruction(secret: &[u8]) -> EscrowInstruction {
+  EscrowInstruction::ClaimWithSecret(ClaimWithSecretArgs {
+    secret: secret.to_vec(),
+  })
+}
+
+#[test]
+fn claim_with_secret_rejects_wrong_secret() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_with_secret_accounts(&fixture, HashAlgorithm::Sha256, 99);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    claim_with_secret_instruction(b"guess"),
+  );
+  assert_escrow_error(result, EscrowError::InvalidSecret);
+}
+
+#[test]
+fn claim_with_secret_rejects_after_timeout() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_with_secret_accounts(&fixture, HashAlgorithm::Sha256, 100);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    claim_with_secret_instruction(SECRET),
+  );
+  assert_escrow_error(result, EscrowError::EscrowExpired);
+}
+
+#[test]
+fn claim_with_secret_requires_taker() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_with_secret_accounts(&fixture, HashAlgorithm::Sha256, 99);
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    claim_with_secret_instruction(SECRET),
+  );
+  assert_escrow_error(result, EscrowError::TakerNotAllowed);
+}
+
+fn refund_accounts(fixture: &Fixture, now: UnixTimestamp) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    token_account(
+      Pubkey::new_unique(),
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    fixture.temp_token_account(),
+    fixture.hash_locked_escrow_account(Pubkey::new_unique(), HashAlgorithm::Sha256),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account_at(now),
+  ]
+}
+
+#[test]
+fn refund_rejects_before_timeout() {
+  let fixture = Fixture::new();
+  let mut accounts = refund_accounts(&fixture, 99);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Refund(),
+  );
+  assert_escrow_error(result, EscrowError::EscrowNotExpired);
+}
+
+#[test]
+fn refund_requires_maker() {
+  let fixture = Fixture::new();
+  let mut accounts = refund_accounts(&fixture, 100);
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Refund(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
@@ -0,0 +1,2177 @@
+use {
+  borsh::{BorshDeserialize, BorshSerialize},
+  escrow::{
+    error::EscrowError,
+    instruction::{
+      cancel_escrow, exchange, expire_escrow, init_escrow, ClaimWithSecretArgs, EscrowInstruction,
+      ExchangeArgs, ExchangeManyArgs, InitArbitratedEscrowArgs, InitAuctionArgs, InitEscrowArgs,
+      InitHashLockedEscrowArgs, InitRingSwapArgs, InitVestingEscrowArgs, PlaceBidArgs,
+      ResolveDisputeArgs, RingLeg,
+    },
+    pda::{
+      find_arbitrated_escrow_address, find_auction_address, find_bid_refund_address,
+      find_config_address, find_escrow_address, find_hash_locked_escrow_address,
+      find_order_book_page_address, find_ring_swap_address, find_vault_authority,
+      find_vesting_escrow_address,
+    },
+    processor::Processor,
+    state::{
+      Auction, Escrow, EscrowReceive, HashAlgorithm, OrderBookPage, SideKind, VestingSchedule,
+      VestingTranche, ORDER_BOOK_PAGE_LEN,
+    },
+  },
+  solana_program::{
+    clock::{Clock, UnixTimestamp},
+    hash,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    keccak,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program, sysvar,
+  },
+  solana_program_test::{processor, tokio, ProgramTest, ProgramTestContext},
+  solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+  },
+  spl_token::{native_mint, state::Account as TokenAccount},
+};
+
+const INITIALIZER_LAMPORTS: u64 = 1_000_000_000;
+
+struct Env {
+  context: ProgramTestContext,
+  program_id: Pubkey,
+  rent: Rent,
+}
+
+struct Trade {
+  initializer: Keypair,
+  taker: Keypair,
+  offered_mint: Pubkey,
+  requested_mint: Pubkey,
+  temp_token_account: Pubkey,
+  initializer_receive_token_account: Pubkey,
+  taker_sending_token_account: Pubkey,
+  taker_receive_token_account: Pubkey,
+  escrow: Pubkey,
+}
+
+async fn setup() -> Env {
+  let program_id = Pubkey::new_unique();
+  let program_test = ProgramTest::new("escrow", program_id, processor!(Processor::process));
+  let mut context = program_test.start_with_context().await;
+  let rent = context.banks_client.get_rent().await.unwrap();
+  Env {
+    context,
+    program_id,
+    rent,
+  }
+}
+
+async fn process(
+  env: &mut Env,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> Result<(), TransportError> {
+  let mut transaction =
+    Transaction::new_with_payer(instructions, Some(&env.context.payer.pubkey()));
+  let mut all_signers = vec![&env.context.payer];
+  all_signers.extend_from_slice(signers);
+  transaction.sign(&all_signers, env.context.last_blockhash);
+  env
+    .context
+    .banks_client
+    .process_transaction(transaction)
+    .await
+}
+
+fn assert_instruction_error(result: Result<(), TransportError>, error: InstructionError) {
+  assert_eq!(
+    result.unwrap_err().unwrap(),
+    TransactionError::InstructionError(0, error)
+  );
+}
+
+async fn lamports(env: &mut Env, address: &Pubkey) -> u64 {
+  match env
+    .context
+    .banks_client
+    .get_account(*address)
+    .await
+    .unwrap()
+  {
+    Some(account) => account.lamports,
+    None => 0,
+  }
+}
+
+async fn token_account(env: &mut Env, address: &Pubkey) -> Option<TokenAccount> {
+  env
+    .context
+    .banks_client
+    .get_account(*address)
+    .await
+    .unwrap()
+    .map(|account| TokenAccount::unpack(&account.data).unwrap())
+}
+
+async fn token_balance(env: &mut Env, address: &Pubkey) -> u64 {
+  token_account(env, address).await.unwrap().amount
+}
+
+async fn fund(env: &mut Env, address: &Pubkey, lamports: u64) {
+  let instruction = system_instruction::transfer(&env.context.payer.pubkey(), address, lamports);
+  process(env, &[instruction], &[]).await.unwrap();
+}
+
+async fn create_mint(env: &mut Env) -> Pubkey {
+  let mint = Keypair::new();
+  let instructions = [
+    system_instruction::create_account(
+      &env.context.payer.pubkey(),
+      &mint.pubkey(),
+      env.rent.minimum_balance(spl_token::state::Mint::LEN),
+      spl_token::state::Mint::LEN as u64,
+      &spl_token::id(),
+    ),
+    spl_token::instruction::initialize_mint(
+      &spl_token::id(),
+      &mint.pubkey(),
+      &env.context.payer.pubkey(),
+      None,
+      0,
+    )
+    .unwrap(),
+  ];
+  process(env, &instructions, &[&mint]).await.unwrap();
+  mint.pubkey()
+}
+
+/// Token account of `mint` owned by `owner`. Lamports on top of rent are wrapped for the native mint
+async fn create_token_account(
+  env: &mut Env,
+  mint: &Pubkey,
+  owner: &Pubkey,
+  extra_lamports: u64,
+) -> Pubkey {
+  let account = Keypair::new();
+  let instructions = [
+    system_instruction::create_account(
+      &env.context.payer.pubkey(),
+      &account.pubkey(),
+      env.rent.minimum_balance(TokenAccount::LEN) + extra_lamports,
+      TokenAccount::LEN as u64,
+      &spl_token::id(),
+    ),
+    spl_token::instruction::initialize_account(&spl_token::id(), &account.pubkey(), mint, owner)
+      .unwrap(),
+  ];
+  process(env, &instructions, &[&account]).await.unwrap();
+  account.pubkey()
+}
+
+async fn mint_to(env: &mut Env, mint: &Pubkey, account: &Pubkey, amount: u64) {
+  let instruction = spl_token::instruction::mint_to(
+    &spl_token::id(),
+    mint,
+    account,
+    &env.context.payer.pubkey(),
+    &[],
+    amount,
+  )
+  .unwrap();
+  process(env, &[instruction], &[]).await.unwrap();
+}
+
+async fn transfer_tokens(
+  env: &mut Env,
+  source: &Pubkey,
+  destination: &Pubkey,
+  owner: &Keypair,
+  amount: u64,
+) {
+  let instruction = spl_token::instruction::transfer(
+    &spl_token::id(),
+    source,
+    destination,
+    &owner.pubkey(),
+    &[],
+    amount,
+  )
+  .unwrap();
+  process(env, &[instruction], &[owner]).await.unwrap();
+}
+
+/// Initializer offers `offered_amount` of a fresh mint, the taker holds `taker_balance` of the requested one
+async fn setup_trade(env: &mut Env, offered_amount: u64, taker_balance: u64) -> Trade {
+  let initializer = Keypair::new();
+  let taker = Keypair::new();
+  fund(env, &initializer.pubkey(), INITIALIZER_LAMPORTS).await;
+
+  let offered_mint = create_mint(env).await;
+  let requested_mint = create_mint(env).await;
+
+  let temp_token_account = create_token_account(env, &offered_mint, &initializer.pubkey(), 0).await;
+  mint_to(env, &offered_mint, &temp_token_account, offered_amount).await;
+  let initializer_receive_token_account =
+    create_token_account(env, &requested_mint, &initializer.pubkey(), 0).await;
+  let taker_sending_token_account =
+    create_token_account(env, &requested_mint, &taker.pubkey(), 0).await;
+  mint_to(
+    env,
+    &requested_mint,
+    &taker_sending_token_account,
+    taker_balance,
+  )
+  .await;
+  let taker_receive_token_account =
+    create_token_account(env, &offered_mint, &taker.pubkey(), 0).await;
+
+  let (escrow, _) = find_escrow_address(
+    &env.program_id,
+    &initializer.pubkey(),
+    &offered_mint,
+    &requested_mint,
+    0,
+  );
+  Trade {
+    initializer,
+    taker,
+    offered_mint,
+    requested_mint,
+    temp_token_account,
+    initializer_receive_token_account,
+    taker_sending_token_account,
+    taker_receive_token_account,
+    escrow,
+  }
+}
+
+fn order_book_page(env: &Env, trade: &Trade) -> Pubkey {
+  find_order_book_page_address(
+    &env.program_id,
+    &trade.offered_mint,
+    &trade.requested_mint,
+    0,
+  )
+  .0
+}
+
+// The first escrow of a mint pair pays for its order book page, which stays open
+fn page_rent(env: &Env) -> u64 {
+  env.rent.minimum_balance(ORDER_BOOK_PAGE_LEN)
+}
+
+async fn order_book_escrows(env: &mut Env, trade: &Trade) -> Vec<Pubkey> {
+  let page = order_book_page(env, trade);
+  let account = env
+    .context
+    .banks_client
+    .get_account(page)
+    .await
+    .unwrap()
+    .unwrap();
+  OrderBookPage::deserialize(&mut account.data.as_slice())
+    .unwrap()
+    .escrows
+}
+
+fn init_args(expected: u64) -> InitEscrowArgs {
+  InitEscrowArgs {
+    data: EscrowReceive { amount: expected },
+    seed: 0,
+    expires_at: None,
+    allowed_taker: None,
+    offered_kind: SideKind::Token,
+    requested_kind: SideKind::Token,
+    offered_amount: 0,
+    dutch_pricing: None,
+  }
+}
+
+fn init_instruction_with(
+  env: &Env,
+  trade: &Trade,
+  temp_token_account: Pubkey,
+  args: InitEscrowArgs,
+) -> Instruction {
+  init_escrow(
+    env.program_id,
+    trade.initializer.pubkey(),
+    temp_token_account,
+    trade.initializer_receive_token_account,
+    trade.offered_mint,
+    trade.requested_mint,
+    0,
+    args,
+  )
+}
+
+fn init_instruction(
+  env: &Env,
+  trade: &Trade,
+  temp_token_account: Pubkey,
+  expected: u64,
+) -> Instruction {
+  init_instruction_with(env, trade, temp_token_account, init_args(expected))
+}
+
+async fn init_trade(env: &mut Env, trade: &Trade, expected: u64) -> Result<(), TransportError> {
+  let instruction = init_instruction(env, trade, trade.temp_token_account, expected);
+  process(env, &[instruction], &[&trade.initializer]).await
+}
+
+fn exchange_instruction(
+  env: &Env,
+  trade: &Trade,
+  max_payment: u64,
+  fill_amount: u64,
+) -> Instruction {
+  exchange(
+    env.program_id,
+    trade.taker.pubkey(),
+    trade.taker_sending_token_account,
+    trade.taker_receive_token_account,
+    trade.temp_token_account,
+    trade.initializer.pubkey(),
+    trade.initializer_receive_token_account,
+    trade.escrow,
+    Pubkey::new_unique(),
+    Pubkey::new_unique(),
+    Pubkey::new_unique(),
+    order_book_page(env, trade),
+    trade.offered_mint,
+    vec![],
+    max_payment,
+    fill_amount,
+  )
+}
+
+#[tokio::test]
+async fn init_then_exchange() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  let temp_rent = lamports(&mut env, &trade.temp_token_account).await;
+
+  init_trade(&mut env, &trade, 50).await.unwrap();
+  assert_eq!(
+    token_account(&mut env, &trade.temp_token_account)
+      .await
+      .unwrap()
+      .owner,
+    escrow::pda::find_vault_authority(&env.program_id, &trade.escrow).0
+  );
+
+  let instruction = exchange_instruction(&env, &trade, 50, 100);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+
+  assert_eq!(
+    token_balance(&mut env, &trade.taker_receive_token_account).await,
+    100
+  );
+  assert_eq!(
+    token_balance(&mut env, &trade.taker_sending_token_account).await,
+    30
+  );
+  assert_eq!(
+    token_balance(&mut env, &trade.initializer_receive_token_account).await,
+    50
+  );
+  // Escrow rent comes back, plus the rent of the closed temp token account
+  assert_eq!(lamports(&mut env, &trade.temp_token_account).await, 0);
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+  assert_eq!(
+    lamports(&mut env, &trade.initializer.pubkey()).await,
+    INITIALIZER_LAMPORTS + temp_rent - page_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn partial_fills_until_vault_is_empty() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_trade(&mut env, &trade, 50).await.unwrap();
+
+  let instruction = exchange_instruction(&env, &trade, 20, 40);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+  assert_eq!(token_balance(&mut env, &trade.temp_token_account).await, 60);
+  assert_eq!(
+    token_balance(&mut env, &trade.initializer_receive_token_account).await,
+    20
+  );
+  assert_eq!(
+    order_book_escrows(&mut env, &trade).await,
+    vec![trade.escrow]
+  );
+
+  // A fill sized for the original vault no longer fits
+  let instruction = exchange_instruction(&env, &trade, 50, 100);
+  let result = process(&mut env, &[instruction], &[&trade.taker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidFillAmount as u32),
+  );
+
+  let instruction = exchange_instruction(&env, &trade, 30, 60);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+  assert_eq!(
+    token_balance(&mut env, &trade.taker_receive_token_account).await,
+    100
+  );
+  assert_eq!(
+    token_balance(&mut env, &trade.initializer_receive_token_account).await,
+    50
+  );
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+}
+
+#[tokio::test]
+async fn init_then_cancel() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+
+  init_trade(&mut env, &trade, 50).await.unwrap();
+  assert_eq!(
+    lamports(&mut env, &trade.initializer.pubkey()).await,
+    INITIALIZER_LAMPORTS - env.rent.minimum_balance(Escrow::LEN) - page_rent(&env)
+  );
+
+  let instruction = cancel_escrow(
+    env.program_id,
+    trade.initializer.pubkey(),
+    trade.temp_token_account,
+    trade.escrow,
+    order_book_page(&env, &trade),
+  );
+  process(&mut env, &[instruction], &[&trade.initializer])
+    .await
+    .unwrap();
+
+  let temp_token_account = token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap();
+  assert_eq!(temp_token_account.owner, trade.initializer.pubkey());
+  assert_eq!(temp_token_account.amount, 100);
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+  assert_eq!(
+    lamports(&mut env, &trade.initializer.pubkey()).await,
+    INITIALIZER_LAMPORTS - page_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn order_book_lists_open_escrows() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+
+  init_trade(&mut env, &trade, 50).await.unwrap();
+  assert_eq!(
+    order_book_escrows(&mut env, &trade).await,
+    vec![trade.escrow]
+  );
+
+  let instruction = exchange_instruction(&env, &trade, 50, 100);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+  assert!(order_book_escrows(&mut env, &trade).await.is_empty());
+}
+
+#[tokio::test]
+async fn exchange_rejects_payment_above_maximum() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_trade(&mut env, &trade, 50).await.unwrap();
+
+  let instruction = exchange_instruction(&env, &trade, 49, 100);
+  let result = process(&mut env, &[instruction], &[&trade.taker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::PaymentAboveMaximum as u32),
+  );
+  assert_eq!(
+    token_balance(&mut env, &trade.temp_token_account).await,
+    100
+  );
+}
+
+#[tokio::test]
+async fn exchange_rejects_wrong_accounts() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_trade(&mut env, &trade, 50).await.unwrap();
+
+  // Payment has to go to the receive account recorded at init
+  let mut instruction = exchange_instruction(&env, &trade, 50, 100);
+  instruction.accounts[5].pubkey = trade.taker_sending_token_account;
+  let result = process(&mut env, &[instruction], &[&trade.taker]).await;
+  assert_instruction_error(result, InstructionError::InvalidAccountData);
+
+  let mut instruction = exchange_instruction(&env, &trade, 50, 100);
+  instruction.accounts[8].pubkey = Pubkey::new_unique();
+  let result = process(&mut env, &[instruction], &[&trade.taker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidVaultAuthority as u32),
+  );
+
+  assert_eq!(
+    token_balance(&mut env, &trade.taker_sending_token_account).await,
+    80
+  );
+}
+
+#[tokio::test]
+async fn init_rejects_wrong_accounts() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+
+  // The delegate has to be the escrow's vault authority
+  let mut instruction = init_instruction_with(
+    &env,
+    &trade,
+    trade.temp_token_account,
+    InitEscrowArgs {
+      offered_kind: SideKind::Delegate,
+      offered_amount: 60,
+      ..init_args(50)
+    },
+  );
+  instruction.accounts[7].pubkey = Pubkey::new_unique();
+  let result = process(&mut env, &[instruction], &[&trade.initializer]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidVaultAuthority as u32),
+  );
+
+  let mut instruction = init_instruction(&env, &trade, trade.temp_token_account, 50);
+  instruction.accounts[9].pubkey = Pubkey::new_unique();
+  let result = process(&mut env, &[instruction], &[&trade.initializer]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidOrderBookPage as u32),
+  );
+
+  assert!(token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap()
+    .delegate
+    .is_none());
+}
+
+#[tokio::test]
+async fn expire_uses_up_delegation() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  let instruction = init_instruction_with(
+    &env,
+    &trade,
+    trade.temp_token_account,
+    InitEscrowArgs {
+      expires_at: Some(0),
+      offered_kind: SideKind::Delegate,
+      offered_amount: 60,
+      ..init_args(50)
+    },
+  );
+  process(&mut env, &[instruction], &[&trade.initializer])
+    .await
+    .unwrap();
+
+  // The delegating account can't back a second escrow
+  let instruction = init_instruction_with(
+    &env,
+    &trade,
+    trade.temp_token_account,
+    InitEscrowArgs {
+      seed: 1,
+      offered_kind: SideKind::Delegate,
+      offered_amount: 10,
+      ..init_args(50)
+    },
+  );
+  let result = process(&mut env, &[instruction], &[&trade.initializer]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::TokenAccountAlreadyDelegated as u32),
+  );
+
+  let refund_token_account = create_token_account(
+    &mut env,
+    &trade.offered_mint,
+    &trade.initializer.pubkey(),
+    0,
+  )
+  .await;
+  let instruction = expire_escrow(
+    env.program_id,
+    trade.initializer.pubkey(),
+    refund_token_account,
+    trade.temp_token_account,
+    trade.escrow,
+    order_book_page(&env, &trade),
+  );
+  process(&mut env, &[instruction], &[]).await.unwrap();
+
+  let delegating_account = token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap();
+  assert!(delegating_account.delegate.is_none());
+  assert_eq!(delegating_account.amount, 40);
+  assert_eq!(token_balance(&mut env, &refund_token_account).await, 60);
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+}
+
+#[tokio::test]
+async fn init_twice_fails() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_trade(&mut env, &trade, 50).await.unwrap();
+
+  let second_temp_token_account = create_token_account(
+    &mut env,
+    &trade.offered_mint,
+    &trade.initializer.pubkey(),
+    0,
+  )
+  .await;
+  mint_to(
+    &mut env,
+    &trade.offered_mint,
+    &second_temp_token_account,
+    10,
+  )
+  .await;
+
+  let instruction = init_instruction(&env, &trade, second_temp_token_account, 5);
+  let result = process(&mut env, &[instruction], &[&trade.initializer]).await;
+  assert_instruction_error(result, InstructionError::AccountAlreadyInitialized);
+  assert_eq!(
+    token_account(&mut env, &second_temp_token_account)
+      .await
+      .unwrap()
+      .owner,
+    trade.initializer.pubkey()
+  );
+}
+
+#[tokio::test]
+async fn init_tops_up_non_rent_exempt_escrow() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+
+  // Someone sent the escrow address lamports before it was created, enough for an empty account only
+  let prefunded = env.rent.minimum_balance(0);
+  fund(&mut env, &trade.escrow, prefunded).await;
+  init_trade(&mut env, &trade, 50).await.unwrap();
+
+  let escrow_lamports = lamports(&mut env, &trade.escrow).await;
+  assert!(env.rent.is_exempt(escrow_lamports, Escrow::LEN));
+  assert_eq!(escrow_lamports, env.rent.minimum_balance(Escrow::LEN));
+  assert_eq!(
+    lamports(&mut env, &trade.initializer.pubkey()).await,
+    INITIALIZER_LAMPORTS - (env.rent.minimum_balance(Escrow::LEN) - prefunded) - page_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn exchange_wrapped_sol() {
+  let mut env = setup().await;
+  let initializer = Keypair::new();
+  let taker = Keypair::new();
+  fund(&mut env, &initializer.pubkey(), INITIALIZER_LAMPORTS).await;
+  let requested_mint = create_mint(&mut env).await;
+
+  let temp_token_account = create_token_account(
+    &mut env,
+    &native_mint::id(),
+    &initializer.pubkey(),
+    500_000_000,
+  )
+  .await;
+  let temp_rent = env.rent.minimum_balance(TokenAccount::LEN);
+  let initializer_receive_token_account =
+    create_token_account(&mut env, &requested_mint, &initializer.pubkey(), 0).await;
+  let taker_sending_token_account =
+    create_token_account(&mut env, &requested_mint, &taker.pubkey(), 0).await;
+  mint_to(&mut env, &requested_mint, &taker_sending_token_account, 30).await;
+  let taker_receive_token_account =
+    create_token_account(&mut env, &native_mint::id(), &taker.pubkey(), 0).await;
+
+  let (escrow, _) = find_escrow_address(
+    &env.program_id,
+    &initializer.pubkey(),
+    &native_mint::id(),
+    &requested_mint,
+    0,
+  );
+  let trade = Trade {
+    initializer,
+    taker,
+    offered_mint: native_mint::id(),
+    requested_mint,
+    temp_token_account,
+    initializer_receive_token_account,
+    taker_sending_token_account,
+    taker_receive_token_account,
+    escrow,
+  };
+
+  init_trade(&mut env, &trade, 30).await.unwrap();
+  let instruction = exchange_instruction(&env, &trade, 30, 500_000_000);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+
+  assert_eq!(
+    token_balance(&mut env, &trade.taker_receive_token_account).await,
+    500_000_000
+  );
+  assert_eq!(
+    lamports(&mut env, &trade.taker_receive_token_account).await,
+    temp_rent + 500_000_000
+  );
+  assert_eq!(
+    token_balance(&mut env, &trade.initializer_receive_token_account).await,
+    30
+  );
+  assert_eq!(lamports(&mut env, &trade.temp_token_account).await, 0);
+  assert_eq!(
+    lamports(&mut env, &trade.initializer.pubkey()).await,
+    INITIALIZER_LAMPORTS + temp_rent - page_rent(&env)
+  );
+}
+
+/// Delegate mode escrow offering 60 of the initializer's 100 tokens for 30
+async fn init_delegate_trade(env: &mut Env, trade: &Trade, expires_at: Option<i64>) {
+  let instruction = init_instruction_with(
+    env,
+    trade,
+    trade.temp_token_account,
+    InitEscrowArgs {
+      expires_at,
+      offered_kind: SideKind::Delegate,
+      offered_amount: 60,
+      ..init_args(30)
+    },
+  );
+  process(env, &[instruction], &[&trade.initializer])
+    .await
+    .unwrap();
+}
+
+/// Leaves the delegating account with 40 tokens, below the allowance of 60
+async fn spend_delegated_tokens(env: &mut Env, trade: &Trade) {
+  let other_account =
+    create_token_account(env, &trade.offered_mint, &trade.initializer.pubkey(), 0).await;
+  transfer_tokens(
+    env,
+    &trade.temp_token_account,
+    &other_account,
+    &trade.initializer,
+    60,
+  )
+  .await;
+}
+
+#[tokio::test]
+async fn delegate_fill_clears_delegation() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_delegate_trade(&mut env, &trade, None).await;
+
+  let instruction = exchange_instruction(&env, &trade, 30, 60);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+
+  let delegating_account = token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap();
+  assert!(delegating_account.delegate.is_none());
+  assert_eq!(delegating_account.amount, 40);
+  assert_eq!(
+    token_balance(&mut env, &trade.taker_receive_token_account).await,
+    60
+  );
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+}
+
+#[tokio::test]
+async fn delegate_fill_of_whole_balance_leaves_escrow_to_cancel() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_delegate_trade(&mut env, &trade, None).await;
+  spend_delegated_tokens(&mut env, &trade).await;
+
+  let instruction = exchange_instruction(&env, &trade, 20, 40);
+  process(&mut env, &[instruction], &[&trade.taker])
+    .await
+    .unwrap();
+  // The vault authority can't revoke the 20 left of the allowance, so the escrow stays open
+  let delegating_account = token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap();
+  assert_eq!(delegating_account.amount, 0);
+  assert_eq!(delegating_account.delegated_amount, 20);
+  assert!(lamports(&mut env, &trade.escrow).await > 0);
+
+  let instruction = cancel_escrow(
+    env.program_id,
+    trade.initializer.pubkey(),
+    trade.temp_token_account,
+    trade.escrow,
+    order_book_page(&env, &trade),
+  );
+  process(&mut env, &[instruction], &[&trade.initializer])
+    .await
+    .unwrap();
+  assert!(token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap()
+    .delegate
+    .is_none());
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+}
+
+#[tokio::test]
+async fn expire_revokes_allowance_above_balance_with_initializer_signature() {
+  let mut env = setup().await;
+  let trade = setup_trade(&mut env, 100, 80).await;
+  init_delegate_trade(&mut env, &trade, Some(0)).await;
+  spend_delegated_tokens(&mut env, &trade).await;
+  let refund_token_account = create_token_account(
+    &mut env,
+    &trade.offered_mint,
+    &trade.initializer.pubkey(),
+    0,
+  )
+  .await;
+  let instruction = expire_escrow(
+    env.program_id,
+    trade.initializer.pubkey(),
+    refund_token_account,
+    trade.temp_token_account,
+    trade.escrow,
+    order_book_page(&env, &trade),
+  );
+
+  let result = process(&mut env, &[instruction.clone()], &[]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::AllowanceAboveBalance as u32),
+  );
+
+  let mut instruction = instruction;
+  instruction.accounts[0].is_signer = true;
+  process(&mut env, &[instruction], &[&trade.initializer])
+    .await
+    .unwrap();
+  let delegating_account = token_account(&mut env, &trade.temp_token_account)
+    .await
+    .unwrap();
+  assert!(delegating_account.delegate.is_none());
+  assert_eq!(delegating_account.amount, 40);
+  assert_eq!(token_balance(&mut env, &refund_token_account).await, 0);
+  assert_eq!(lamports(&mut env, &trade.escrow).await, 0);
+}
+
+/// Another initializer offering `offered_amount` for the same mint pair to the same taker
+async fn setup_trade_of_same_pair(env: &mut Env, trade: &Trade, offered_amount: u64) -> Trade {
+  let initializer = Keypair::new();
+  fund(env, &initializer.pubkey(), INITIALIZER_LAMPORTS).await;
+  let temp_token_account =
+    create_token_account(env, &trade.offered_mint, &initializer.pubkey(), 0).await;
+  mint_to(
+    env,
+    &trade.offered_mint,
+    &temp_token_account,
+    offered_amount,
+  )
+  .await;
+  let initializer_receive_token_account =
+    create_token_account(env, &trade.requested_mint, &initializer.pubkey(), 0).await;
+  let (escrow, _) = find_escrow_address(
+    &env.program_id,
+    &initializer.pubkey(),
+    &trade.offered_mint,
+    &trade.requested_mint,
+    0,
+  );
+  Trade {
+    initializer,
+    taker: Keypair::from_bytes(&trade.taker.to_bytes()).unwrap(),
+    temp_token_account,
+    initializer_receive_token_account,
+    escrow,
+    ..*trade
+  }
+}
+
+/// ExchangeMany of `legs` (trade, max payment, fill amount), with the taker accounts of the first trade
+fn exchange_many_instruction(env: &Env, legs: &[(&Trade, u64, u64)]) -> Instruction {
+  let mut accounts = vec![];
+  for (index, (trade, max_payment, fill_amount)) in legs.iter().enumerate() {
+    let single = exchange_instruction(env, trade, *max_payment, *fill_amount);
+    if index == 0 {
+      accounts.extend(
+        [0, 1, 2, 7, 9, 10, 11, 12, 13]
+          .iter()
+          .map(|&i| single.accounts[i].clone()),
+      );
+    }
+    accounts.extend(
+      [3, 4, 5, 6, 8, 14, 15, 16]
+        .iter()
+        .map(|&i| single.accounts[i].clone()),
+    );
+  }
+  Instruction {
+    program_id: env.program_id,
+    accounts,
+    data: EscrowInstruction::ExchangeMany(ExchangeManyArgs {
+      legs: legs
+        .iter()
+        .map(|(_, max_payment, fill_amount)| ExchangeArgs {
+          data: EscrowReceive {
+            amount: *max_payment,
+          },
+          fill_amount: *fill_amount,
+        })
+        .collect(),
+    })
+    .try_to_vec()
+    .unwrap(),
+  }
+}
+
+#[tokio::test]
+async fn exchange_many_sweeps_escrows_of_a_pair() {
+  let mut env = setup().await;
+  let first = setup_trade(&mut env, 100, 80).await;
+  let second = setup_trade_of_same_pair(&mut env, &first, 60).await;
+  init_trade(&mut env, &first, 50).await.unwrap();
+  let instruction = init_instruction(&env, &second, second.temp_token_account, 20);
+  process(&mut env, &[instruction], &[&second.initializer])
+    .await
+    .unwrap();
+
+  // All of the first escrow and half of the second
+  let instruction = exchange_many_instruction(&env, &[(&first, 50, 100), (&second, 10, 30)]);
+  process(&mut env, &[instruction], &[&first.taker])
+    .await
+    .unwrap();
+
+  assert_eq!(
+    token_balance(&mut env, &first.taker_receive_token_account).await,
+    130
+  );
+  assert_eq!(
+    token_balance(&mut env, &first.taker_sending_token_account).await,
+    20
+  );
+  assert_eq!(
+    token_balance(&mut env, &first.initializer_receive_token_account).await,
+    50
+  );
+  assert_eq!(
+    token_balance(&mut env, &second.initializer_receive_token_account).await,
+    10
+  );
+  assert_eq!(lamports(&mut env, &first.escrow).await, 0);
+  assert_eq!(
+    token_balance(&mut env, &second.temp_token_account).await,
+    30
+  );
+  assert_eq!(
+    order_book_escrows(&mut env, &first).await,
+    vec![second.escrow]
+  );
+}
+
+#[tokio::test]
+async fn exchange_many_reverts_every_leg_when_one_fails() {
+  let mut env = setup().await;
+  let first = setup_trade(&mut env, 100, 80).await;
+  let second = setup_trade_of_same_pair(&mut env, &first, 60).await;
+  init_trade(&mut env, &first, 50).await.unwrap();
+  let instruction = init_instruction(&env, &second, second.temp_token_account, 20);
+  process(&mut env, &[instruction], &[&second.initializer])
+    .await
+    .unwrap();
+
+  // The second leg pays below its price
+  let instruction = exchange_many_instruction(&env, &[(&first, 50, 100), (&second, 9, 30)]);
+  let result = process(&mut env, &[instruction], &[&first.taker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::PaymentAboveMaximum as u32),
+  );
+
+  assert_eq!(
+    token_balance(&mut env, &first.temp_token_account).await,
+    100
+  );
+  assert_eq!(
+    token_balance(&mut env, &first.taker_sending_token_account).await,
+    80
+  );
+  assert_eq!(
+    token_balance(&mut env, &first.taker_receive_token_account).await,
+    0
+  );
+  assert!(lamports(&mut env, &first.escrow).await > 0);
+}
+
+#[tokio::test]
+async fn exchange_many_rejects_legs_of_another_pair() {
+  let mut env = setup().await;
+  let first = setup_trade(&mut env, 100, 80).await;
+  let other = setup_trade(&mut env, 100, 80).await;
+  init_trade(&mut env, &first, 50).await.unwrap();
+  init_trade(&mut env, &other, 50).await.unwrap();
+
+  let instruction = exchange_many_instruction(&env, &[(&first, 50, 100), (&other, 50, 100)]);
+  let result = process(&mut env, &[instruction], &[&first.taker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::ExchangeLegsMixMints as u32),
+  );
+  assert_eq!(
+    token_balance(&mut env, &first.temp_token_account).await,
+    100
+  );
+}
+
+fn escrow_instruction(
+  env: &Env,
+  data: EscrowInstruction,
+  accounts: Vec<AccountMeta>,
+) -> Instruction {
+  Instruction {
+    program_id: env.program_id,
+    accounts,
+    data: data.try_to_vec().unwrap(),
+  }
+}
+
+fn vault_authority(env: &Env, escrow: &Pubkey) -> Pubkey {
+  find_vault_authority(&env.program_id, escrow).0
+}
+
+fn token_account_rent(env: &Env) -> u64 {
+  env.rent.minimum_balance(TokenAccount::LEN)
+}
+
+struct Arbitration {
+  buyer: Keypair,
+  seller: Keypair,
+  arbiter: Keypair,
+  vault: Pubkey,
+  buyer_token_account: Pubkey,
+  seller_token_account: Pubkey,
+  escrow: Pubkey,
+}
+
+/// Buyer locks 100 tokens for the seller
+async fn setup_arbitration(env: &mut Env) -> Arbitration {
+  let buyer = Keypair::new();
+  let seller = Keypair::new();
+  let arbiter = Keypair::new();
+  fund(env, &buyer.pubkey(), INITIALIZER_LAMPORTS).await;
+  let mint = create_mint(env).await;
+  let vault = create_token_account(env, &mint, &buyer.pubkey(), 0).await;
+  mint_to(env, &mint, &vault, 100).await;
+  let buyer_token_account = create_token_account(env, &mint, &buyer.pubkey(), 0).await;
+  let seller_token_account = create_token_account(env, &mint, &seller.pubkey(), 0).await;
+  let (escrow, _) = find_arbitrated_escrow_address(&env.program_id, &buyer.pubkey(), 0);
+
+  let instruction = escrow_instruction(
+    env,
+    EscrowInstruction::InitArbitratedEscrow(InitArbitratedEscrowArgs {
+      seed: 0,
+      seller: seller.pubkey(),
+      arbiter: arbiter.pubkey(),
+    }),
+    vec![
+      AccountMeta::new(buyer.pubkey(), true),
+      AccountMeta::new(vault, false),
+      AccountMeta::new(escrow, false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+      AccountMeta::new_readonly(find_config_address(&env.program_id).0, false),
+    ],
+  );
+  process(env, &[instruction], &[&buyer]).await.unwrap();
+  Arbitration {
+    buyer,
+    seller,
+    arbiter,
+    vault,
+    buyer_token_account,
+    seller_token_account,
+    escrow,
+  }
+}
+
+fn release_instruction(env: &Env, arbitration: &Arbitration) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::Release(),
+    vec![
+      AccountMeta::new(arbitration.buyer.pubkey(), true),
+      AccountMeta::new(arbitration.vault, false),
+      AccountMeta::new(arbitration.seller_token_account, false),
+      AccountMeta::new(arbitration.escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &arbitration.escrow), false),
+    ],
+  )
+}
+
+fn open_dispute_instruction(env: &Env, arbitration: &Arbitration, party: &Keypair) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::OpenDispute(),
+    vec![
+      AccountMeta::new_readonly(party.pubkey(), true),
+      AccountMeta::new(arbitration.escrow, false),
+    ],
+  )
+}
+
+fn resolve_instruction(env: &Env, arbitration: &Arbitration, buyer_amount: u64) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::ResolveDispute(ResolveDisputeArgs { buyer_amount }),
+    vec![
+      AccountMeta::new_readonly(arbitration.arbiter.pubkey(), true),
+      AccountMeta::new(arbitration.vault, false),
+      AccountMeta::new(arbitration.buyer_token_account, false),
+      AccountMeta::new(arbitration.seller_token_account, false),
+      AccountMeta::new(arbitration.buyer.pubkey(), false),
+      AccountMeta::new(arbitration.escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &arbitration.escrow), false),
+    ],
+  )
+}
+
+#[tokio::test]
+async fn release_pays_vault_to_seller() {
+  let mut env = setup().await;
+  let arbitration = setup_arbitration(&mut env).await;
+
+  let instruction = release_instruction(&env, &arbitration);
+  process(&mut env, &[instruction], &[&arbitration.buyer])
+    .await
+    .unwrap();
+
+  assert_eq!(
+    token_balance(&mut env, &arbitration.seller_token_account).await,
+    100
+  );
+  assert_eq!(
+    token_balance(&mut env, &arbitration.buyer_token_account).await,
+    0
+  );
+  assert_eq!(lamports(&mut env, &arbitration.vault).await, 0);
+  assert_eq!(lamports(&mut env, &arbitration.escrow).await, 0);
+  // The buyer gets back the escrow rent it paid and the vault rent
+  assert_eq!(
+    lamports(&mut env, &arbitration.buyer.pubkey()).await,
+    INITIALIZER_LAMPORTS + token_account_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn buyer_releases_during_dispute() {
+  let mut env = setup().await;
+  let arbitration = setup_arbitration(&mut env).await;
+
+  let instruction = open_dispute_instruction(&env, &arbitration, &arbitration.seller);
+  process(&mut env, &[instruction], &[&arbitration.seller])
+    .await
+    .unwrap();
+  // A dispute is only opened once
+  let instruction = open_dispute_instruction(&env, &arbitration, &arbitration.buyer);
+  let result = process(&mut env, &[instruction], &[&arbitration.buyer]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidEscrowStatus as u32),
+  );
+
+  let instruction = release_instruction(&env, &arbitration);
+  process(&mut env, &[instruction], &[&arbitration.buyer])
+    .await
+    .unwrap();
+  assert_eq!(
+    token_balance(&mut env, &arbitration.seller_token_account).await,
+    100
+  );
+  assert_eq!(lamports(&mut env, &arbitration.escrow).await, 0);
+}
+
+#[tokio::test]
+async fn resolve_splits_disputed_vault() {
+  let mut env = setup().await;
+  let arbitration = setup_arbitration(&mut env).await;
+
+  // Only a disputed escrow can be resolved
+  let instruction = resolve_instruction(&env, &arbitration, 40);
+  let result = process(&mut env, &[instruction], &[&arbitration.arbiter]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidEscrowStatus as u32),
+  );
+
+  let instruction = open_dispute_instruction(&env, &arbitration, &arbitration.buyer);
+  process(&mut env, &[instruction], &[&arbitration.buyer])
+    .await
+    .unwrap();
+  let instruction = resolve_instruction(&env, &arbitration, 30);
+  process(&mut env, &[instruction], &[&arbitration.arbiter])
+    .await
+    .unwrap();
+
+  assert_eq!(
+    token_balance(&mut env, &arbitration.buyer_token_account).await,
+    30
+  );
+  assert_eq!(
+    token_balance(&mut env, &arbitration.seller_token_account).await,
+    70
+  );
+  assert_eq!(lamports(&mut env, &arbitration.vault).await, 0);
+  assert_eq!(lamports(&mut env, &arbitration.escrow).await, 0);
+  assert_eq!(
+    lamports(&mut env, &arbitration.buyer.pubkey()).await,
+    INITIALIZER_LAMPORTS + token_account_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn resolve_can_pay_everything_to_one_side() {
+  let mut env = setup().await;
+  for &buyer_amount in &[0, 100] {
+    let arbitration = setup_arbitration(&mut env).await;
+    let instruction = open_dispute_instruction(&env, &arbitration, &arbitration.seller);
+    process(&mut env, &[instruction], &[&arbitration.seller])
+      .await
+      .unwrap();
+    let instruction = resolve_instruction(&env, &arbitration, buyer_amount);
+    process(&mut env, &[instruction], &[&arbitration.arbiter])
+      .await
+      .unwrap();
+
+    assert_eq!(
+      token_balance(&mut env, &arbitration.buyer_token_account).await,
+      buyer_amount
+    );
+    assert_eq!(
+      token_balance(&mut env, &arbitration.seller_token_account).await,
+      100 - buyer_amount
+    );
+    assert_eq!(lamports(&mut env, &arbitration.escrow).await, 0);
+  }
+}
+
+async fn now(env: &mut Env) -> UnixTimestamp {
+  env
+    .context
+    .banks_client
+    .get_sysvar::<Clock>()
+    .await
+    .unwrap()
+    .unix_timestamp
+}
+
+struct Grant {
+  funder: Keypair,
+  revocation_authority: Keypair,
+  mint: Pubkey,
+  vault: Pubkey,
+  beneficiary_token_account: Pubkey,
+  funder_token_account: Pubkey,
+  escrow: Pubkey,
+}
+
+/// Funder grants `amount` tokens vesting along `schedule`, revocable by the grant's revocation authority
+async fn setup_grant(env: &mut Env, amount: u64, schedule: VestingSchedule) -> Grant {
+  let funder = Keypair::new();
+  let beneficiary = Pubkey::new_unique();
+  let revocation_authority = Keypair::new();
+  fund(env, &funder.pubkey(), INITIALIZER_LAMPORTS).await;
+  let mint = create_mint(env).await;
+  let vault = create_token_account(env, &mint, &funder.pubkey(), 0).await;
+  mint_to(env, &mint, &vault, amount).await;
+  let beneficiary_token_account = create_token_account(env, &mint, &beneficiary, 0).await;
+  let funder_token_account = create_token_account(env, &mint, &funder.pubkey(), 0).await;
+  let (escrow, _) = find_vesting_escrow_address(&env.program_id, &funder.pubkey(), 0);
+
+  let instruction = escrow_instruction(
+    env,
+    EscrowInstruction::InitVestingEscrow(InitVestingEscrowArgs {
+      seed: 0,
+      beneficiary,
+      revocation_authority: Some(revocation_authority.pubkey()),
+      schedule,
+    }),
+    vec![
+      AccountMeta::new(funder.pubkey(), true),
+      AccountMeta::new(vault, false),
+      AccountMeta::new(escrow, false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+      AccountMeta::new_readonly(find_config_address(&env.program_id).0, false),
+    ],
+  );
+  process(env, &[instruction], &[&funder]).await.unwrap();
+  Grant {
+    funder,
+    revocation_authority,
+    mint,
+    vault,
+    beneficiary_token_account,
+    funder_token_account,
+    escrow,
+  }
+}
+
+fn claim_instruction(env: &Env, grant: &Grant) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::Claim(),
+    vec![
+      AccountMeta::new(grant.beneficiary_token_account, false),
+      AccountMeta::new(grant.vault, false),
+      AccountMeta::new(grant.escrow, false),
+      AccountMeta::new(grant.funder.pubkey(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &grant.escrow), false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  )
+}
+
+fn revoke_instruction(env: &Env, grant: &Grant) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::RevokeVesting(),
+    vec![
+      AccountMeta::new_readonly(grant.revocation_authority.pubkey(), true),
+      AccountMeta::new(grant.vault, false),
+      AccountMeta::new(grant.beneficiary_token_account, false),
+      AccountMeta::new(grant.funder_token_account, false),
+      AccountMeta::new(grant.funder.pubkey(), false),
+      AccountMeta::new(grant.escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &grant.escrow), false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  )
+}
+
+#[tokio::test]
+async fn claim_pays_vested_tranches_and_revoke_returns_the_rest() {
+  let mut env = setup().await;
+  let now = now(&mut env).await;
+  let schedule = VestingSchedule::Tranches(vec![
+    VestingTranche {
+      timestamp: now - 10,
+      amount: 40,
+    },
+    VestingTranche {
+      timestamp: now + 1_000_000,
+      amount: 60,
+    },
+  ]);
+  let grant = setup_grant(&mut env, 100, schedule).await;
+
+  let instruction = claim_instruction(&env, &grant);
+  process(&mut env, &[instruction], &[]).await.unwrap();
+  assert_eq!(
+    token_balance(&mut env, &grant.beneficiary_token_account).await,
+    40
+  );
+  assert_eq!(token_balance(&mut env, &grant.vault).await, 60);
+
+  let instruction = revoke_instruction(&env, &grant);
+  process(&mut env, &[instruction], &[&grant.revocation_authority])
+    .await
+    .unwrap();
+  assert_eq!(
+    token_balance(&mut env, &grant.beneficiary_token_account).await,
+    40
+  );
+  assert_eq!(
+    token_balance(&mut env, &grant.funder_token_account).await,
+    60
+  );
+  assert_eq!(lamports(&mut env, &grant.vault).await, 0);
+  assert_eq!(lamports(&mut env, &grant.escrow).await, 0);
+  assert_eq!(
+    lamports(&mut env, &grant.funder.pubkey()).await,
+    INITIALIZER_LAMPORTS + token_account_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn claim_pays_linear_schedule_after_cliff() {
+  let mut env = setup().await;
+  let now = now(&mut env).await;
+  let schedule = VestingSchedule::Linear {
+    start: now - 60,
+    cliff: 25,
+    duration: 100,
+  };
+  let grant = setup_grant(&mut env, 100, schedule).await;
+
+  let instruction = claim_instruction(&env, &grant);
+  process(&mut env, &[instruction], &[]).await.unwrap();
+  assert_eq!(
+    token_balance(&mut env, &grant.beneficiary_token_account).await,
+    60
+  );
+  assert_eq!(token_balance(&mut env, &grant.vault).await, 40);
+}
+
+#[tokio::test]
+async fn last_claim_pays_extra_tokens_and_closes_vault() {
+  let mut env = setup().await;
+  let now = now(&mut env).await;
+  let schedule = VestingSchedule::Tranches(vec![
+    VestingTranche {
+      timestamp: now - 20,
+      amount: 40,
+    },
+    VestingTranche {
+      timestamp: now - 10,
+      amount: 60,
+    },
+  ]);
+  let grant = setup_grant(&mut env, 100, schedule).await;
+  mint_to(&mut env, &grant.mint, &grant.vault, 50).await;
+
+  let instruction = claim_instruction(&env, &grant);
+  process(&mut env, &[instruction], &[]).await.unwrap();
+  assert_eq!(
+    token_balance(&mut env, &grant.beneficiary_token_account).await,
+    150
+  );
+  assert_eq!(lamports(&mut env, &grant.vault).await, 0);
+  assert_eq!(lamports(&mut env, &grant.escrow).await, 0);
+  assert_eq!(
+    lamports(&mut env, &grant.funder.pubkey()).await,
+    INITIALIZER_LAMPORTS + token_account_rent(&env)
+  );
+}
+
+/// Warps the cluster ahead until its clock is past `timestamp`
+async fn warp_past(env: &mut Env, timestamp: UnixTimestamp) {
+  let mut clock = env
+    .context
+    .banks_client
+    .get_sysvar::<Clock>()
+    .await
+    .unwrap();
+  while clock.unix_timestamp <= timestamp {
+    env.context.warp_to_slot(clock.slot + 100).unwrap();
+    clock = env
+      .context
+      .banks_client
+      .get_sysvar::<Clock>()
+      .await
+      .unwrap();
+  }
+}
+
+const SECRET: &[u8] = b"preimage";
+
+struct HashLock {
+  maker: Keypair,
+  taker: Keypair,
+  vault: Pubkey,
+  maker_token_account: Pubkey,
+  taker_token_account: Pubkey,
+  escrow: Pubkey,
+}
+
+/// Maker locks `amount` tokens of a fresh mint until the taker reveals SECRET or `timeout` passes
+async fn setup_hash_lock(
+  env: &mut Env,
+  amount: u64,
+  hash_algorithm: HashAlgorithm,
+  timeout: UnixTimestamp,
+) -> HashLock {
+  let maker = Keypair::new();
+  let taker = Keypair::new();
+  fund(env, &maker.pubkey(), INITIALIZER_LAMPORTS).await;
+  let mint = create_mint(env).await;
+  let vault = create_token_account(env, &mint, &maker.pubkey(), 0).await;
+  mint_to(env, &mint, &vault, amount).await;
+  let maker_token_account = create_token_account(env, &mint, &maker.pubkey(), 0).await;
+  let taker_token_account = create_token_account(env, &mint, &taker.pubkey(), 0).await;
+  let (escrow, _) = find_hash_locked_escrow_address(&env.program_id, &maker.pubkey(), 0);
+  let hash = match hash_algorithm {
+    HashAlgorithm::Sha256 => hash::hash(SECRET).to_bytes(),
+    HashAlgorithm::Keccak256 => keccak::hash(SECRET).to_bytes(),
+  };
+
+  let instruction = escrow_instruction(
+    env,
+    EscrowInstruction::InitHashLockedEscrow(InitHashLockedEscrowArgs {
+      seed: 0,
+      taker: taker.pubkey(),
+      hash_algorithm,
+      hash,
+      timeout,
+    }),
+    vec![
+      AccountMeta::new(maker.pubkey(), true),
+      AccountMeta::new(vault, false),
+      AccountMeta::new(escrow, false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+      AccountMeta::new_readonly(find_config_address(&env.program_id).0, false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  );
+  process(env, &[instruction], &[&maker]).await.unwrap();
+  HashLock {
+    maker,
+    taker,
+    vault,
+    maker_token_account,
+    taker_token_account,
+    escrow,
+  }
+}
+
+fn claim_with_secret_instruction(env: &Env, lock: &HashLock, secret: &[u8]) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::ClaimWithSecret(ClaimWithSecretArgs {
+      secret: secret.to_vec(),
+    }),
+    vec![
+      AccountMeta::new_readonly(lock.taker.pubkey(), true),
+      AccountMeta::new(lock.taker_token_account, false),
+      AccountMeta::new(lock.vault, false),
+      AccountMeta::new(lock.maker.pubkey(), false),
+      AccountMeta::new(lock.escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &lock.escrow), false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  )
+}
+
+fn refund_instruction(env: &Env, lock: &HashLock) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::Refund(),
+    vec![
+      AccountMeta::new(lock.maker.pubkey(), true),
+      AccountMeta::new(lock.maker_token_account, false),
+      AccountMeta::new(lock.vault, false),
+      AccountMeta::new(lock.escrow, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &lock.escrow), false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  )
+}
+
+#[tokio::test]
+async fn claim_with_secret_pays_vault_to_taker() {
+  let mut env = setup().await;
+  for &hash_algorithm in &[HashAlgorithm::Sha256, HashAlgorithm::Keccak256] {
+    let timeout = now(&mut env).await + 1_000;
+    let lock = setup_hash_lock(&mut env, 100, hash_algorithm, timeout).await;
+
+    let instruction = claim_with_secret_instruction(&env, &lock, SECRET);
+    process(&mut env, &[instruction], &[&lock.taker])
+      .await
+      .unwrap();
+    assert_eq!(
+      token_balance(&mut env, &lock.taker_token_account).await,
+      100
+    );
+    assert_eq!(lamports(&mut env, &lock.vault).await, 0);
+    assert_eq!(lamports(&mut env, &lock.escrow).await, 0);
+    assert_eq!(
+      lamports(&mut env, &lock.maker.pubkey()).await,
+      INITIALIZER_LAMPORTS + token_account_rent(&env)
+    );
+  }
+}
+
+#[tokio::test]
+async fn refund_returns_vault_to_maker_once_timed_out() {
+  let mut env = setup().await;
+  let timeout = now(&mut env).await + 10;
+  let lock = setup_hash_lock(&mut env, 100, HashAlgorithm::Sha256, timeout).await;
+
+  let instruction = refund_instruction(&env, &lock);
+  let result = process(&mut env, &[instruction], &[&lock.maker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::EscrowNotExpired as u32),
+  );
+
+  warp_past(&mut env, timeout).await;
+  let instruction = claim_with_secret_instruction(&env, &lock, SECRET);
+  let result = process(&mut env, &[instruction], &[&lock.taker]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::EscrowExpired as u32),
+  );
+  let instruction = refund_instruction(&env, &lock);
+  process(&mut env, &[instruction], &[&lock.maker])
+    .await
+    .unwrap();
+  assert_eq!(
+    token_balance(&mut env, &lock.maker_token_account).await,
+    100
+  );
+  assert_eq!(token_balance(&mut env, &lock.taker_token_account).await, 0);
+  assert_eq!(lamports(&mut env, &lock.vault).await, 0);
+  assert_eq!(lamports(&mut env, &lock.escrow).await, 0);
+}
+
+struct Lot {
+  seller: Keypair,
+  nft_mint: Pubkey,
+  bid_mint: Pubkey,
+  vault: Pubkey,
+  bid_vault: Pubkey,
+  seller_bid_token_account: Pubkey,
+  seller_nft_token_account: Pubkey,
+  auction: Pubkey,
+}
+
+struct Bidder {
+  keypair: Keypair,
+  bid_token_account: Pubkey,
+  nft_token_account: Pubkey,
+}
+
+/// Seller auctions a fresh NFT for bids of a fresh mint, above a reserve price of 10
+async fn setup_auction(env: &mut Env, end_time: UnixTimestamp, extension_window: i64) -> Lot {
+  let seller = Keypair::new();
+  fund(env, &seller.pubkey(), INITIALIZER_LAMPORTS).await;
+  let nft_mint = create_mint(env).await;
+  let bid_mint = create_mint(env).await;
+  let vault = create_token_account(env, &nft_mint, &seller.pubkey(), 0).await;
+  mint_to(env, &nft_mint, &vault, 1).await;
+  let bid_vault = create_token_account(env, &bid_mint, &seller.pubkey(), 0).await;
+  let seller_bid_token_account = create_token_account(env, &bid_mint, &seller.pubkey(), 0).await;
+  let seller_nft_token_account = create_token_account(env, &nft_mint, &seller.pubkey(), 0).await;
+  let (auction, _) = find_auction_address(&env.program_id, &seller.pubkey(), 0);
+
+  let instruction = escrow_instruction(
+    env,
+    EscrowInstruction::InitAuction(InitAuctionArgs {
+      seed: 0,
+      reserve_price: 10,
+      end_time,
+      extension_window,
+    }),
+    vec![
+      AccountMeta::new(seller.pubkey(), true),
+      AccountMeta::new(vault, false),
+      AccountMeta::new(bid_vault, false),
+      AccountMeta::new(auction, false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+      AccountMeta::new_readonly(find_config_address(&env.program_id).0, false),
+      AccountMeta::new_readonly(nft_mint, false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  );
+  process(env, &[instruction], &[&seller]).await.unwrap();
+  Lot {
+    seller,
+    nft_mint,
+    bid_mint,
+    vault,
+    bid_vault,
+    seller_bid_token_account,
+    seller_nft_token_account,
+    auction,
+  }
+}
+
+/// Bidder holding 100 of the bid mint and lamports for the rent of its bid refund account
+async fn setup_bidder(env: &mut Env, lot: &Lot) -> Bidder {
+  let keypair = Keypair::new();
+  fund(env, &keypair.pubkey(), INITIALIZER_LAMPORTS).await;
+  let bid_token_account = create_token_account(env, &lot.bid_mint, &keypair.pubkey(), 0).await;
+  mint_to(env, &lot.bid_mint, &bid_token_account, 100).await;
+  let nft_token_account = create_token_account(env, &lot.nft_mint, &keypair.pubkey(), 0).await;
+  Bidder {
+    keypair,
+    bid_token_account,
+    nft_token_account,
+  }
+}
+
+fn bid_refund(env: &Env, lot: &Lot, bidder: &Pubkey) -> Pubkey {
+  find_bid_refund_address(&env.program_id, &lot.auction, bidder).0
+}
+
+async fn auction_state(env: &mut Env, lot: &Lot) -> Auction {
+  let account = env
+    .context
+    .banks_client
+    .get_account(lot.auction)
+    .await
+    .unwrap()
+    .unwrap();
+  Auction::deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+fn place_bid_instruction(
+  env: &Env,
+  lot: &Lot,
+  bidder: &Bidder,
+  previous_bidder: Option<&Bidder>,
+  amount: u64,
+) -> Instruction {
+  let bidder_refund = bid_refund(env, lot, &bidder.keypair.pubkey());
+  let previous_bidder_refund = previous_bidder
+    .map(|previous| bid_refund(env, lot, &previous.keypair.pubkey()))
+    .unwrap_or(bidder_refund);
+  escrow_instruction(
+    env,
+    EscrowInstruction::PlaceBid(PlaceBidArgs { amount }),
+    vec![
+      AccountMeta::new(bidder.keypair.pubkey(), true),
+      AccountMeta::new(bidder.bid_token_account, false),
+      AccountMeta::new(lot.bid_vault, false),
+      AccountMeta::new(bidder_refund, false),
+      AccountMeta::new(previous_bidder_refund, false),
+      AccountMeta::new(lot.auction, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &lot.auction), false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+    ],
+  )
+}
+
+async fn place_bid(
+  env: &mut Env,
+  lot: &Lot,
+  bidder: &Bidder,
+  previous_bidder: Option<&Bidder>,
+  amount: u64,
+) -> Result<(), TransportError> {
+  let instruction = place_bid_instruction(env, lot, bidder, previous_bidder, amount);
+  process(env, &[instruction], &[&bidder.keypair]).await
+}
+
+fn settle_auction_instruction(env: &Env, lot: &Lot, nft_token_account: &Pubkey) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::SettleAuction(),
+    vec![
+      AccountMeta::new(lot.seller.pubkey(), false),
+      AccountMeta::new(lot.seller_bid_token_account, false),
+      AccountMeta::new(*nft_token_account, false),
+      AccountMeta::new(lot.vault, false),
+      AccountMeta::new(lot.bid_vault, false),
+      AccountMeta::new(lot.auction, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &lot.auction), false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ],
+  )
+}
+
+fn withdraw_bid_refund_instruction(env: &Env, lot: &Lot, bidder: &Bidder) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::WithdrawBidRefund(),
+    vec![
+      AccountMeta::new(bidder.keypair.pubkey(), true),
+      AccountMeta::new(bidder.bid_token_account, false),
+      AccountMeta::new(bid_refund(env, lot, &bidder.keypair.pubkey()), false),
+      AccountMeta::new(lot.bid_vault, false),
+      AccountMeta::new(lot.auction, false),
+      AccountMeta::new(lot.seller.pubkey(), false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(env, &lot.auction), false),
+    ],
+  )
+}
+
+#[tokio::test]
+async fn auction_delivers_nft_to_top_bidder_and_refunds_outbid_bidder() {
+  let mut env = setup().await;
+  let end_time = now(&mut env).await + 10;
+  let lot = setup_auction(&mut env, end_time, 0).await;
+  let first = setup_bidder(&mut env, &lot).await;
+  let second = setup_bidder(&mut env, &lot).await;
+
+  let result = place_bid(&mut env, &lot, &first, None, 9).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::BidTooLow as u32),
+  );
+  place_bid(&mut env, &lot, &first, None, 30).await.unwrap();
+  let result = place_bid(&mut env, &lot, &second, Some(&first), 30).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::BidTooLow as u32),
+  );
+  place_bid(&mut env, &lot, &second, Some(&first), 50)
+    .await
+    .unwrap();
+  assert_eq!(token_balance(&mut env, &lot.bid_vault).await, 80);
+  assert_eq!(auction_state(&mut env, &lot).await.pending_refunds, 30);
+
+  let instruction = withdraw_bid_refund_instruction(&env, &lot, &first);
+  process(&mut env, &[instruction], &[&first.keypair])
+    .await
+    .unwrap();
+  assert_eq!(token_balance(&mut env, &first.bid_token_account).await, 100);
+  let first_refund = bid_refund(&env, &lot, &first.keypair.pubkey());
+  assert_eq!(lamports(&mut env, &first_refund).await, 0);
+  assert_eq!(
+    lamports(&mut env, &first.keypair.pubkey()).await,
+    INITIALIZER_LAMPORTS
+  );
+  let instruction = withdraw_bid_refund_instruction(&env, &lot, &second);
+  let result = process(&mut env, &[instruction], &[&second.keypair]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::TopBidderCannotWithdraw as u32),
+  );
+
+  let instruction = settle_auction_instruction(&env, &lot, &second.nft_token_account);
+  let result = process(&mut env, &[instruction], &[]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::AuctionNotEnded as u32),
+  );
+
+  warp_past(&mut env, end_time).await;
+  let result = place_bid(&mut env, &lot, &first, Some(&second), 60).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::AuctionEnded as u32),
+  );
+  let instruction = settle_auction_instruction(&env, &lot, &second.nft_token_account);
+  process(&mut env, &[instruction], &[]).await.unwrap();
+  assert_eq!(token_balance(&mut env, &second.nft_token_account).await, 1);
+  assert_eq!(token_balance(&mut env, &second.bid_token_account).await, 50);
+  assert_eq!(
+    token_balance(&mut env, &lot.seller_bid_token_account).await,
+    50
+  );
+  assert_eq!(lamports(&mut env, &lot.vault).await, 0);
+  assert_eq!(lamports(&mut env, &lot.bid_vault).await, 0);
+  assert_eq!(lamports(&mut env, &lot.auction).await, 0);
+  assert_eq!(
+    lamports(&mut env, &lot.seller.pubkey()).await,
+    INITIALIZER_LAMPORTS + 2 * token_account_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn settled_auction_stays_open_until_last_refund_is_withdrawn() {
+  let mut env = setup().await;
+  let end_time = now(&mut env).await + 10;
+  let lot = setup_auction(&mut env, end_time, 0).await;
+  let first = setup_bidder(&mut env, &lot).await;
+  let second = setup_bidder(&mut env, &lot).await;
+  place_bid(&mut env, &lot, &first, None, 30).await.unwrap();
+  place_bid(&mut env, &lot, &second, Some(&first), 50)
+    .await
+    .unwrap();
+
+  warp_past(&mut env, end_time).await;
+  let instruction = settle_auction_instruction(&env, &lot, &first.nft_token_account);
+  let result = process(&mut env, &[instruction], &[]).await;
+  assert_instruction_error(result, InstructionError::InvalidAccountData);
+  let instruction = settle_auction_instruction(&env, &lot, &second.nft_token_account);
+  process(&mut env, &[instruction], &[]).await.unwrap();
+  assert_eq!(token_balance(&mut env, &second.nft_token_account).await, 1);
+  assert_eq!(token_balance(&mut env, &lot.bid_vault).await, 30);
+  assert!(auction_state(&mut env, &lot).await.settled);
+
+  let instruction = settle_auction_instruction(&env, &lot, &lot.seller_nft_token_account);
+  let result = process(&mut env, &[instruction], &[]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::InvalidEscrowStatus as u32),
+  );
+
+  let instruction = withdraw_bid_refund_instruction(&env, &lot, &first);
+  process(&mut env, &[instruction], &[&first.keypair])
+    .await
+    .unwrap();
+  assert_eq!(token_balance(&mut env, &first.bid_token_account).await, 100);
+  assert_eq!(lamports(&mut env, &lot.bid_vault).await, 0);
+  assert_eq!(lamports(&mut env, &lot.auction).await, 0);
+  assert_eq!(
+    lamports(&mut env, &lot.seller.pubkey()).await,
+    INITIALIZER_LAMPORTS + 2 * token_account_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn settle_returns_nft_to_seller_without_bids() {
+  let mut env = setup().await;
+  let end_time = now(&mut env).await + 10;
+  let lot = setup_auction(&mut env, end_time, 0).await;
+
+  warp_past(&mut env, end_time).await;
+  let instruction = settle_auction_instruction(&env, &lot, &lot.seller_nft_token_account);
+  process(&mut env, &[instruction], &[]).await.unwrap();
+  assert_eq!(
+    token_balance(&mut env, &lot.seller_nft_token_account).await,
+    1
+  );
+  assert_eq!(lamports(&mut env, &lot.vault).await, 0);
+  assert_eq!(lamports(&mut env, &lot.bid_vault).await, 0);
+  assert_eq!(lamports(&mut env, &lot.auction).await, 0);
+  assert_eq!(
+    lamports(&mut env, &lot.seller.pubkey()).await,
+    INITIALIZER_LAMPORTS + 2 * token_account_rent(&env)
+  );
+}
+
+#[tokio::test]
+async fn late_bid_extends_auction() {
+  let mut env = setup().await;
+  let end_time = now(&mut env).await + 10;
+  let lot = setup_auction(&mut env, end_time, 1_000).await;
+  let first = setup_bidder(&mut env, &lot).await;
+  let second = setup_bidder(&mut env, &lot).await;
+
+  place_bid(&mut env, &lot, &first, None, 30).await.unwrap();
+  assert!(auction_state(&mut env, &lot).await.end_time >= end_time + 990);
+
+  warp_past(&mut env, end_time).await;
+  place_bid(&mut env, &lot, &second, Some(&first), 50)
+    .await
+    .unwrap();
+  assert_eq!(token_balance(&mut env, &lot.bid_vault).await, 80);
+}
+
+struct RingParty {
+  keypair: Keypair,
+  mint: Pubkey,
+  vault: Pubkey,
+  receive_token_account: Pubkey,
+}
+
+struct Ring {
+  creator: Keypair,
+  parties: Vec<RingParty>,
+  ring: Pubkey,
+}
+
+/// Ring where each party deposits `amounts[i]` of its own fresh mint for the next party
+async fn setup_ring(env: &mut Env, amounts: &[u64]) -> Ring {
+  let creator = Keypair::new();
+  fund(env, &creator.pubkey(), INITIALIZER_LAMPORTS).await;
+  let mut keypairs = Vec::new();
+  let mut mints = Vec::new();
+  for _ in amounts {
+    keypairs.push(Keypair::new());
+    mints.push(create_mint(env).await);
+  }
+  let mut parties = Vec::new();
+  for (index, (keypair, &amount)) in keypairs.into_iter().zip(amounts).enumerate() {
+    let mint = mints[index];
+    let previous_mint = mints[(index + mints.len() - 1) % mints.len()];
+    let vault = create_token_account(env, &mint, &keypair.pubkey(), 0).await;
+    mint_to(env, &mint, &vault, amount).await;
+    let receive_token_account =
+      create_token_account(env, &previous_mint, &keypair.pubkey(), 0).await;
+    parties.push(RingParty {
+      keypair,
+      mint,
+      vault,
+      receive_token_account,
+    });
+  }
+  let (ring, _) = find_ring_swap_address(&env.program_id, &creator.pubkey(), 0);
+
+  let instruction = escrow_instruction(
+    env,
+    EscrowInstruction::InitRingSwap(InitRingSwapArgs {
+      seed: 0,
+      legs: parties
+        .iter()
+        .zip(amounts)
+        .map(|(party, &amount)| RingLeg {
+          party: party.keypair.pubkey(),
+          mint: party.mint,
+          amount,
+        })
+        .collect(),
+    }),
+    vec![
+      AccountMeta::new(creator.pubkey(), true),
+      AccountMeta::new(ring, false),
+      AccountMeta::new_readonly(sysvar::rent::id(), false),
+      AccountMeta::new_readonly(system_program::id(), false),
+      AccountMeta::new_readonly(find_config_address(&env.program_id).0, false),
+    ],
+  );
+  process(env, &[instruction], &[&creator]).await.unwrap();
+  Ring {
+    creator,
+    parties,
+    ring,
+  }
+}
+
+fn deposit_ring_instruction(env: &Env, ring: &Ring, party: &RingParty) -> Instruction {
+  escrow_instruction(
+    env,
+    EscrowInstruction::DepositRing(),
+    vec![
+      AccountMeta::new_readonly(party.keypair.pubkey(), true),
+      AccountMeta::new(party.vault, false),
+      AccountMeta::new_readonly(party.receive_token_account, false),
+      AccountMeta::new(ring.ring, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+    ],
+  )
+}
+
+async fn deposit_ring(env: &mut Env, ring: &Ring, party: &RingParty) {
+  let instruction = deposit_ring_instruction(env, ring, party);
+  process(env, &[instruction], &[&party.keypair])
+    .await
+    .unwrap();
+}
+
+fn settle_ring_instruction(env: &Env, ring: &Ring) -> Instruction {
+  let mut accounts = vec![
+    AccountMeta::new(ring.creator.pubkey(), false),
+    AccountMeta::new(ring.ring, false),
+    AccountMeta::new_readonly(spl_token::id(), false),
+    AccountMeta::new_readonly(vault_authority(env, &ring.ring), false),
+  ];
+  for (index, party) in ring.parties.iter().enumerate() {
+    let next = &ring.parties[(index + 1) % ring.parties.len()];
+    accounts.push(AccountMeta::new(party.vault, false));
+    accounts.push(AccountMeta::new(next.receive_token_account, false));
+    accounts.push(AccountMeta::new(party.keypair.pubkey(), false));
+  }
+  escrow_instruction(env, EscrowInstruction::SettleRing(), accounts)
+}
+
+#[tokio::test]
+async fn settle_ring_pays_every_deposit_to_next_party() {
+  let mut env = setup().await;
+  let ring = setup_ring(&mut env, &[10, 20, 30]).await;
+  deposit_ring(&mut env, &ring, &ring.parties[0]).await;
+  deposit_ring(&mut env, &ring, &ring.parties[1]).await;
+
+  let instruction = settle_ring_instruction(&env, &ring);
+  let result = process(&mut env, &[instruction], &[]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::RingIncomplete as u32),
+  );
+
+  // The last party can deposit and settle in one transaction
+  let instructions = [
+    deposit_ring_instruction(&env, &ring, &ring.parties[2]),
+    settle_ring_instruction(&env, &ring),
+  ];
+  process(&mut env, &instructions, &[&ring.parties[2].keypair])
+    .await
+    .unwrap();
+  for (party, received) in ring.parties.iter().zip(&[30, 10, 20]) {
+    assert_eq!(
+      token_balance(&mut env, &party.receive_token_account).await,
+      *received
+    );
+    assert_eq!(lamports(&mut env, &party.vault).await, 0);
+    assert_eq!(
+      lamports(&mut env, &party.keypair.pubkey()).await,
+      token_account_rent(&env)
+    );
+  }
+  assert_eq!(lamports(&mut env, &ring.ring).await, 0);
+  assert_eq!(
+    lamports(&mut env, &ring.creator.pubkey()).await,
+    INITIALIZER_LAMPORTS
+  );
+}
+
+#[tokio::test]
+async fn withdrawn_ring_can_be_closed() {
+  let mut env = setup().await;
+  let ring = setup_ring(&mut env, &[10, 20, 30]).await;
+  let party = &ring.parties[0];
+  deposit_ring(&mut env, &ring, party).await;
+  assert_eq!(
+    token_account(&mut env, &party.vault).await.unwrap().owner,
+    vault_authority(&env, &ring.ring)
+  );
+
+  let close_instruction = escrow_instruction(
+    &env,
+    EscrowInstruction::CloseRingSwap(),
+    vec![
+      AccountMeta::new(ring.creator.pubkey(), true),
+      AccountMeta::new(ring.ring, false),
+    ],
+  );
+  let result = process(&mut env, &[close_instruction.clone()], &[&ring.creator]).await;
+  assert_instruction_error(
+    result,
+    InstructionError::Custom(EscrowError::RingHasDeposits as u32),
+  );
+
+  let withdraw_instruction = escrow_instruction(
+    &env,
+    EscrowInstruction::WithdrawRing(),
+    vec![
+      AccountMeta::new_readonly(party.keypair.pubkey(), true),
+      AccountMeta::new(party.vault, false),
+      AccountMeta::new(ring.ring, false),
+      AccountMeta::new_readonly(spl_token::id(), false),
+      AccountMeta::new_readonly(vault_authority(&env, &ring.ring), false),
+    ],
+  );
+  process(
+    &mut env,
+    &[withdraw_instruction, close_instruction],
+    &[&party.keypair, &ring.creator],
+  )
+  .await
+  .unwrap();
+  let vault = token_account(&mut env, &party.vault).await.unwrap();
+  assert_eq!(vault.owner, party.keypair.pubkey());
+  assert_eq!(vault.amount, 10);
+  assert_eq!(lamports(&mut env, &ring.ring).await, 0);
+  assert_eq!(
+    lamports(&mut env, &ring.creator.pubkey()).await,
+    INITIALIZER_LAMPORTS
+  );
+}
@@ -0,0 +1,86 @@
+use {
+  escrow::{
+    error::EscrowError,
+    state::{Key, OrderNonce, ORDER_NONCE_WINDOW},
+  },
+  solana_program::{program_error::ProgramError, pubkey::Pubkey},
+};
+
+fn order_nonce() -> OrderNonce {
+  OrderNonce {
+    key: Key::OrderNonceV1,
+    maker: Pubkey::new_unique(),
+    nonce: 0,
+    used: 0,
+  }
+}
+
+fn assert_escrow_error(result: Result<(), ProgramError>, error: EscrowError) {
+  assert_eq!(result, Err(ProgramError::Custom(error as u32)));
+}
+
+#[test]
+fn nonce_fills_a_single_order() {
+  let mut order_nonce = order_nonce();
+  assert_eq!(order_nonce.use_nonce(3), Ok(()));
+  assert!(order_nonce.is_used(3));
+  assert_escrow_error(order_nonce.use_nonce(3), EscrowError::OrderNonceUsed);
+}
+
+#[test]
+fn nonces_can_be_used_out_of_order() {
+  let mut order_nonce = order_nonce();
+  assert_eq!(order_nonce.use_nonce(5), Ok(()));
+  assert_eq!(order_nonce.use_nonce(2), Ok(()));
+  assert_eq!(order_nonce.nonce, 0);
+  assert!(!order_nonce.is_used(0));
+  assert!(!order_nonce.is_used(4));
+
+  assert_eq!(order_nonce.use_nonce(0), Ok(()));
+  assert_eq!(order_nonce.use_nonce(1), Ok(()));
+  // The window starts at the lowest unused nonce
+  assert_eq!(order_nonce.nonce, 3);
+  assert!(order_nonce.is_used(2));
+  assert!(order_nonce.is_used(5));
+  assert_escrow_error(order_nonce.use_nonce(5), EscrowError::OrderNonceUsed);
+  assert_eq!(order_nonce.use_nonce(4), Ok(()));
+  assert_eq!(order_nonce.use_nonce(3), Ok(()));
+  assert_eq!(order_nonce.nonce, 6);
+  assert_eq!(order_nonce.used, 0);
+}
+
+#[test]
+fn nonce_outside_window_is_rejected() {
+  let mut order_nonce = order_nonce();
+  assert_escrow_error(
+    order_nonce.use_nonce(ORDER_NONCE_WINDOW),
+    EscrowError::OrderNonceOutsideWindow,
+  );
+  assert_eq!(order_nonce.use_nonce(ORDER_NONCE_WINDOW - 1), Ok(()));
+  assert_eq!(order_nonce.use_nonce(0), Ok(()));
+  assert_eq!(order_nonce.use_nonce(ORDER_NONCE_WINDOW), Ok(()));
+}
+
+#[test]
+fn cancel_below_keeps_used_nonces_above() {
+  let mut order_nonce = order_nonce();
+  assert_eq!(order_nonce.use_nonce(10), Ok(()));
+  order_nonce.cancel_below(4);
+  assert_eq!(order_nonce.nonce, 4);
+  assert!(order_nonce.is_used(3));
+  assert!(order_nonce.is_used(10));
+  assert!(!order_nonce.is_used(4));
+
+  order_nonce.cancel_below(4 + ORDER_NONCE_WINDOW);
+  assert_eq!(order_nonce.used, 0);
+  assert!(order_nonce.is_used(10));
+  assert_escrow_error(order_nonce.use_nonce(10), EscrowError::OrderNonceUsed);
+}
+
+#[test]
+fn last_nonce_can_not_overflow() {
+  let mut order_nonce = order_nonce();
+  order_nonce.cancel_below(u64::MAX);
+  assert_escrow_error(order_nonce.use_nonce(u64::MAX), EscrowError::AmountOverflow);
+  assert!(!order_nonce.is_used(u64::MAX));
+}
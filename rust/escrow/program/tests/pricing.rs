@@ -0,0 +1,93 @@
+use {
+  escrow::state::{DutchPricing, Escrow, SideKind},
+  solana_program::pubkey::Pubkey,
+};
+
+fn dutch_pricing() -> DutchPricing {
+  DutchPricing {
+    start_price: 1_000,
+    end_price: 100,
+    start_time: 10_000,
+    end_time: 10_007,
+  }
+}
+
+fn escrow(offered_amount: u64, expected_amount: u64) -> Escrow {
+  Escrow {
+    is_initialized: true,
+    initializer_pubkey: Pubkey::new_unique(),
+    temp_token_account_pubkey: Pubkey::new_unique(),
+    initializer_token_to_receive_account_pubkey: Pubkey::new_unique(),
+    expected_amount,
+    vault_authority_bump_seed: 255,
+    seed: 0,
+    offered_mint: Pubkey::new_unique(),
+    requested_mint: Pubkey::new_unique(),
+    offered_amount,
+    filled_amount: 0,
+    expires_at: None,
+    allowed_taker: None,
+    offered_kind: SideKind::Token,
+    requested_kind: SideKind::Token,
+    dutch_pricing: None,
+  }
+}
+
+#[test]
+fn dutch_price_holds_outside_its_window() {
+  let pricing = dutch_pricing();
+  assert_eq!(pricing.price_at(0).unwrap(), 1_000);
+  assert_eq!(pricing.price_at(10_000).unwrap(), 1_000);
+  assert_eq!(pricing.price_at(10_007).unwrap(), 100);
+  assert_eq!(pricing.price_at(i64::MAX).unwrap(), 100);
+}
+
+#[test]
+fn dutch_price_decays_linearly_rounding_up() {
+  let pricing = dutch_pricing();
+  // 900 of decay over 7 seconds, 128.57 per second
+  assert_eq!(pricing.price_at(10_001).unwrap(), 872);
+  assert_eq!(pricing.price_at(10_006).unwrap(), 229);
+  let prices: Vec<u64> = (10_000..=10_007)
+    .map(|now| pricing.price_at(now).unwrap())
+    .collect();
+  assert!(prices.windows(2).all(|pair| pair[0] >= pair[1]));
+}
+
+#[test]
+fn dutch_pricing_validity() {
+  assert!(dutch_pricing().is_valid());
+  let flat = DutchPricing {
+    end_price: 1_000,
+    ..dutch_pricing()
+  };
+  assert!(flat.is_valid());
+  let rising = DutchPricing {
+    end_price: 1_001,
+    ..dutch_pricing()
+  };
+  assert!(!rising.is_valid());
+  let empty_window = DutchPricing {
+    end_time: 10_000,
+    ..dutch_pricing()
+  };
+  assert!(!empty_window.is_valid());
+}
+
+#[test]
+fn payment_at_uses_dutch_price() {
+  let mut escrow = escrow(10, 1_000);
+  escrow.dutch_pricing = Some(dutch_pricing());
+  assert_eq!(escrow.payment_at(10, 10_000).unwrap(), 1_000);
+  assert_eq!(escrow.payment_at(10, 10_007).unwrap(), 100);
+  // 3 of 10 at 872 is 261.6
+  assert_eq!(escrow.payment_at(3, 10_001).unwrap(), 262);
+}
+
+#[test]
+fn payment_without_dutch_pricing_rounds_up() {
+  let escrow = escrow(3, 10);
+  assert_eq!(escrow.payment_at(1, 0).unwrap(), 4);
+  assert_eq!(escrow.payment_at(3, 0).unwrap(), 10);
+  assert_eq!(escrow.payment_for(0).unwrap(), 0);
+}
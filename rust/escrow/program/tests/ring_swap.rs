@@ -0,0 +1,518 @@
+use {
+  borsh::BorshSerialize,
+  escrow::{
+    error::EscrowError,
+    instruction::{EscrowInstruction, InitRingSwapArgs, RingLeg},
+    pda::find_ring_swap_address,
+    state::{Key, RingParticipant, RingSwap, MAX_RING_SWAP_LEN},
+  },
+  solana_program::{program_error::ProgramError, pubkey::Pubkey, system_program},
+};
+
+mod common;
+
+use common::{
+  any_account, assert_escrow_error, process, rent_account, token_account, token_program_account,
+  Fixture, TestAccount,
+};
+
+impl Fixture {
+  /// Ring of three parties at the escrow address, nobody has deposited yet
+  fn ring_swap(&self) -> RingSwap {
+    let mints = [self.offered_mint, self.requested_mint, Pubkey::new_unique()];
+    RingSwap {
+      key: Key::RingSwapV1,
+      creator: self.initializer,
+      seed: 0,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      participants: mints
+        .iter()
+        .map(|mint| RingParticipant {
+          party: Pubkey::new_unique(),
+          mint: *mint,
+          amount: 100,
+          vault: None,
+          receive_account: None,
+        })
+        .collect(),
+    }
+  }
+
+  fn pack_ring_swap(&self, ring: &RingSwap) -> TestAccount {
+    let mut data = ring.try_to_vec().unwrap();
+    data.resize(MAX_RING_SWAP_LEN, 0);
+    TestAccount::new(self.escrow, self.program_id, data)
+  }
+}
+
+/// Marks every party of `ring` as having deposited
+fn deposit_everyone(ring: &mut RingSwap) {
+  for participant in ring.participants.iter_mut() {
+    participant.vault = Some(Pubkey::new_unique());
+    participant.receive_account = Some(Pubkey::new_unique());
+  }
+}
+
+fn init_ring_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  let (ring, _) = find_ring_swap_address(&fixture.program_id, &fixture.initializer, 0);
+  vec![
+    fixture.initializer_account(),
+    TestAccount::new(ring, system_program::id(), vec![]),
+    rent_account(),
+    any_account(),
+    fixture.initialized_config_account(Pubkey::new_unique()),
+  ]
+}
+
+fn init_ring_instruction(mints: &[Pubkey]) -> EscrowInstruction {
+  EscrowInstruction::InitRingSwap(InitRingSwapArgs {
+    seed: 0,
+    legs: mints
+      .iter()
+      .map(|mint| RingLeg {
+        party: Pubkey::new_unique(),
+        mint: *mint,
+        amount: 100,
+      })
+      .collect(),
+  })
+}
+
+#[test]
+fn init_ring_rejects_neighbours_with_same_mint() {
+  let fixture = Fixture::new();
+  let other_mint = Pubkey::new_unique();
+  for mints in [
+    [fixture.offered_mint, fixture.offered_mint, other_mint],
+    [fixture.offered_mint, other_mint, fixture.offered_mint],
+  ] {
+    let mut accounts = init_ring_accounts(&fixture);
+    let result = process(
+      &fixture.program_id,
+      &mut accounts,
+      init_ring_instruction(&mints),
+    );
+    assert_escrow_error(result, EscrowError::RingLegsShareMint);
+  }
+}
+
+#[test]
+fn init_ring_rejects_too_few_parties() {
+  let fixture = Fixture::new();
+  let mut accounts = init_ring_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_ring_instruction(&[fixture.offered_mint, fixture.requested_mint]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidRingParties);
+}
+
+/// Accounts of `party` depositing its leg of `ring`, the previous party pays `previous_mint`
+fn deposit_ring_accounts(
+  fixture: &Fixture,
+  ring: &RingSwap,
+  party: Pubkey,
+  mint: Pubkey,
+  previous_mint: Pubkey,
+) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(party, system_program::id(), vec![]).signer(),
+    token_account(Pubkey::new_unique(), mint, party),
+    token_account(Pubkey::new_unique(), previous_mint, party),
+    fixture.pack_ring_swap(ring),
+    token_program_account(),
+  ]
+}
+
+#[test]
+fn deposit_ring_rejects_receive_account_of_next_mint() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let party = &ring.participants[0];
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    party.party,
+    party.mint,
+    ring.participants[1].mint,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn deposit_ring_requires_party_signature() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let party = &ring.participants[1];
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    party.party,
+    party.mint,
+    ring.participants[0].mint,
+  );
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn deposit_ring_rejects_outsider() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    Pubkey::new_unique(),
+    ring.participants[1].mint,
+    ring.participants[0].mint,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_escrow_error(result, EscrowError::NotEscrowParty);
+}
+
+#[test]
+fn deposit_ring_rejects_second_deposit() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  ring.participants[1].vault = Some(Pubkey::new_unique());
+  let party = &ring.participants[1];
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    party.party,
+    party.mint,
+    ring.participants[0].mint,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidEscrowStatus);
+}
+
+#[test]
+fn deposit_ring_rejects_another_mint() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let party = &ring.participants[1];
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    party.party,
+    ring.participants[2].mint,
+    ring.participants[0].mint,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_escrow_error(result, EscrowError::OfferedMintMismatch);
+}
+
+#[test]
+fn deposit_ring_rejects_another_amount() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  ring.participants[1].amount = 99;
+  let party = &ring.participants[1];
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    party.party,
+    party.mint,
+    ring.participants[0].mint,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_escrow_error(result, EscrowError::ExpectedAmountMismatch);
+}
+
+#[test]
+fn deposit_ring_rejects_receive_account_of_another_party() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let party = &ring.participants[1];
+  let mut accounts = deposit_ring_accounts(
+    &fixture,
+    &ring,
+    party.party,
+    party.mint,
+    ring.participants[0].mint,
+  );
+  accounts[2] = token_account(
+    Pubkey::new_unique(),
+    ring.participants[0].mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::DepositRing(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+fn withdraw_ring_accounts(fixture: &Fixture, ring: &RingSwap, index: usize) -> Vec<TestAccount> {
+  let participant = &ring.participants[index];
+  vec![
+    TestAccount::new(participant.party, system_program::id(), vec![]).signer(),
+    token_account(
+      participant.vault.unwrap_or_else(Pubkey::new_unique),
+      participant.mint,
+      fixture.vault_authority,
+    ),
+    fixture.pack_ring_swap(ring),
+    token_program_account(),
+    fixture.vault_authority_account(),
+  ]
+}
+
+#[test]
+fn withdraw_ring_requires_party_signature() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  let mut accounts = withdraw_ring_accounts(&fixture, &ring, 2);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawRing(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn withdraw_ring_rejects_party_without_deposit() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let mut accounts = withdraw_ring_accounts(&fixture, &ring, 0);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawRing(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidEscrowStatus);
+}
+
+#[test]
+fn withdraw_ring_rejects_vault_of_another_party() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  let mut accounts = withdraw_ring_accounts(&fixture, &ring, 0);
+  accounts[1] = withdraw_ring_accounts(&fixture, &ring, 1).remove(1);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawRing(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn withdraw_ring_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  let mut accounts = withdraw_ring_accounts(&fixture, &ring, 0);
+  accounts[4] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::WithdrawRing(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+fn settle_ring_accounts(fixture: &Fixture, ring: &RingSwap) -> Vec<TestAccount> {
+  let mut accounts = vec![
+    TestAccount::new(ring.creator, system_program::id(), vec![]),
+    fixture.pack_ring_swap(ring),
+    token_program_account(),
+    fixture.vault_authority_account(),
+  ];
+  for (index, participant) in ring.participants.iter().enumerate() {
+    let next = &ring.participants[(index + 1) % ring.participants.len()];
+    accounts.push(token_account(
+      participant.vault.unwrap_or_else(Pubkey::new_unique),
+      participant.mint,
+      fixture.vault_authority,
+    ));
+    accounts.push(token_account(
+      next.receive_account.unwrap_or_else(Pubkey::new_unique),
+      participant.mint,
+      next.party,
+    ));
+    accounts.push(TestAccount::new(
+      participant.party,
+      system_program::id(),
+      vec![],
+    ));
+  }
+  accounts
+}
+
+#[test]
+fn settle_ring_rejects_incomplete_ring() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  ring.participants[1].vault = None;
+  ring.participants[1].receive_account = None;
+  let mut accounts = settle_ring_accounts(&fixture, &ring);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleRing(),
+  );
+  assert_escrow_error(result, EscrowError::RingIncomplete);
+}
+
+#[test]
+fn settle_ring_rejects_another_creator() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  let mut accounts = settle_ring_accounts(&fixture, &ring);
+  accounts[0] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleRing(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn settle_ring_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  let mut accounts = settle_ring_accounts(&fixture, &ring);
+  accounts[3] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleRing(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn settle_ring_rejects_paying_deposit_back() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  // The first deposit must go to the second party, not back to the first one
+  let mut accounts = settle_ring_accounts(&fixture, &ring);
+  accounts[5] = token_account(
+    ring.participants[0].receive_account.unwrap(),
+    ring.participants[0].mint,
+    ring.participants[0].party,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleRing(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn settle_ring_rejects_missing_party_group() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  deposit_everyone(&mut ring);
+  let mut accounts = settle_ring_accounts(&fixture, &ring);
+  accounts.truncate(accounts.len() - 3);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::SettleRing(),
+  );
+  assert_eq!(result, Err(ProgramError::NotEnoughAccountKeys));
+}
+
+fn close_ring_accounts(fixture: &Fixture, ring: &RingSwap) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(ring.creator, system_program::id(), vec![]).signer(),
+    fixture.pack_ring_swap(ring),
+  ]
+}
+
+#[test]
+fn close_ring_requires_creator_signature() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let mut accounts = close_ring_accounts(&fixture, &ring);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseRingSwap(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn close_ring_rejects_another_creator() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let mut accounts = close_ring_accounts(&fixture, &ring);
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseRingSwap(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn close_ring_rejects_ring_holding_a_deposit() {
+  let fixture = Fixture::new();
+  let mut ring = fixture.ring_swap();
+  ring.participants[2].vault = Some(Pubkey::new_unique());
+  let mut accounts = close_ring_accounts(&fixture, &ring);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseRingSwap(),
+  );
+  assert_escrow_error(result, EscrowError::RingHasDeposits);
+}
+
+#[test]
+fn close_ring_rejects_ring_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let ring = fixture.ring_swap();
+  let mut accounts = close_ring_accounts(&fixture, &ring);
+  accounts[1].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseRingSwap(),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
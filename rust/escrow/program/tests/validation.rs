@@ -0,0 +1,2500 @@
+use {
+  borsh::{BorshDeserialize, BorshSerialize},
+  escrow::{
+    error::EscrowError,
+    instruction::{
+      EscrowInstruction, ExchangeArgs, ExchangeManyArgs, FillSignedOrderArgs, InitBasketEscrowArgs,
+      InitConfigArgs, InitEscrowArgs, RequestedLeg, SetOrderNonceArgs, UpdateConfigArgs,
+      UpdateEscrowArgs,
+    },
+    pda::{
+      find_basket_escrow_address, find_escrow_address, find_metadata_address,
+      find_order_book_page_address, find_order_delegate, find_order_nonce_address,
+    },
+    state::{
+      BasketEscrow, BasketLeg, Config, DutchPricing, Escrow, EscrowReceive, FeeRecipient, Key,
+      OrderBookPage, OrderNonce, SideKind, SignedOrder, MAX_FEE_RECIPIENTS, ORDER_BOOK_PAGE_LEN,
+      ORDER_NONCE_LEN,
+    },
+    util::ed25519_program,
+  },
+  solana_program::{
+    program_error::ProgramError, program_option::COption, program_pack::Pack, pubkey::Pubkey,
+    system_program, sysvar,
+  },
+  spl_token::state::Account as TokenAccount,
+  tokenmetadataprogram::state::{Creator, Data, Key as MetadataKey, Metadata, MAX_METADATA_LEN},
+};
+
+mod common;
+
+use common::{
+  any_account, assert_escrow_error, clock_account, clock_account_at, mint_account, process,
+  rent_account, token_account, token_program_account, Fixture, TestAccount,
+};
+
+fn init_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    TestAccount::new(fixture.temp_token_account, fixture.initializer, vec![]),
+    fixture.receive_token_account(),
+    fixture.uninitialized_escrow_account(),
+    TestAccount::new(sysvar::rent::id(), sysvar::id(), vec![]),
+    token_program_account(),
+    any_account(),
+    fixture.vault_authority_account(),
+    fixture.config_account(),
+    any_account(),
+    any_account(),
+  ]
+}
+
+fn init_instruction(offered_kind: SideKind, offered_amount: u64) -> EscrowInstruction {
+  EscrowInstruction::InitEscrow(InitEscrowArgs {
+    data: EscrowReceive { amount: 10 },
+    seed: 0,
+    expires_at: None,
+    allowed_taker: None,
+    offered_kind,
+    requested_kind: SideKind::Token,
+    offered_amount,
+    dutch_pricing: None,
+  })
+}
+
+fn cancel_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    fixture.temp_token_account(),
+    fixture.escrow_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    any_account(),
+  ]
+}
+
+fn exchange_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  vec![
+    any_account().signer(),
+    any_account(),
+    any_account(),
+    fixture.temp_token_account(),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    fixture.receive_token_account(),
+    fixture.escrow_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+  ]
+}
+
+/// Accounts of an exchange that passes every check up to the payment
+fn payable_exchange_accounts(fixture: &Fixture, escrow: TestAccount) -> Vec<TestAccount> {
+  let taker = Pubkey::new_unique();
+  let mut accounts = exchange_accounts(fixture);
+  accounts[0] = TestAccount::new(taker, system_program::id(), vec![]).signer();
+  accounts[1] = token_account(Pubkey::new_unique(), fixture.requested_mint, taker);
+  accounts[2] = token_account(Pubkey::new_unique(), fixture.offered_mint, taker);
+  accounts[6] = escrow;
+  accounts[11] = fixture.config_account();
+  accounts
+}
+
+fn exchange_instruction() -> EscrowInstruction {
+  exchange_instruction_for(10, 100)
+}
+
+fn exchange_instruction_for(max_payment: u64, fill_amount: u64) -> EscrowInstruction {
+  EscrowInstruction::Exchange(ExchangeArgs {
+    data: EscrowReceive {
+      amount: max_payment,
+    },
+    fill_amount,
+  })
+}
+
+#[test]
+fn cancel_requires_initializer_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_accounts(&fixture);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn cancel_rejects_escrow_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_accounts(&fixture);
+  accounts[2].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn cancel_rejects_uninitialized_escrow() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_accounts(&fixture);
+  accounts[2] = fixture.uninitialized_escrow_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::UninitializedEscrow);
+}
+
+#[test]
+fn cancel_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_accounts(&fixture);
+  accounts[4] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn cancel_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_accounts(&fixture);
+  accounts[3] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn exchange_requires_taker_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_accounts(&fixture);
+  accounts[0].is_signer = false;
+  let result = process(&fixture.program_id, &mut accounts, exchange_instruction());
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn exchange_many_checks_each_leg() {
+  let fixture = Fixture::new();
+  let mut accounts = vec![
+    any_account().signer(),
+    any_account(),
+    any_account(),
+    token_program_account(),
+    clock_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    fixture.temp_token_account(),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    fixture.receive_token_account(),
+    fixture.uninitialized_escrow_account(),
+    fixture.vault_authority_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+  ];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeMany(ExchangeManyArgs {
+      legs: vec![ExchangeArgs {
+        data: EscrowReceive { amount: 10 },
+        fill_amount: 100,
+      }],
+    }),
+  );
+  assert_escrow_error(result, EscrowError::UninitializedEscrow);
+}
+
+#[test]
+fn exchange_rejects_escrow_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_accounts(&fixture);
+  accounts[6].owner = Pubkey::new_unique();
+  let result = process(&fixture.program_id, &mut accounts, exchange_instruction());
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn exchange_rejects_uninitialized_escrow() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_accounts(&fixture);
+  accounts[6] = fixture.uninitialized_escrow_account();
+  let result = process(&fixture.program_id, &mut accounts, exchange_instruction());
+  assert_escrow_error(result, EscrowError::UninitializedEscrow);
+}
+
+#[test]
+fn exchange_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_accounts(&fixture);
+  accounts[7] = any_account();
+  let result = process(&fixture.program_id, &mut accounts, exchange_instruction());
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn exchange_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_accounts(&fixture);
+  accounts[8] = any_account();
+  let result = process(&fixture.program_id, &mut accounts, exchange_instruction());
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn expire_rejects_uninitialized_escrow() {
+  let fixture = Fixture::new();
+  let mut accounts = vec![
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    any_account(),
+    fixture.temp_token_account(),
+    fixture.uninitialized_escrow_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account(),
+    any_account(),
+  ];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::UninitializedEscrow);
+}
+
+#[test]
+fn update_requires_initializer_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = vec![
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    any_account(),
+    fixture.temp_token_account(),
+    fixture.escrow_account(),
+    fixture.receive_token_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    any_account(),
+  ];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::UpdateEscrow(UpdateEscrowArgs {
+      expected_amount: Some(20),
+      deposit_amount: 0,
+      withdraw_amount: 0,
+    }),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn exchange_rejects_payment_above_maximum() {
+  let fixture = Fixture::new();
+  let mut accounts = payable_exchange_accounts(&fixture, fixture.escrow_account());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(9, 100),
+  );
+  assert_escrow_error(result, EscrowError::PaymentAboveMaximum);
+}
+
+#[test]
+fn exchange_rounds_partial_payment_up() {
+  let fixture = Fixture::new();
+  let mut accounts = payable_exchange_accounts(&fixture, fixture.escrow_account());
+  // 15 of 100 offered for 10 requested is 1.5, rounded up to 2
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(1, 15),
+  );
+  assert_escrow_error(result, EscrowError::PaymentAboveMaximum);
+}
+
+#[test]
+fn exchange_charges_current_dutch_price() {
+  let fixture = Fixture::new();
+  let mut escrow = fixture.escrow();
+  escrow.dutch_pricing = Some(DutchPricing {
+    start_price: 100,
+    end_price: 20,
+    start_time: 1_000,
+    end_time: 2_000,
+  });
+  let mut accounts = payable_exchange_accounts(&fixture, fixture.pack_escrow(escrow));
+  // Halfway through, the price has decayed to 60
+  accounts[9] = clock_account_at(1_500);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(59, 100),
+  );
+  assert_escrow_error(result, EscrowError::PaymentAboveMaximum);
+}
+
+#[test]
+fn exchange_rejects_fill_above_vault_balance() {
+  let fixture = Fixture::new();
+  let mut accounts = payable_exchange_accounts(&fixture, fixture.escrow_account());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(20, 101),
+  );
+  assert_escrow_error(result, EscrowError::InvalidFillAmount);
+}
+
+#[test]
+fn exchange_rejects_empty_fill() {
+  let fixture = Fixture::new();
+  let mut accounts = payable_exchange_accounts(&fixture, fixture.escrow_account());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(10, 0),
+  );
+  assert_escrow_error(result, EscrowError::InvalidFillAmount);
+}
+
+#[test]
+fn init_rejects_empty_lamport_offer() {
+  let fixture = Fixture::new();
+  let mut accounts = init_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Lamports, 0),
+  );
+  assert_escrow_error(result, EscrowError::EmptyOffer);
+}
+
+#[test]
+fn init_rejects_empty_token_offer() {
+  let fixture = Fixture::new();
+  let mut accounts = init_accounts(&fixture);
+  let mut temp_token_account = fixture.temp_token_account();
+  let mut temp_token_account_info = TokenAccount::unpack(&temp_token_account.data).unwrap();
+  temp_token_account_info.amount = 0;
+  TokenAccount::pack(temp_token_account_info, &mut temp_token_account.data).unwrap();
+  accounts[1] = temp_token_account;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::EmptyOffer);
+}
+
+#[test]
+fn init_rejects_already_delegated_account() {
+  let fixture = Fixture::new();
+  let mut accounts = init_accounts(&fixture);
+  let mut delegating_account = fixture.temp_token_account();
+  let mut delegating_account_info = TokenAccount::unpack(&delegating_account.data).unwrap();
+  delegating_account_info.delegate = COption::Some(Pubkey::new_unique());
+  delegating_account_info.delegated_amount = 10;
+  TokenAccount::pack(delegating_account_info, &mut delegating_account.data).unwrap();
+  accounts[1] = delegating_account;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Delegate, 10),
+  );
+  assert_escrow_error(result, EscrowError::TokenAccountAlreadyDelegated);
+}
+
+#[test]
+fn expire_rejects_refund_to_delegating_account() {
+  let fixture = Fixture::new();
+  let mut escrow = fixture.escrow();
+  escrow.offered_kind = SideKind::Delegate;
+  escrow.expires_at = Some(0);
+  let delegating_account = token_account(
+    fixture.temp_token_account,
+    fixture.offered_mint,
+    fixture.initializer,
+  );
+  let mut accounts = vec![
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    token_account(
+      fixture.temp_token_account,
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    delegating_account,
+    fixture.pack_escrow(escrow),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account(),
+    any_account(),
+  ];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+fn update_config_instruction(args: UpdateConfigArgs) -> EscrowInstruction {
+  EscrowInstruction::UpdateConfig(args)
+}
+
+fn no_config_update() -> UpdateConfigArgs {
+  UpdateConfigArgs {
+    admin: None,
+    fee_basis_points: None,
+    fee_recipients: None,
+    paused: None,
+    token_metadata_program: None,
+  }
+}
+
+#[test]
+fn update_config_keys_fee_recipients_by_kind() {
+  let fixture = Fixture::new();
+  let admin = Pubkey::new_unique();
+  let mut accounts = vec![
+    TestAccount::new(admin, system_program::id(), vec![]).signer(),
+    fixture.initialized_config_account(admin),
+  ];
+  let wallet = Pubkey::new_unique();
+  let token_account = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(UpdateConfigArgs {
+      fee_recipients: Some(vec![
+        FeeRecipient {
+          kind: SideKind::Lamports,
+          mint: spl_token::native_mint::id(),
+          account: wallet,
+        },
+        FeeRecipient {
+          kind: SideKind::Token,
+          mint: spl_token::native_mint::id(),
+          account: token_account,
+        },
+      ]),
+      ..no_config_update()
+    }),
+  );
+  assert_eq!(result, Ok(()));
+
+  let config = Config::deserialize(&mut &accounts[1].data[..]).unwrap();
+  let mint = spl_token::native_mint::id();
+  assert_eq!(
+    config.fee_for(SideKind::Lamports, &mint, &wallet, 1_000),
+    Ok(10)
+  );
+  assert_eq!(
+    config.fee_for(SideKind::Token, &mint, &token_account, 1_000),
+    Ok(10)
+  );
+  assert_eq!(
+    config.fee_for(SideKind::Delegate, &mint, &token_account, 1_000),
+    Ok(10)
+  );
+  assert_eq!(
+    config.fee_for(SideKind::Lamports, &mint, &token_account, 1_000),
+    Err(ProgramError::Custom(
+      EscrowError::InvalidFeeRecipient as u32
+    ))
+  );
+  assert_eq!(
+    config.fee_for(SideKind::Token, &Pubkey::new_unique(), &wallet, 1_000),
+    Ok(0)
+  );
+}
+
+#[test]
+fn update_config_rejects_delegate_fee_recipient() {
+  let fixture = Fixture::new();
+  let admin = Pubkey::new_unique();
+  let mut accounts = vec![
+    TestAccount::new(admin, system_program::id(), vec![]).signer(),
+    fixture.initialized_config_account(admin),
+  ];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(UpdateConfigArgs {
+      fee_recipients: Some(vec![FeeRecipient {
+        kind: SideKind::Delegate,
+        mint: fixture.offered_mint,
+        account: Pubkey::new_unique(),
+      }]),
+      ..no_config_update()
+    }),
+  );
+  assert_escrow_error(result, EscrowError::UnsupportedSideKind);
+}
+
+/// Exchange accounts with royalties enabled and `offered_mint` as the offered mint account
+fn royalty_exchange_accounts(fixture: &Fixture, offered_mint: TestAccount) -> Vec<TestAccount> {
+  let mut accounts = payable_exchange_accounts(fixture, fixture.escrow_account());
+  accounts[11] = fixture.pack_config(Config {
+    key: Key::ConfigV1,
+    admin: Pubkey::new_unique(),
+    fee_basis_points: 0,
+    paused: false,
+    token_metadata_program: Some(Pubkey::new_unique()),
+    fee_recipients: vec![],
+  });
+  accounts[16] = offered_mint;
+  accounts
+}
+
+#[test]
+fn exchange_checks_offered_mint_account() {
+  let fixture = Fixture::new();
+  let mut accounts = royalty_exchange_accounts(&fixture, mint_account(Pubkey::new_unique(), 1, 0));
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(5, 50),
+  );
+  assert_escrow_error(result, EscrowError::OfferedMintMismatch);
+}
+
+#[test]
+fn exchange_loads_metadata_of_nft() {
+  let fixture = Fixture::new();
+  let mut accounts = royalty_exchange_accounts(&fixture, mint_account(fixture.offered_mint, 1, 0));
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(5, 50),
+  );
+  assert_escrow_error(result, EscrowError::InvalidMetadataKey);
+}
+
+#[test]
+fn exchange_skips_royalties_of_fungible_token() {
+  let fixture = Fixture::new();
+  for &(supply, decimals) in &[(100, 0), (1, 6)] {
+    let mut accounts = royalty_exchange_accounts(
+      &fixture,
+      mint_account(fixture.offered_mint, supply, decimals),
+    );
+    // The metadata account is not even looked at
+    let result = process(
+      &fixture.program_id,
+      &mut accounts,
+      exchange_instruction_for(5, 50),
+    );
+    assert_eq!(result, Ok(()));
+  }
+}
+
+#[test]
+fn exchange_rejects_royalties_and_fees_above_payment() {
+  let fixture = Fixture::new();
+  let token_metadata_program = Pubkey::new_unique();
+  let requested_fee_recipient = any_account();
+  let mut accounts = royalty_exchange_accounts(&fixture, mint_account(fixture.offered_mint, 1, 0));
+  accounts[11] = fixture.pack_config(Config {
+    key: Key::ConfigV1,
+    admin: Pubkey::new_unique(),
+    fee_basis_points: 5000,
+    paused: false,
+    token_metadata_program: Some(token_metadata_program),
+    fee_recipients: vec![FeeRecipient {
+      kind: SideKind::Token,
+      mint: fixture.requested_mint,
+      account: requested_fee_recipient.key,
+    }],
+  });
+  accounts[12] = requested_fee_recipient;
+  // The creators take the whole payment on top of the 50% fee
+  let (metadata_key, _) = find_metadata_address(&token_metadata_program, &fixture.offered_mint);
+  let mut data = Metadata {
+    key: MetadataKey::MetadataV1,
+    update_authority: Pubkey::new_unique(),
+    mint: fixture.offered_mint,
+    data: Data {
+      name: String::new(),
+      symbol: String::new(),
+      uri: String::new(),
+      seller_fee_basis_points: 10000,
+      creators: Some(vec![Creator {
+        address: Pubkey::new_unique(),
+        verified: true,
+        share: 100,
+      }]),
+    },
+    primary_sale_happened: true,
+    is_mutable: false,
+    edition_nonce: None,
+  }
+  .try_to_vec()
+  .unwrap();
+  data.resize(MAX_METADATA_LEN, 0);
+  accounts[14] = TestAccount::new(metadata_key, token_metadata_program, data);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    exchange_instruction_for(10, 100),
+  );
+  assert_escrow_error(result, EscrowError::FeesExceedPayment);
+}
+
+#[test]
+fn update_config_disables_royalties() {
+  let fixture = Fixture::new();
+  let admin = Pubkey::new_unique();
+  let mut accounts = vec![
+    TestAccount::new(admin, system_program::id(), vec![]).signer(),
+    fixture.initialized_config_account(admin),
+  ];
+  let token_metadata_program = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(UpdateConfigArgs {
+      token_metadata_program: Some(Some(token_metadata_program)),
+      ..no_config_update()
+    }),
+  );
+  assert_eq!(result, Ok(()));
+  let config = Config::deserialize(&mut &accounts[1].data[..]).unwrap();
+  assert_eq!(config.token_metadata_program, Some(token_metadata_program));
+
+  // Leaving it out keeps the program
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(no_config_update()),
+  );
+  assert_eq!(result, Ok(()));
+  let config = Config::deserialize(&mut &accounts[1].data[..]).unwrap();
+  assert_eq!(config.token_metadata_program, Some(token_metadata_program));
+
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(UpdateConfigArgs {
+      token_metadata_program: Some(None),
+      ..no_config_update()
+    }),
+  );
+  assert_eq!(result, Ok(()));
+  let config = Config::deserialize(&mut &accounts[1].data[..]).unwrap();
+  assert_eq!(config.token_metadata_program, None);
+}
+
+#[test]
+fn exchange_basket_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = vec![
+    any_account().signer(),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    any_account(),
+    any_account(),
+    any_account(),
+    any_account(),
+    fixture.config_account(),
+  ];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+/// Instructions sysvar holding `instructions`, the program's own one being `current_index`.
+/// Accounts of the instructions are left out, the program doesn't read them
+fn instructions_sysvar_account(
+  instructions: &[(Pubkey, Vec<u8>)],
+  current_index: u16,
+) -> TestAccount {
+  let mut data = (instructions.len() as u16).to_le_bytes().to_vec();
+  let offsets_start = data.len();
+  data.resize(offsets_start + 2 * instructions.len(), 0);
+  for (index, (program_id, instruction_data)) in instructions.iter().enumerate() {
+    let offset = (data.len() as u16).to_le_bytes();
+    data[offsets_start + 2 * index..offsets_start + 2 * index + 2].copy_from_slice(&offset);
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(program_id.as_ref());
+    data.extend_from_slice(&(instruction_data.len() as u16).to_le_bytes());
+    data.extend_from_slice(instruction_data);
+  }
+  data.extend_from_slice(&current_index.to_le_bytes());
+  TestAccount::new(sysvar::instructions::id(), sysvar::id(), data)
+}
+
+/// Data of an ed25519 program instruction verifying a signature of `message` by `public_key`
+fn ed25519_instruction_data(public_key: &Pubkey, message: &[u8]) -> Vec<u8> {
+  let public_key_offset: u16 = 16;
+  let signature_offset = public_key_offset + 32;
+  let message_offset = signature_offset + 64;
+  let mut data = vec![1, 0];
+  for field in &[
+    signature_offset,
+    u16::MAX,
+    public_key_offset,
+    u16::MAX,
+    message_offset,
+    message.len() as u16,
+    u16::MAX,
+  ] {
+    data.extend_from_slice(&field.to_le_bytes());
+  }
+  data.extend_from_slice(public_key.as_ref());
+  data.extend_from_slice(&[0; 64]);
+  data.extend_from_slice(message);
+  data
+}
+
+fn signed_order(fixture: &Fixture, maker: Pubkey, nonce: u64) -> SignedOrder {
+  SignedOrder {
+    program_id: fixture.program_id,
+    maker,
+    offered_mint: fixture.offered_mint,
+    offered_amount: 100,
+    requested_mint: fixture.requested_mint,
+    requested_amount: 10,
+    expires_at: 100,
+    nonce,
+  }
+}
+
+fn order_nonce_account(fixture: &Fixture, maker: Pubkey) -> TestAccount {
+  let (nonce_key, _) = find_order_nonce_address(&fixture.program_id, &maker);
+  let mut data = OrderNonce {
+    key: Key::OrderNonceV1,
+    maker,
+    nonce: 0,
+    used: 0,
+  }
+  .try_to_vec()
+  .unwrap();
+  data.resize(ORDER_NONCE_LEN, 0);
+  TestAccount::new(nonce_key, fixture.program_id, data)
+}
+
+/// Accounts filling `order`, whose signature is verified right before
+fn fill_signed_order_accounts(fixture: &Fixture, order: &SignedOrder) -> Vec<TestAccount> {
+  let taker = Pubkey::new_unique();
+  let (order_delegate, _) = find_order_delegate(&fixture.program_id);
+  let verify_instruction = (
+    ed25519_program::id(),
+    ed25519_instruction_data(&order.maker, &order.try_to_vec().unwrap()),
+  );
+  let fill_instruction = (fixture.program_id, vec![]);
+  vec![
+    TestAccount::new(taker, system_program::id(), vec![]).signer(),
+    token_account(Pubkey::new_unique(), order.requested_mint, taker),
+    token_account(Pubkey::new_unique(), order.offered_mint, taker),
+    token_account(Pubkey::new_unique(), order.offered_mint, order.maker),
+    token_account(Pubkey::new_unique(), order.requested_mint, order.maker),
+    order_nonce_account(fixture, order.maker),
+    TestAccount::new(order_delegate, system_program::id(), vec![]),
+    token_program_account(),
+    clock_account(),
+    instructions_sysvar_account(&[verify_instruction, fill_instruction], 1),
+    any_account(),
+    any_account(),
+    fixture.config_account(),
+    any_account(),
+    any_account(),
+  ]
+}
+
+fn fill_signed_order_instruction(order: SignedOrder) -> EscrowInstruction {
+  EscrowInstruction::FillSignedOrder(FillSignedOrderArgs { order })
+}
+
+#[test]
+fn fill_signed_order_cannot_be_replayed() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 7);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order.clone()),
+  );
+  assert_eq!(result, Ok(()));
+
+  let order_nonce = OrderNonce::deserialize(&mut &accounts[5].data[..]).unwrap();
+  assert!(order_nonce.is_used(7));
+  assert!(!order_nonce.is_used(6));
+
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::OrderNonceUsed);
+}
+
+#[test]
+fn fill_signed_order_requires_ed25519_instruction() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  let verify_instruction = (
+    Pubkey::new_unique(),
+    ed25519_instruction_data(&order.maker, &order.try_to_vec().unwrap()),
+  );
+  accounts[9] = instructions_sysvar_account(&[verify_instruction, (fixture.program_id, vec![])], 1);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidOrderSignature);
+}
+
+#[test]
+fn fill_signed_order_requires_preceding_instruction() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[9] = instructions_sysvar_account(&[(fixture.program_id, vec![])], 0);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidOrderSignature);
+}
+
+#[test]
+fn fill_signed_order_rejects_signature_of_another_order() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  let tampered_order = SignedOrder {
+    requested_amount: 1,
+    ..order.clone()
+  };
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(tampered_order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidOrderSignature);
+}
+
+#[test]
+fn fill_signed_order_rejects_order_for_another_program() {
+  let fixture = Fixture::new();
+  let order = SignedOrder {
+    program_id: Pubkey::new_unique(),
+    ..signed_order(&fixture, Pubkey::new_unique(), 0)
+  };
+  // Signed by the maker, but for another deployment
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::OrderForAnotherProgram);
+}
+
+#[test]
+fn fill_signed_order_rejects_signature_of_another_maker() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  let verify_instruction = (
+    ed25519_program::id(),
+    ed25519_instruction_data(&Pubkey::new_unique(), &order.try_to_vec().unwrap()),
+  );
+  accounts[9] = instructions_sysvar_account(&[verify_instruction, (fixture.program_id, vec![])], 1);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidOrderSignature);
+}
+
+fn update_accounts(fixture: &Fixture, escrow: TestAccount) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    token_account(
+      Pubkey::new_unique(),
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    fixture.temp_token_account(),
+    escrow,
+    fixture.receive_token_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    any_account(),
+  ]
+}
+
+fn update_instruction(expected_amount: Option<u64>, withdraw_amount: u64) -> EscrowInstruction {
+  EscrowInstruction::UpdateEscrow(UpdateEscrowArgs {
+    expected_amount,
+    deposit_amount: 0,
+    withdraw_amount,
+  })
+}
+
+#[test]
+fn update_keeps_price_ratio() {
+  let fixture = Fixture::new();
+  let mut escrow = fixture.escrow();
+  escrow.offered_amount = 3;
+  escrow.expected_amount = 10;
+  escrow.dutch_pricing = Some(DutchPricing {
+    start_price: 20,
+    end_price: 10,
+    start_time: 0,
+    end_time: 100,
+  });
+  let mut accounts = update_accounts(&fixture, fixture.pack_escrow(escrow));
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 1),
+  );
+  assert_eq!(result, Ok(()));
+
+  let escrow = Escrow::unpack(&accounts[3].data).unwrap();
+  assert_eq!(escrow.offered_amount, 3);
+  assert_eq!(escrow.expected_amount, 10);
+  assert_eq!(
+    escrow.dutch_pricing,
+    Some(DutchPricing {
+      start_price: 20,
+      end_price: 10,
+      start_time: 0,
+      end_time: 100,
+    })
+  );
+  // 99 left at 10 for 3 is exactly 330
+  assert_eq!(escrow.payment_at(99, 100).unwrap(), 330);
+}
+
+#[test]
+fn update_reprices_what_is_left() {
+  let fixture = Fixture::new();
+  let mut escrow = fixture.escrow();
+  escrow.dutch_pricing = Some(DutchPricing {
+    start_price: 20,
+    end_price: 10,
+    start_time: 0,
+    end_time: 100,
+  });
+  let mut accounts = update_accounts(&fixture, fixture.pack_escrow(escrow));
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(Some(45), 10),
+  );
+  assert_eq!(result, Ok(()));
+
+  let escrow = Escrow::unpack(&accounts[3].data).unwrap();
+  assert_eq!(escrow.offered_amount, 90);
+  assert_eq!(escrow.expected_amount, 45);
+  assert_eq!(escrow.dutch_pricing, None);
+  assert_eq!(escrow.payment_for(30).unwrap(), 15);
+}
+
+#[test]
+fn update_rejects_emptying_the_escrow() {
+  let fixture = Fixture::new();
+  let mut accounts = update_accounts(&fixture, fixture.escrow_account());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 100),
+  );
+  assert_escrow_error(result, EscrowError::InvalidUpdateAmount);
+}
+
+impl Fixture {
+  /// Escrow account at the address init derives for the fixture's trade
+  fn init_escrow_account(&self) -> TestAccount {
+    let (escrow, _) = find_escrow_address(
+      &self.program_id,
+      &self.initializer,
+      &self.offered_mint,
+      &self.requested_mint,
+      0,
+    );
+    TestAccount::new(escrow, system_program::id(), vec![])
+  }
+}
+
+fn token_init_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  let mut accounts = init_accounts(fixture);
+  accounts[1] = fixture.temp_token_account();
+  accounts[3] = fixture.init_escrow_account();
+  accounts
+}
+
+#[test]
+fn init_requires_initializer_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn init_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[5] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn init_rejects_wrong_config() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[8] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::InvalidConfigKey);
+}
+
+#[test]
+fn init_rejects_while_paused() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[8] = fixture.paused_config_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::ProgramPaused);
+}
+
+#[test]
+fn init_rejects_invalid_dutch_pricing() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::InitEscrow(InitEscrowArgs {
+      data: EscrowReceive { amount: 10 },
+      seed: 0,
+      expires_at: None,
+      allowed_taker: None,
+      offered_kind: SideKind::Token,
+      requested_kind: SideKind::Token,
+      offered_amount: 0,
+      dutch_pricing: Some(DutchPricing {
+        start_price: 10,
+        end_price: 20,
+        start_time: 0,
+        end_time: 100,
+      }),
+    }),
+  );
+  assert_escrow_error(result, EscrowError::InvalidDutchPricing);
+}
+
+#[test]
+fn init_rejects_temp_account_of_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[1].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn init_rejects_receive_account_of_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[2].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn init_rejects_requested_delegate() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::InitEscrow(InitEscrowArgs {
+      data: EscrowReceive { amount: 10 },
+      seed: 0,
+      expires_at: None,
+      allowed_taker: None,
+      offered_kind: SideKind::Token,
+      requested_kind: SideKind::Delegate,
+      offered_amount: 0,
+      dutch_pricing: None,
+    }),
+  );
+  assert_escrow_error(result, EscrowError::UnsupportedSideKind);
+}
+
+#[test]
+fn init_rejects_wrong_escrow_address() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[3] = fixture.uninitialized_escrow_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_escrow_error(result, EscrowError::InvalidEscrowKey);
+}
+
+#[test]
+fn init_rejects_existing_escrow() {
+  let fixture = Fixture::new();
+  let mut accounts = token_init_accounts(&fixture);
+  accounts[3].data = vec![0; Escrow::LEN];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_instruction(SideKind::Token, 0),
+  );
+  assert_eq!(result, Err(ProgramError::AccountAlreadyInitialized));
+}
+
+fn init_config_accounts(fixture: &Fixture, admin: Pubkey) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(admin, system_program::id(), vec![]).signer(),
+    fixture.config_account(),
+    rent_account(),
+    any_account(),
+  ]
+}
+
+fn init_config_instruction(fee_basis_points: u16) -> EscrowInstruction {
+  EscrowInstruction::InitConfig(InitConfigArgs { fee_basis_points })
+}
+
+#[test]
+fn init_config_requires_admin_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = init_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_config_instruction(100),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn init_config_rejects_basis_points_above_10000() {
+  let fixture = Fixture::new();
+  let mut accounts = init_config_accounts(&fixture, Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_config_instruction(10001),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBasisPoints);
+}
+
+#[test]
+fn init_config_rejects_wrong_config_address() {
+  let fixture = Fixture::new();
+  let mut accounts = init_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[1] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_config_instruction(100),
+  );
+  assert_escrow_error(result, EscrowError::InvalidConfigKey);
+}
+
+#[test]
+fn init_config_rejects_existing_config() {
+  let fixture = Fixture::new();
+  let admin = Pubkey::new_unique();
+  let mut accounts = init_config_accounts(&fixture, admin);
+  accounts[1] = fixture.initialized_config_account(admin);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_config_instruction(100),
+  );
+  assert_eq!(result, Err(ProgramError::AccountAlreadyInitialized));
+}
+
+fn update_config_accounts(fixture: &Fixture, admin: Pubkey) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(admin, system_program::id(), vec![]).signer(),
+    fixture.initialized_config_account(admin),
+  ]
+}
+
+#[test]
+fn update_config_rejects_wrong_config_address() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[1] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(no_config_update()),
+  );
+  assert_escrow_error(result, EscrowError::InvalidConfigKey);
+}
+
+#[test]
+fn update_config_rejects_uninitialized_config() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[1] = fixture.config_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(no_config_update()),
+  );
+  assert_eq!(result, Err(ProgramError::UninitializedAccount));
+}
+
+#[test]
+fn update_config_rejects_config_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[1].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(no_config_update()),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn update_config_requires_admin_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(no_config_update()),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn update_config_rejects_another_admin() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(no_config_update()),
+  );
+  assert_escrow_error(result, EscrowError::AdminMismatch);
+}
+
+#[test]
+fn update_config_rejects_basis_points_above_10000() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(UpdateConfigArgs {
+      fee_basis_points: Some(10001),
+      ..no_config_update()
+    }),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBasisPoints);
+}
+
+#[test]
+fn update_config_rejects_too_many_fee_recipients() {
+  let fixture = Fixture::new();
+  let mut accounts = update_config_accounts(&fixture, Pubkey::new_unique());
+  let fee_recipients = (0..=MAX_FEE_RECIPIENTS)
+    .map(|_| FeeRecipient {
+      kind: SideKind::Token,
+      mint: Pubkey::new_unique(),
+      account: Pubkey::new_unique(),
+    })
+    .collect();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_config_instruction(UpdateConfigArgs {
+      fee_recipients: Some(fee_recipients),
+      ..no_config_update()
+    }),
+  );
+  assert_escrow_error(result, EscrowError::TooManyFeeRecipients);
+}
+
+impl Fixture {
+  fn basket_address(&self) -> Pubkey {
+    find_basket_escrow_address(&self.program_id, &self.initializer, 0).0
+  }
+
+  /// Basket of the temp token account against 10 of the requested mint, at the fixture's escrow address
+  fn basket_account(&self) -> TestAccount {
+    let basket = BasketEscrow {
+      key: Key::BasketEscrowV1,
+      initializer: self.initializer,
+      seed: 0,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      offered: vec![BasketLeg {
+        mint: self.offered_mint,
+        amount: 100,
+        account: self.temp_token_account,
+      }],
+      requested: vec![BasketLeg {
+        mint: self.requested_mint,
+        amount: 10,
+        account: self.receive_token_account,
+      }],
+    };
+    TestAccount::new(self.escrow, self.program_id, basket.try_to_vec().unwrap())
+  }
+}
+
+fn init_basket_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    TestAccount::new(fixture.basket_address(), system_program::id(), vec![]),
+    rent_account(),
+    token_program_account(),
+    any_account(),
+    fixture.config_account(),
+    fixture.receive_token_account(),
+    fixture.temp_token_account(),
+  ]
+}
+
+fn init_basket_instruction(requested: Vec<RequestedLeg>) -> EscrowInstruction {
+  EscrowInstruction::InitBasketEscrow(InitBasketEscrowArgs { seed: 0, requested })
+}
+
+fn requested_leg(fixture: &Fixture) -> RequestedLeg {
+  RequestedLeg {
+    mint: fixture.requested_mint,
+    amount: 10,
+  }
+}
+
+#[test]
+fn init_basket_accepts_valid_legs() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  // The basket account is never allocated without the system program
+  assert!(result.is_err());
+  assert_ne!(
+    result,
+    Err(ProgramError::Custom(EscrowError::InvalidBasketLegs as u32))
+  );
+}
+
+#[test]
+fn init_basket_requires_initializer_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn init_basket_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[3] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn init_basket_rejects_wrong_config() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[5] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidConfigKey);
+}
+
+#[test]
+fn init_basket_rejects_while_paused() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[5] = fixture.paused_config_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::ProgramPaused);
+}
+
+#[test]
+fn init_basket_requires_requested_legs() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBasketLegs);
+}
+
+#[test]
+fn init_basket_requires_offered_legs() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts.pop();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBasketLegs);
+}
+
+#[test]
+fn init_basket_rejects_wrong_basket_address() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[1] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidEscrowKey);
+}
+
+#[test]
+fn init_basket_rejects_existing_basket() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[1].data = vec![0; 8];
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_eq!(result, Err(ProgramError::AccountAlreadyInitialized));
+}
+
+#[test]
+fn init_basket_rejects_empty_requested_leg() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![RequestedLeg {
+      amount: 0,
+      ..requested_leg(&fixture)
+    }]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBasketLegs);
+}
+
+#[test]
+fn init_basket_pays_requested_lamports_to_initializer() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![RequestedLeg {
+      mint: spl_token::native_mint::id(),
+      amount: 10,
+    }]),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn init_basket_rejects_receive_account_of_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  accounts[6].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn init_basket_rejects_receive_account_of_another_mint() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![RequestedLeg {
+      mint: Pubkey::new_unique(),
+      amount: 10,
+    }]),
+  );
+  assert_escrow_error(result, EscrowError::RequestedMintMismatch);
+}
+
+#[test]
+fn init_basket_rejects_empty_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = init_basket_accounts(&fixture);
+  let mut vault_info = TokenAccount::unpack(&accounts[7].data).unwrap();
+  vault_info.amount = 0;
+  TokenAccount::pack(vault_info, &mut accounts[7].data).unwrap();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    init_basket_instruction(vec![requested_leg(&fixture)]),
+  );
+  assert_escrow_error(result, EscrowError::InvalidBasketLegs);
+}
+
+fn exchange_basket_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  let taker = Pubkey::new_unique();
+  vec![
+    TestAccount::new(taker, system_program::id(), vec![]).signer(),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    fixture.basket_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    any_account(),
+    fixture.config_account(),
+    token_account(Pubkey::new_unique(), fixture.requested_mint, taker),
+    fixture.receive_token_account(),
+    any_account(),
+    fixture.temp_token_account(),
+    token_account(Pubkey::new_unique(), fixture.offered_mint, taker),
+    any_account(),
+  ]
+}
+
+#[test]
+fn exchange_basket_accepts_valid_legs() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn exchange_basket_requires_taker_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn exchange_basket_rejects_basket_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[2].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn exchange_basket_rejects_other_account_types() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[2] = fixture.initialized_config_account(fixture.initializer);
+  accounts[2].key = fixture.escrow;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::DataTypeMismatch);
+}
+
+#[test]
+fn exchange_basket_rejects_another_initializer() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[1] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn exchange_basket_rejects_wrong_config() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[6] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidConfigKey);
+}
+
+#[test]
+fn exchange_basket_rejects_while_paused() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[6] = fixture.paused_config_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::ProgramPaused);
+}
+
+#[test]
+fn exchange_basket_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[4] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn exchange_basket_pays_initializer_receive_account() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[8] = token_account(
+    Pubkey::new_unique(),
+    fixture.requested_mint,
+    fixture.initializer,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn exchange_basket_rejects_payment_of_another_mint() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[7] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::RequestedMintMismatch);
+}
+
+#[test]
+fn exchange_basket_rejects_wrong_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[10] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    fixture.vault_authority,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn exchange_basket_rejects_receive_account_of_another_mint() {
+  let fixture = Fixture::new();
+  let mut accounts = exchange_basket_accounts(&fixture);
+  accounts[11] = token_account(
+    Pubkey::new_unique(),
+    fixture.requested_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExchangeBasket(),
+  );
+  assert_escrow_error(result, EscrowError::OfferedMintMismatch);
+}
+
+fn cancel_basket_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  vec![
+    fixture.initializer_account(),
+    fixture.basket_account(),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    fixture.temp_token_account(),
+  ]
+}
+
+#[test]
+fn cancel_basket_requires_initializer_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_basket_accounts(&fixture);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelBasketEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn cancel_basket_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_basket_accounts(&fixture);
+  accounts[2] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelBasketEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn cancel_basket_rejects_basket_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_basket_accounts(&fixture);
+  accounts[1].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelBasketEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn cancel_basket_rejects_another_initializer() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_basket_accounts(&fixture);
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelBasketEscrow(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn cancel_basket_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_basket_accounts(&fixture);
+  accounts[3] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelBasketEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn cancel_basket_rejects_wrong_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_basket_accounts(&fixture);
+  accounts[4] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelBasketEscrow(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn fill_signed_order_requires_taker_signature() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn fill_signed_order_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[7] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn fill_signed_order_rejects_wrong_config() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[12] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidConfigKey);
+}
+
+#[test]
+fn fill_signed_order_rejects_while_paused() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[12] = fixture.paused_config_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::ProgramPaused);
+}
+
+#[test]
+fn fill_signed_order_rejects_expired_order() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[8] = clock_account_at(order.expires_at);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::EscrowExpired);
+}
+
+#[test]
+fn fill_signed_order_rejects_wrong_nonce_account() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[5] = order_nonce_account(&fixture, Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidNonceKey);
+}
+
+#[test]
+fn fill_signed_order_rejects_nonce_account_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[5].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn fill_signed_order_rejects_nonce_outside_window() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 1_000);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::OrderNonceOutsideWindow);
+}
+
+#[test]
+fn fill_signed_order_rejects_accounts_of_another_maker() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[3] = token_account(
+    Pubkey::new_unique(),
+    order.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn fill_signed_order_rejects_offered_account_of_another_mint() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[2] = token_account(
+    Pubkey::new_unique(),
+    order.requested_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::OfferedMintMismatch);
+}
+
+#[test]
+fn fill_signed_order_rejects_requested_account_of_another_mint() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[1] = token_account(
+    Pubkey::new_unique(),
+    order.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::RequestedMintMismatch);
+}
+
+#[test]
+fn fill_signed_order_rejects_wrong_order_delegate() {
+  let fixture = Fixture::new();
+  let order = signed_order(&fixture, Pubkey::new_unique(), 0);
+  let mut accounts = fill_signed_order_accounts(&fixture, &order);
+  accounts[6] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    fill_signed_order_instruction(order),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+fn set_order_nonce_accounts(fixture: &Fixture, maker: Pubkey) -> Vec<TestAccount> {
+  vec![
+    TestAccount::new(maker, system_program::id(), vec![]).signer(),
+    order_nonce_account(fixture, maker),
+    rent_account(),
+    any_account(),
+  ]
+}
+
+fn set_order_nonce_instruction(nonce: u64) -> EscrowInstruction {
+  EscrowInstruction::SetOrderNonce(SetOrderNonceArgs { nonce })
+}
+
+#[test]
+fn set_order_nonce_cancels_lower_nonces() {
+  let fixture = Fixture::new();
+  let mut accounts = set_order_nonce_accounts(&fixture, Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    set_order_nonce_instruction(5),
+  );
+  assert_eq!(result, Ok(()));
+  let order_nonce = OrderNonce::deserialize(&mut &accounts[1].data[..]).unwrap();
+  assert_eq!(order_nonce.nonce, 5);
+  assert!(order_nonce.is_used(4));
+}
+
+#[test]
+fn set_order_nonce_requires_maker_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = set_order_nonce_accounts(&fixture, Pubkey::new_unique());
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    set_order_nonce_instruction(5),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn set_order_nonce_rejects_nonce_account_of_another_maker() {
+  let fixture = Fixture::new();
+  let mut accounts = set_order_nonce_accounts(&fixture, Pubkey::new_unique());
+  accounts[1] = order_nonce_account(&fixture, Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    set_order_nonce_instruction(5),
+  );
+  assert_escrow_error(result, EscrowError::InvalidNonceKey);
+}
+
+#[test]
+fn set_order_nonce_rejects_nonce_account_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = set_order_nonce_accounts(&fixture, Pubkey::new_unique());
+  accounts[1].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    set_order_nonce_instruction(5),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn set_order_nonce_only_raises_the_nonce() {
+  let fixture = Fixture::new();
+  let mut accounts = set_order_nonce_accounts(&fixture, Pubkey::new_unique());
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    set_order_nonce_instruction(0),
+  );
+  assert_escrow_error(result, EscrowError::OrderNonceUsed);
+}
+
+fn expire_accounts(fixture: &Fixture) -> Vec<TestAccount> {
+  let mut escrow = fixture.escrow();
+  escrow.expires_at = Some(0);
+  vec![
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    token_account(
+      Pubkey::new_unique(),
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    fixture.temp_token_account(),
+    fixture.pack_escrow(escrow),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account(),
+    any_account(),
+  ]
+}
+
+#[test]
+fn expire_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = expire_accounts(&fixture);
+  accounts[4] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn expire_rejects_escrow_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = expire_accounts(&fixture);
+  accounts[3].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn expire_rejects_open_escrow() {
+  let fixture = Fixture::new();
+  let mut accounts = expire_accounts(&fixture);
+  accounts[3] = fixture.escrow_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::EscrowNotExpired);
+}
+
+#[test]
+fn expire_refunds_only_the_initializer() {
+  let fixture = Fixture::new();
+  let mut accounts = expire_accounts(&fixture);
+  accounts[1] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn expire_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = expire_accounts(&fixture);
+  accounts[5] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::ExpireEscrow(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn update_rejects_wrong_token_program() {
+  let fixture = Fixture::new();
+  let mut accounts = update_accounts(&fixture, fixture.escrow_account());
+  accounts[5] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 1),
+  );
+  assert_escrow_error(result, EscrowError::InvalidTokenProgram);
+}
+
+#[test]
+fn update_rejects_escrow_owned_by_another_program() {
+  let fixture = Fixture::new();
+  let mut accounts = update_accounts(&fixture, fixture.escrow_account());
+  accounts[3].owner = Pubkey::new_unique();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 1),
+  );
+  assert_escrow_error(result, EscrowError::IncorrectOwner);
+}
+
+#[test]
+fn update_rejects_another_initializer() {
+  let fixture = Fixture::new();
+  let mut accounts = update_accounts(&fixture, fixture.escrow_account());
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 1),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn update_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = update_accounts(&fixture, fixture.escrow_account());
+  accounts[6] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 1),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+#[test]
+fn update_rejects_wrong_vault() {
+  let fixture = Fixture::new();
+  let mut accounts = update_accounts(&fixture, fixture.escrow_account());
+  accounts[2] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    fixture.vault_authority,
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    update_instruction(None, 1),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+impl Fixture {
+  fn order_book_page_account(
+    &self,
+    page_index: u32,
+    escrows: Vec<Pubkey>,
+    next_page: Option<Pubkey>,
+    payer: Pubkey,
+  ) -> TestAccount {
+    let (key, _) = find_order_book_page_address(
+      &self.program_id,
+      &self.offered_mint,
+      &self.requested_mint,
+      page_index,
+    );
+    let page = OrderBookPage {
+      key: Key::OrderBookPageV1,
+      offered_mint: self.offered_mint,
+      requested_mint: self.requested_mint,
+      page_index,
+      payer,
+      next_page,
+      escrows,
+    };
+    let mut data = page.try_to_vec().unwrap();
+    data.resize(ORDER_BOOK_PAGE_LEN, 0);
+    TestAccount::new(key, self.program_id, data)
+  }
+}
+
+#[test]
+fn cancel_requires_order_book_page() {
+  let fixture = Fixture::new();
+  let mut accounts = cancel_accounts(&fixture);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  );
+  assert_eq!(result, Err(EscrowError::IncorrectOwner.into()));
+}
+
+#[test]
+fn cancel_removes_escrow_from_its_page() {
+  let fixture = Fixture::new();
+  let other_escrow = Pubkey::new_unique();
+  let mut accounts = cancel_accounts(&fixture);
+  accounts[5] = fixture.order_book_page_account(
+    0,
+    vec![fixture.escrow, other_escrow],
+    None,
+    fixture.initializer,
+  );
+  process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CancelEscrow(),
+  )
+  .unwrap();
+  let page = OrderBookPage::deserialize(&mut &accounts[5].data[..]).unwrap();
+  assert_eq!(page.escrows, vec![other_escrow]);
+}
+
+fn close_page_accounts(fixture: &Fixture, page: TestAccount) -> Vec<TestAccount> {
+  vec![
+    page,
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    any_account(),
+  ]
+}
+
+#[test]
+fn close_page_returns_rent_to_payer() {
+  let fixture = Fixture::new();
+  let page = fixture.order_book_page_account(0, vec![], None, fixture.initializer);
+  let mut accounts = close_page_accounts(&fixture, page);
+  process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  )
+  .unwrap();
+  assert_eq!(accounts[0].lamports, 0);
+  assert_eq!(accounts[1].lamports, 2_000_000);
+}
+
+#[test]
+fn close_page_unlinks_it_from_previous_page() {
+  let fixture = Fixture::new();
+  let page = fixture.order_book_page_account(1, vec![], None, fixture.initializer);
+  let previous_page = fixture.order_book_page_account(
+    0,
+    vec![Pubkey::new_unique()],
+    Some(page.key),
+    Pubkey::new_unique(),
+  );
+  let mut accounts = close_page_accounts(&fixture, page);
+  accounts[2] = previous_page;
+  process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  )
+  .unwrap();
+  assert_eq!(accounts[0].lamports, 0);
+  let previous_page = OrderBookPage::deserialize(&mut &accounts[2].data[..]).unwrap();
+  assert_eq!(previous_page.next_page, None);
+}
+
+#[test]
+fn close_page_rejects_page_listing_escrows() {
+  let fixture = Fixture::new();
+  let page = fixture.order_book_page_account(0, vec![fixture.escrow], None, fixture.initializer);
+  let mut accounts = close_page_accounts(&fixture, page);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  );
+  assert_escrow_error(result, EscrowError::OrderBookPageInUse);
+}
+
+#[test]
+fn close_page_rejects_page_with_next_page() {
+  let fixture = Fixture::new();
+  let page =
+    fixture.order_book_page_account(0, vec![], Some(Pubkey::new_unique()), fixture.initializer);
+  let mut accounts = close_page_accounts(&fixture, page);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  );
+  assert_escrow_error(result, EscrowError::OrderBookPageInUse);
+}
+
+#[test]
+fn close_page_rejects_another_payer() {
+  let fixture = Fixture::new();
+  let page = fixture.order_book_page_account(0, vec![], None, Pubkey::new_unique());
+  let mut accounts = close_page_accounts(&fixture, page);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn close_page_rejects_previous_page_linking_elsewhere() {
+  let fixture = Fixture::new();
+  let page = fixture.order_book_page_account(1, vec![], None, fixture.initializer);
+  let previous_page =
+    fixture.order_book_page_account(0, vec![], Some(Pubkey::new_unique()), fixture.initializer);
+  let mut accounts = close_page_accounts(&fixture, page);
+  accounts[2] = previous_page;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidOrderBookPage);
+}
+
+#[test]
+fn close_page_rejects_page_at_another_address() {
+  let fixture = Fixture::new();
+  let mut page = fixture.order_book_page_account(0, vec![], None, fixture.initializer);
+  page.key = Pubkey::new_unique();
+  let mut accounts = close_page_accounts(&fixture, page);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::CloseOrderBookPage(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidOrderBookPage);
+}
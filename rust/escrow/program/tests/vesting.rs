@@ -0,0 +1,282 @@
+use {
+  borsh::BorshSerialize,
+  escrow::{
+    error::EscrowError,
+    instruction::{EscrowInstruction, InitVestingEscrowArgs},
+    pda::find_vesting_escrow_address,
+    state::{Key, VestingEscrow, VestingSchedule, VestingTranche},
+  },
+  solana_program::{
+    clock::UnixTimestamp, program_error::ProgramError, pubkey::Pubkey, system_program,
+  },
+};
+
+mod common;
+
+use common::{
+  any_account, assert_escrow_error, clock_account_at, process, rent_account, token_account,
+  token_program_account, Fixture, TestAccount,
+};
+
+fn tranches() -> VestingSchedule {
+  VestingSchedule::Tranches(vec![
+    VestingTranche {
+      timestamp: 100,
+      amount: 40,
+    },
+    VestingTranche {
+      timestamp: 200,
+      amount: 60,
+    },
+  ])
+}
+
+impl Fixture {
+  /// Grant of the temp token account's 100 tokens from the fixture's initializer as funder
+  fn vesting_escrow(&self, schedule: VestingSchedule) -> VestingEscrow {
+    VestingEscrow {
+      key: Key::VestingEscrowV1,
+      funder: self.initializer,
+      beneficiary: self.receive_token_account,
+      revocation_authority: None,
+      mint: self.offered_mint,
+      vault: self.temp_token_account,
+      seed: 0,
+      vault_authority_bump_seed: self.vault_authority_bump_seed,
+      total_amount: 100,
+      claimed_amount: 0,
+      schedule,
+    }
+  }
+
+  fn pack_vesting_escrow(&self, escrow_info: VestingEscrow) -> TestAccount {
+    TestAccount::new(
+      self.escrow,
+      self.program_id,
+      escrow_info.try_to_vec().unwrap(),
+    )
+  }
+
+  fn beneficiary_token_account(&self) -> TestAccount {
+    token_account(
+      Pubkey::new_unique(),
+      self.offered_mint,
+      self.receive_token_account,
+    )
+  }
+}
+
+fn claim_accounts(
+  fixture: &Fixture,
+  escrow_info: VestingEscrow,
+  now: UnixTimestamp,
+) -> Vec<TestAccount> {
+  vec![
+    fixture.beneficiary_token_account(),
+    fixture.temp_token_account(),
+    fixture.pack_vesting_escrow(escrow_info),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account_at(now),
+  ]
+}
+
+#[test]
+fn init_vesting_rejects_tranches_not_adding_up_to_vault() {
+  let fixture = Fixture::new();
+  let (escrow, _) = find_vesting_escrow_address(&fixture.program_id, &fixture.initializer, 0);
+  let mut accounts = vec![
+    fixture.initializer_account(),
+    token_account(
+      fixture.temp_token_account,
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    TestAccount::new(escrow, system_program::id(), vec![]),
+    rent_account(),
+    token_program_account(),
+    any_account(),
+    fixture.config_account(),
+  ];
+  let schedules = vec![
+    VestingSchedule::Tranches(vec![]),
+    VestingSchedule::Tranches(vec![VestingTranche {
+      timestamp: 100,
+      amount: 99,
+    }]),
+    VestingSchedule::Linear {
+      start: 0,
+      cliff: 10,
+      duration: 0,
+    },
+    VestingSchedule::Linear {
+      start: 0,
+      cliff: 20,
+      duration: 10,
+    },
+  ];
+  for schedule in schedules {
+    let result = process(
+      &fixture.program_id,
+      &mut accounts,
+      EscrowInstruction::InitVestingEscrow(InitVestingEscrowArgs {
+        seed: 0,
+        beneficiary: Pubkey::new_unique(),
+        revocation_authority: None,
+        schedule,
+      }),
+    );
+    assert_escrow_error(result, EscrowError::InvalidVestingSchedule);
+  }
+}
+
+#[test]
+fn claim_rejects_before_first_tranche() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_accounts(&fixture, fixture.vesting_escrow(tranches()), 99);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Claim(),
+  );
+  assert_escrow_error(result, EscrowError::NothingToClaim);
+}
+
+#[test]
+fn claim_rejects_linear_schedule_before_cliff() {
+  let fixture = Fixture::new();
+  let schedule = VestingSchedule::Linear {
+    start: 1_000,
+    cliff: 25,
+    duration: 100,
+  };
+  let mut accounts = claim_accounts(&fixture, fixture.vesting_escrow(schedule), 1_024);
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Claim(),
+  );
+  assert_escrow_error(result, EscrowError::NothingToClaim);
+}
+
+#[test]
+fn claim_rejects_account_of_another_beneficiary() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_accounts(&fixture, fixture.vesting_escrow(tranches()), 200);
+  accounts[0] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Claim(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn claim_rejects_another_rent_receiver() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_accounts(&fixture, fixture.vesting_escrow(tranches()), 200);
+  accounts[3] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Claim(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn claim_rejects_wrong_vault_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = claim_accounts(&fixture, fixture.vesting_escrow(tranches()), 200);
+  accounts[5] = any_account();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::Claim(),
+  );
+  assert_escrow_error(result, EscrowError::InvalidVaultAuthority);
+}
+
+fn revoke_accounts(fixture: &Fixture, authority: Pubkey, now: UnixTimestamp) -> Vec<TestAccount> {
+  let escrow_info = VestingEscrow {
+    revocation_authority: Some(authority),
+    ..fixture.vesting_escrow(tranches())
+  };
+  vec![
+    TestAccount::new(authority, system_program::id(), vec![]).signer(),
+    fixture.temp_token_account(),
+    fixture.beneficiary_token_account(),
+    token_account(
+      Pubkey::new_unique(),
+      fixture.offered_mint,
+      fixture.initializer,
+    ),
+    TestAccount::new(fixture.initializer, system_program::id(), vec![]),
+    fixture.pack_vesting_escrow(escrow_info),
+    token_program_account(),
+    fixture.vault_authority_account(),
+    clock_account_at(now),
+  ]
+}
+
+#[test]
+fn revoke_requires_revocation_authority() {
+  let fixture = Fixture::new();
+  let mut accounts = revoke_accounts(&fixture, Pubkey::new_unique(), 150);
+  accounts[0] = any_account().signer();
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::RevokeVesting(),
+  );
+  assert_escrow_error(result, EscrowError::RevocationAuthorityMismatch);
+}
+
+#[test]
+fn revoke_requires_authority_signature() {
+  let fixture = Fixture::new();
+  let mut accounts = revoke_accounts(&fixture, Pubkey::new_unique(), 150);
+  accounts[0].is_signer = false;
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::RevokeVesting(),
+  );
+  assert_escrow_error(result, EscrowError::MissingSigner);
+}
+
+#[test]
+fn irrevocable_grant_cannot_be_revoked() {
+  let fixture = Fixture::new();
+  let mut accounts = revoke_accounts(&fixture, Pubkey::new_unique(), 150);
+  accounts[5] = fixture.pack_vesting_escrow(fixture.vesting_escrow(tranches()));
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::RevokeVesting(),
+  );
+  assert_escrow_error(result, EscrowError::RevocationAuthorityMismatch);
+}
+
+#[test]
+fn revoke_rejects_account_of_another_funder() {
+  let fixture = Fixture::new();
+  let mut accounts = revoke_accounts(&fixture, Pubkey::new_unique(), 150);
+  accounts[3] = token_account(
+    Pubkey::new_unique(),
+    fixture.offered_mint,
+    Pubkey::new_unique(),
+  );
+  let result = process(
+    &fixture.program_id,
+    &mut accounts,
+    EscrowInstruction::RevokeVesting(),
+  );
+  assert_eq!(result, Err(ProgramError::InvalidAccountData));
+}